@@ -0,0 +1,49 @@
+pub use source::ApkSource;
+pub use target::ApkTarget;
+
+use crate::util::spawn_install;
+use eyre::{Context, Result};
+use std::path::Path;
+use subprocess::Exec;
+
+pub mod source;
+pub mod target;
+
+/// Install an apk with `apk add`. Pass in the filename of the apk to install.
+///
+/// Packages generated by `xenomorph` are not signed, so `--allow-untrusted`
+/// has to be passed for `apk` to accept them.
+pub fn install(apk: &Path, dry_run: bool) -> Result<()> {
+	let cmd = Exec::cmd("apk")
+		.args(&["add", "--allow-untrusted"])
+		.arg(apk);
+	spawn_install(cmd, dry_run).wrap_err("Unable to install")
+}
+
+/// Control files that live at the root of an apk, alongside the actual package data.
+const PKGINFO: &str = ".PKGINFO";
+
+/// Maps an Alpine architecture name to the format used by Debian.
+fn apk_arch_to_deb(arch: &str) -> &str {
+	match arch {
+		"x86_64" => "amd64",
+		"x86" => "i386",
+		"aarch64" => "arm64",
+		"armv7" => "armhf",
+		"ppc64le" => "ppc64el",
+		"noarch" => "all",
+		_ => arch,
+	}
+}
+
+/// Maps a Debian architecture name to the format used by Alpine.
+fn deb_arch_to_apk(arch: &str) -> &str {
+	match arch {
+		"amd64" => "x86_64",
+		"i386" => "x86",
+		"arm64" => "aarch64",
+		"ppc64el" => "ppc64le",
+		"all" => "noarch",
+		_ => arch,
+	}
+}
@@ -0,0 +1,258 @@
+use std::{
+	collections::HashMap,
+	fmt::Debug,
+	fs::File,
+	io::{Cursor, Read, Seek},
+	path::{Path, PathBuf},
+};
+
+use eyre::{bail, Result};
+use flate2::read::MultiGzDecoder;
+
+use crate::{
+	deb::source::retain_portable_dependencies,
+	util::{check_entry_inside, make_unpack_work_dir, normalize_archive_path, Verbosity},
+	Args, Format, PackageInfo, Script, SourcePackage,
+};
+
+pub struct ApkSource {
+	info: PackageInfo,
+	tar: tar::Archive<Cursor<Vec<u8>>>,
+	verbosity: Verbosity,
+	/// Where to unpack the package, if not the current directory.
+	build_dir: Option<PathBuf>,
+}
+impl ApkSource {
+	/// `apk` files are gzipped tarballs with an `.apk` extension.
+	///
+	/// Android also uses the `.apk` extension for its (zip-based) packages,
+	/// so the gzip magic number is checked as well.
+	#[must_use]
+	pub fn check_file(file: &Path) -> bool {
+		if !file
+			.extension()
+			.is_some_and(|o| o.eq_ignore_ascii_case("apk"))
+		{
+			return false;
+		}
+		let Ok(mut file) = File::open(file) else {
+			return false;
+		};
+		let mut magic = [0u8; 2];
+		file.read_exact(&mut magic).is_ok() && magic == [0x1f, 0x8b]
+	}
+
+	pub fn new(file: PathBuf, args: &Args) -> Result<Self> {
+		// An apk is made up of several concatenated gzip streams
+		// (signature, control and data), which together form a single tarball.
+		let mut data = vec![];
+		MultiGzDecoder::new(File::open(&file)?).read_to_end(&mut data)?;
+		let mut tar = tar::Archive::new(Cursor::new(data));
+
+		let mut info = PackageInfo {
+			file,
+			group: "unknown".into(),
+			copyright: "unknown".into(),
+			original_format: Format::Apk,
+			distribution: "Alpine".into(),
+			..Default::default()
+		};
+		let mut pkginfo = None;
+
+		for entry in tar.entries()? {
+			let mut entry = entry?;
+			let path = entry.path()?.into_owned();
+			let Some(name) = path.to_str() else {
+				info.files.push(normalize_archive_path(&path));
+				continue;
+			};
+
+			if name == super::PKGINFO {
+				let mut content = String::new();
+				entry.read_to_string(&mut content)?;
+				pkginfo = Some(content);
+			} else if let Some(script) = Script::from_apk_script_name(name) {
+				let mut content = String::new();
+				entry.read_to_string(&mut content)?;
+				info.scripts.insert(script, content);
+			} else if name.starts_with('.') && !name.contains('/') {
+				// Signatures and other control files we don't care about.
+			} else {
+				let path = normalize_archive_path(&path);
+				// Assume any regular file in /etc/ is a conffile.
+				if path.starts_with("/etc/") && entry.header().entry_type().is_file() {
+					info.conffiles.push(path.clone());
+				}
+				info.files.push(path);
+			}
+		}
+
+		let Some(pkginfo) = pkginfo else {
+			bail!("Malformed .apk archive - .PKGINFO not found!");
+		};
+		parse_pkginfo(&mut info, &pkginfo)?;
+		info.binary_info = pkginfo;
+		if !args.keep_depends {
+			retain_portable_dependencies(&mut info.dependencies);
+		}
+
+		if let Some(arch) = &args.target {
+			info.arch.clone_from(arch);
+		}
+
+		// Rewind tar for unpacking
+		let mut tar = tar.into_inner();
+		tar.rewind()?;
+		let tar = tar::Archive::new(tar);
+
+		Ok(Self {
+			info,
+			tar,
+			verbosity: args.verbosity,
+			build_dir: args.build_dir.clone(),
+		})
+	}
+}
+impl SourcePackage for ApkSource {
+	fn info(&self) -> &PackageInfo {
+		&self.info
+	}
+	fn info_mut(&mut self) -> &mut PackageInfo {
+		&mut self.info
+	}
+	fn into_info(self) -> PackageInfo {
+		self.info
+	}
+	fn unpack(&mut self) -> Result<PathBuf> {
+		let work_dir = make_unpack_work_dir(&self.info, self.build_dir.as_deref(), self.verbosity)?;
+
+		for entry in self.tar.entries()? {
+			let mut entry = entry?;
+			check_entry_inside(&entry)?;
+			let path = entry.path()?;
+			// Skip the control files at the root of the archive.
+			if path
+				.to_str()
+				.is_some_and(|p| p.starts_with('.') && !p.contains('/'))
+			{
+				continue;
+			}
+			entry.unpack_in(&work_dir)?;
+		}
+
+		Ok(work_dir)
+	}
+}
+impl Debug for ApkSource {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("ApkSource")
+			.field("info", &self.info)
+			.finish()
+	}
+}
+
+fn parse_pkginfo(info: &mut PackageInfo, content: &str) -> Result<()> {
+	// See https://wiki.alpinelinux.org/wiki/Apk_spec#PKGINFO_Format
+	let mut info_map: HashMap<&str, Vec<&str>> = HashMap::new();
+	for line in content.lines() {
+		if line.starts_with('#') {
+			continue;
+		}
+		let Some((key, value)) = line.split_once('=') else {
+			continue;
+		};
+		info_map.entry(key.trim()).or_default().push(value.trim());
+	}
+	let mut field = |key: &str| info_map.remove(key).and_then(|mut v| v.pop());
+
+	let Some(name) = field("pkgname") else {
+		bail!("pkgname field missing in .PKGINFO!");
+	};
+	let Some(pkgver) = field("pkgver") else {
+		bail!("pkgver field missing in .PKGINFO!");
+	};
+
+	info.name = name.to_owned();
+
+	// Alpine versions look like `1.2.3-r4`, where `4` is the package release.
+	let (version, release) = pkgver.rsplit_once("-r").unwrap_or((pkgver, "1"));
+	info.version = version.to_owned();
+	info.release = release.to_owned();
+
+	info.arch = super::apk_arch_to_deb(field("arch").unwrap_or("noarch")).to_owned();
+	if let Some(desc) = field("pkgdesc") {
+		info.summary = desc.to_owned();
+		info.description = desc.to_owned();
+	}
+	if let Some(license) = field("license") {
+		info.copyright = license.to_owned();
+	}
+	if let Some(maintainer) = field("maintainer") {
+		info.maintainer = maintainer.to_owned();
+	}
+	if let Some(url) = field("url") {
+		info.url = url.to_owned();
+	}
+	// The size is given in bytes.
+	info.installed_size = field("size")
+		.and_then(|s| s.parse::<u64>().ok())
+		.map(|s| s.div_ceil(1024));
+
+	// Dependencies starting with `!` are really conflicts.
+	info.dependencies = info_map
+		.remove("depend")
+		.unwrap_or_default()
+		.into_iter()
+		.filter(|d| !d.starts_with('!'))
+		.map(|d| d.to_owned())
+		.collect();
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::deb::source::retain_portable_dependencies;
+
+	#[test]
+	fn test_parse_pkginfo() -> eyre::Result<()> {
+		let mut info = crate::PackageInfo::default();
+
+		super::parse_pkginfo(
+			&mut info,
+			r"# Generated by abuild 3.12.0-r0
+# using fakeroot version 1.32.1
+pkgname = xenomorph
+pkgver = 0.1.0-r2
+pkgdesc = Shapeshift between package formats
+url = https://github.com/pluiedev/xenomorph
+builddate = 1700000000
+size = 4096
+arch = x86_64
+license = GPL-2.0-or-later
+depend = so:libc.musl-x86_64.so.1
+depend = musl>=1.2
+depend = lsb>=3.0
+depend = !alien
+",
+		)?;
+
+		assert_eq!(info.name, "xenomorph");
+		assert_eq!(info.installed_size, Some(4));
+		assert_eq!(info.url, "https://github.com/pluiedev/xenomorph");
+		assert_eq!(info.version, "0.1.0");
+		assert_eq!(info.release, "2");
+		assert_eq!(info.arch, "amd64");
+		assert_eq!(info.summary, "Shapeshift between package formats");
+		assert_eq!(info.copyright, "GPL-2.0-or-later");
+		assert_eq!(
+			info.dependencies,
+			vec!["so:libc.musl-x86_64.so.1", "musl>=1.2", "lsb>=3.0"]
+		);
+		// Without `--keep-depends`, only the portable ones are kept.
+		retain_portable_dependencies(&mut info.dependencies);
+		assert_eq!(info.dependencies, vec!["lsb>=3.0"]);
+
+		Ok(())
+	}
+}
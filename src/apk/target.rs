@@ -0,0 +1,335 @@
+use std::{
+	collections::HashMap,
+	fs::{DirEntry, File},
+	io::Write,
+	path::{Path, PathBuf},
+};
+
+use eyre::Result;
+use flate2::{write::GzEncoder, Compression};
+
+use crate::{
+	deb::split_relation, tgz::target::append_entry, util::chmod, Args, Format, PackageInfo, Script,
+	TargetPackage,
+};
+
+#[derive(Debug)]
+pub struct ApkTarget {
+	info: PackageInfo,
+	unpacked_dir: PathBuf,
+	clamp_mtime: Option<u64>,
+}
+impl ApkTarget {
+	pub fn new(mut info: PackageInfo, unpacked_dir: PathBuf, args: &Args) -> Result<Self> {
+		Self::sanitize_info(&mut info);
+		let PackageInfo {
+			name,
+			version,
+			release,
+			arch,
+			summary,
+			maintainer,
+			copyright,
+			dependencies,
+			installed_size,
+			url,
+			..
+		} = &info;
+		let arch = super::deb_arch_to_apk(arch);
+
+		let mut pkginfo = File::create(unpacked_dir.join(super::PKGINFO))?;
+		#[rustfmt::skip]
+		writeln!(
+			pkginfo,
+r"# Generated by xenomorph {xenomorph_version}
+pkgname = {name}
+pkgver = {version}-r{release}
+pkgdesc = {summary}
+arch = {arch}
+license = {copyright}",
+			xenomorph_version = env!("CARGO_PKG_VERSION")
+		)?;
+		if !maintainer.is_empty() {
+			writeln!(pkginfo, "maintainer = {maintainer}")?;
+		}
+		if !url.is_empty() {
+			writeln!(pkginfo, "url = {url}")?;
+		}
+		if let Some(size) = installed_size {
+			writeln!(pkginfo, "size = {}", size * 1024)?;
+		}
+		for dep in dependencies {
+			writeln!(pkginfo, "depend = {dep}")?;
+		}
+
+		if info.use_scripts {
+			for (script, data) in &info.scripts {
+				if data.chars().all(char::is_whitespace) {
+					continue;
+				}
+				let out = unpacked_dir.join(script.apk_script_name());
+				std::fs::write(&out, data)?;
+				chmod(&out, 0o755, args.verbosity)?;
+			}
+		}
+
+		Ok(Self {
+			info,
+			unpacked_dir,
+			clamp_mtime: args.clamp_mtime,
+		})
+	}
+
+	pub(crate) fn sanitize_info(info: &mut PackageInfo) {
+		info.version = apk_version(&info.version);
+
+		// The release goes after `-r`, and has to be a plain number.
+		let release = info
+			.release
+			.trim_start_matches(|c: char| c.is_ascii_digit());
+		let number = &info.release[..info.release.len() - release.len()];
+		info.release = if number.is_empty() { "1" } else { number }.to_owned();
+
+		// Every field in .PKGINFO is a single line.
+		info.summary = info.summary.lines().collect::<Vec<_>>().join(" ");
+
+		if info.original_format == Format::Deb {
+			for dep in &mut info.dependencies {
+				*dep = apk_dependency(dep);
+			}
+		}
+	}
+
+	fn control_files() -> impl Iterator<Item = &'static str> {
+		std::iter::once(super::PKGINFO).chain(Script::ALL.iter().map(|s| s.apk_script_name()))
+	}
+}
+impl TargetPackage for ApkTarget {
+	fn clean_tree(&mut self) -> Result<()> {
+		for file in Self::control_files() {
+			let _ignore = std::fs::remove_file(self.unpacked_dir.join(file));
+		}
+		Ok(())
+	}
+
+	fn build(&mut self, output_dir: &Path) -> Result<PathBuf> {
+		let path = output_dir.join(file_name(&self.info));
+		let mut apk = File::create(&path)?;
+
+		// The control section and the data section are separate gzip streams.
+		// The control tarball must not have an end-of-archive marker,
+		// so that both streams concatenate into a single tarball.
+		let mut control = tar::Builder::new(vec![]);
+		for file in Self::control_files() {
+			let src = self.unpacked_dir.join(file);
+			if src.exists() {
+				control.append_path_with_name(src, file)?;
+			}
+		}
+		let mut control = control.into_inner()?;
+		control.truncate(control.len().saturating_sub(1024));
+
+		let mut gz = GzEncoder::new(&mut apk, Compression::default());
+		gz.write_all(&control)?;
+		gz.finish()?;
+
+		let mut data = tar::Builder::new(GzEncoder::new(&mut apk, Compression::default()));
+		let control_files: Vec<_> = Self::control_files().collect();
+		let mut entries = std::fs::read_dir(&self.unpacked_dir)?.collect::<Result<Vec<_>, _>>()?;
+		entries.sort_by_key(DirEntry::file_name);
+		let mut inodes = HashMap::new();
+		for entry in entries {
+			let file_name = entry.file_name();
+			if file_name
+				.to_str()
+				.is_some_and(|f| control_files.contains(&f))
+			{
+				continue;
+			}
+			// Symlinks have to stay links, rather than being followed.
+			append_entry(
+				&mut data,
+				&entry.path(),
+				Path::new(&file_name),
+				self.clamp_mtime,
+				&mut inodes,
+			)?;
+		}
+		data.into_inner()?.finish()?;
+
+		Ok(path)
+	}
+}
+
+/// Rewrites a version into one apk accepts: numbers separated by dots,
+/// optionally followed by a letter and suffixes like `_rc1`.
+///
+/// Pre-release and VCS parts of the version, like `~beta2` and `+git20240101`,
+/// become the matching suffix, and plain numbers become `_p` suffixes.
+/// Anything else, like `+dfsg`, is dropped.
+fn apk_version(version: &str) -> String {
+	const SUFFIXES: [&str; 9] = ["alpha", "beta", "pre", "rc", "cvs", "svn", "git", "hg", "p"];
+
+	let rest = version.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+	let numbers: Vec<_> = version[..version.len() - rest.len()]
+		.split('.')
+		.filter(|n| !n.is_empty())
+		.collect();
+	let mut out = if numbers.is_empty() {
+		"0".to_owned()
+	} else {
+		numbers.join(".")
+	};
+
+	// A single letter may follow the numbers, as in `1.0a`.
+	let mut chars = rest.chars();
+	let rest = match (chars.next(), chars.next()) {
+		(Some(c), next) if c.is_ascii_lowercase() && !next.is_some_and(char::is_alphanumeric) => {
+			out.push(c);
+			&rest[1..]
+		}
+		_ => rest,
+	};
+
+	let mut last_has_number = true;
+	for part in rest.split(|c: char| !c.is_ascii_alphanumeric()) {
+		// Like `beta2`, leaving out anything after the number.
+		let after_word = part.trim_start_matches(|c: char| c.is_ascii_alphabetic());
+		let word = &part[..part.len() - after_word.len()];
+		let number = after_word
+			.split(|c: char| !c.is_ascii_digit())
+			.next()
+			.unwrap_or_default();
+
+		if word.is_empty() {
+			if number.is_empty() {
+				continue;
+			}
+			// `~beta-2` is the second beta.
+			if last_has_number {
+				out.push_str("_p");
+			}
+			out.push_str(number);
+			last_has_number = true;
+		} else if let Some(suffix) = SUFFIXES.iter().find(|s| word.eq_ignore_ascii_case(s)) {
+			out.push('_');
+			out.push_str(suffix);
+			out.push_str(number);
+			last_has_number = !number.is_empty();
+		}
+	}
+	out
+}
+
+/// Rewrites a dependency in Debian's syntax, like `lsb (>= 3.0)`, the way
+/// apk spells it, like `lsb>=3.0`.
+///
+/// apk has no alternatives, so only the first one is kept.
+fn apk_dependency(dep: &str) -> String {
+	let relation = dep.split('|').next().unwrap_or(dep);
+	let (name, op, version) = split_relation(relation);
+	format!("{name}{op}{version}")
+}
+
+/// The name of the file the package is built into.
+pub(crate) fn file_name(info: &PackageInfo) -> String {
+	let PackageInfo {
+		name,
+		version,
+		release,
+		..
+	} = info;
+	format!("{name}-{version}-r{release}.apk")
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{io::Read, path::PathBuf};
+
+	use bpaf::Parser;
+	use flate2::read::MultiGzDecoder;
+
+	use super::{apk_version, ApkTarget};
+	use crate::{util::args, Format, PackageInfo, TargetPackage};
+
+	#[test]
+	fn test_sanitize_info() {
+		let mut info = PackageInfo {
+			version: "2024.1~beta-2".into(),
+			release: "3ubuntu1".into(),
+			summary: "Shapeshift\nbetween package formats".into(),
+			dependencies: vec!["lsb (>= 3.0)".into(), "libc6 (>> 2.31) | musl".into()],
+			original_format: Format::Deb,
+			..Default::default()
+		};
+		ApkTarget::sanitize_info(&mut info);
+		assert_eq!(info.version, "2024.1_beta2");
+		assert_eq!(info.release, "3");
+		assert_eq!(info.summary, "Shapeshift between package formats");
+		assert_eq!(info.dependencies, ["lsb>=3.0", "libc6>2.31"]);
+
+		let mut info = PackageInfo {
+			version: "beta".into(),
+			release: "el7".into(),
+			..Default::default()
+		};
+		ApkTarget::sanitize_info(&mut info);
+		assert_eq!(info.version, "0_beta");
+		assert_eq!(info.release, "1");
+	}
+
+	#[test]
+	fn test_apk_version() {
+		for (version, expected) in [
+			("1.2.3", "1.2.3"),
+			("1.0a", "1.0a"),
+			("2.0~rc1", "2.0_rc1"),
+			("1.0+dfsg", "1.0"),
+			("1.0+dfsg1-2", "1.0_p2"),
+			("0.9+git20240101.abcdef", "0.9_git20240101"),
+			("3.1-4", "3.1_p4"),
+			("beta", "0_beta"),
+			("", "0"),
+		] {
+			assert_eq!(apk_version(version), expected, "{version}");
+		}
+	}
+
+	#[test]
+	fn test_build_keeps_symlinks() -> eyre::Result<()> {
+		let unpacked_dir = tempfile::tempdir()?;
+		let output_dir = tempfile::tempdir()?;
+		let bin_dir = unpacked_dir.path().join("usr/bin");
+		std::fs::create_dir_all(&bin_dir)?;
+		std::fs::write(bin_dir.join("xenomorph"), "#!/bin/sh\n")?;
+		std::os::unix::fs::symlink("xenomorph", bin_dir.join("alien"))?;
+
+		let info = PackageInfo {
+			name: "xenomorph".into(),
+			version: "0.1.0".into(),
+			release: "1".into(),
+			arch: "amd64".into(),
+			..Default::default()
+		};
+		let args = args()
+			.to_options()
+			.run_inner(&["--to-apk", "xenomorph.deb"])
+			.unwrap();
+		let mut target = ApkTarget::new(info, unpacked_dir.path().to_path_buf(), &args)?;
+		let apk = target.build(output_dir.path())?;
+
+		// Both gzip streams concatenate into a single tarball.
+		let mut tar = vec![];
+		MultiGzDecoder::new(std::fs::File::open(apk)?).read_to_end(&mut tar)?;
+		let mut archive = tar::Archive::new(&tar[..]);
+		let link = archive
+			.entries()?
+			.map(|e| e.unwrap())
+			.find(|e| e.path().unwrap().ends_with("usr/bin/alien"))
+			.unwrap();
+		assert_eq!(link.header().entry_type(), tar::EntryType::Symlink);
+		assert_eq!(link.link_name()?, Some(PathBuf::from("xenomorph").into()));
+
+		Ok(())
+	}
+}
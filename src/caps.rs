@@ -0,0 +1,266 @@
+//! File capabilities, as stored in the `security.capability` extended
+//! attribute, and their textual form as used by `setcap` and rpm's `%caps`.
+
+use std::{fmt::Write as _, io::Read};
+
+/// The names of the capabilities known to Linux, indexed by number.
+const CAP_NAMES: &[&str] = &[
+	"cap_chown",
+	"cap_dac_override",
+	"cap_dac_read_search",
+	"cap_fowner",
+	"cap_fsetid",
+	"cap_kill",
+	"cap_setgid",
+	"cap_setuid",
+	"cap_setpcap",
+	"cap_linux_immutable",
+	"cap_net_bind_service",
+	"cap_net_broadcast",
+	"cap_net_admin",
+	"cap_net_raw",
+	"cap_ipc_lock",
+	"cap_ipc_owner",
+	"cap_sys_module",
+	"cap_sys_rawio",
+	"cap_sys_chroot",
+	"cap_sys_ptrace",
+	"cap_sys_pacct",
+	"cap_sys_admin",
+	"cap_sys_boot",
+	"cap_sys_nice",
+	"cap_sys_resource",
+	"cap_sys_time",
+	"cap_sys_tty_config",
+	"cap_mknod",
+	"cap_lease",
+	"cap_audit_write",
+	"cap_audit_control",
+	"cap_setfcap",
+	"cap_mac_override",
+	"cap_mac_admin",
+	"cap_syslog",
+	"cap_wake_alarm",
+	"cap_block_suspend",
+	"cap_audit_read",
+	"cap_perfmon",
+	"cap_bpf",
+	"cap_checkpoint_restore",
+];
+
+/// The key tar archives store the attribute under, in PAX extended headers.
+const PAX_KEY: &str = "SCHILY.xattr.security.capability";
+
+const REVISION_MASK: u32 = 0xFF00_0000;
+const REVISION_1: u32 = 0x0100_0000;
+const REVISION_2: u32 = 0x0200_0000;
+const REVISION_3: u32 = 0x0300_0000;
+const FLAG_EFFECTIVE: u32 = 0x0000_0001;
+
+/// Converts the raw value of a `security.capability` attribute to text,
+/// such as `cap_net_raw=ep`.
+///
+/// Returns `None` if the value is malformed or grants nothing.
+#[must_use]
+pub fn to_text(raw: &[u8]) -> Option<String> {
+	let word = |i: usize| {
+		let bytes = raw.get(i * 4..i * 4 + 4)?;
+		Some(u32::from_le_bytes(bytes.try_into().ok()?))
+	};
+	let magic = word(0)?;
+	let (permitted, inheritable) = match magic & REVISION_MASK {
+		REVISION_1 => (u64::from(word(1)?), u64::from(word(2)?)),
+		REVISION_2 | REVISION_3 => (
+			u64::from(word(1)?) | u64::from(word(3)?) << 32,
+			u64::from(word(2)?) | u64::from(word(4)?) << 32,
+		),
+		_ => return None,
+	};
+	let effective = magic & FLAG_EFFECTIVE != 0;
+
+	// Capabilities with the same flags are listed together, as in
+	// `cap_chown,cap_fowner=ep cap_kill=i`.
+	let mut clauses: Vec<(String, Vec<String>)> = vec![];
+	for bit in 0..64 {
+		let (p, i) = (permitted >> bit & 1 != 0, inheritable >> bit & 1 != 0);
+		if !p && !i {
+			continue;
+		}
+		let mut flags = String::new();
+		if effective {
+			flags.push('e');
+		}
+		if i {
+			flags.push('i');
+		}
+		if p {
+			flags.push('p');
+		}
+		let name = CAP_NAMES
+			.get(bit)
+			.map_or(bit.to_string(), |&n| n.to_owned());
+		match clauses.iter_mut().find(|(f, _)| *f == flags) {
+			Some((_, names)) => names.push(name),
+			None => clauses.push((flags, vec![name])),
+		}
+	}
+	if clauses.is_empty() {
+		return None;
+	}
+
+	let mut text = String::new();
+	for (flags, names) in clauses {
+		if !text.is_empty() {
+			text.push(' ');
+		}
+		_ = write!(text, "{}={flags}", names.join(","));
+	}
+	Some(text)
+}
+
+/// Converts capabilities in text form, such as `cap_net_raw=ep` or
+/// `cap_chown,cap_kill+ei`, to the raw value of a `security.capability`
+/// attribute.
+///
+/// Since a file only has a single effective flag, it's set if any
+/// capability is effective. Returns `None` if the text can't be parsed.
+#[must_use]
+pub fn from_text(text: &str) -> Option<Vec<u8>> {
+	let (mut permitted, mut inheritable, mut effective) = (0u64, 0u64, false);
+
+	for clause in text.split_whitespace() {
+		let op = clause.find(['=', '+', '-'])?;
+		let (names, mut actions) = clause.split_at(op);
+
+		let mut bits = 0u64;
+		for name in names.split(',').filter(|n| !n.is_empty()) {
+			let name = name.to_ascii_lowercase();
+			if name == "all" {
+				bits |= (1 << CAP_NAMES.len()) - 1;
+				continue;
+			}
+			let bit = match CAP_NAMES.iter().position(|&n| n == name) {
+				Some(bit) => bit,
+				None => name.parse().ok().filter(|&b: &usize| b < 64)?,
+			};
+			bits |= 1 << bit;
+		}
+		if names.is_empty() {
+			bits = (1 << CAP_NAMES.len()) - 1;
+		}
+
+		// Each clause is a series of actions, like `=e+p`.
+		while let Some(op) = actions.chars().next() {
+			let rest = &actions[1..];
+			let end = rest.find(['=', '+', '-']).unwrap_or(rest.len());
+			let (flags, next) = rest.split_at(end);
+			if op == '=' {
+				permitted &= !bits;
+				inheritable &= !bits;
+			}
+			for flag in flags.chars() {
+				let set = |caps: &mut u64| {
+					if op == '-' {
+						*caps &= !bits;
+					} else {
+						*caps |= bits;
+					}
+				};
+				match flag {
+					'p' => set(&mut permitted),
+					'i' => set(&mut inheritable),
+					'e' => effective = op != '-',
+					_ => return None,
+				}
+			}
+			actions = next;
+		}
+	}
+	if permitted == 0 && inheritable == 0 {
+		return None;
+	}
+
+	let mut magic = REVISION_2;
+	if effective {
+		magic |= FLAG_EFFECTIVE;
+	}
+	#[allow(clippy::cast_possible_truncation)]
+	let words = [
+		magic,
+		permitted as u32,
+		inheritable as u32,
+		(permitted >> 32) as u32,
+		(inheritable >> 32) as u32,
+	];
+	Some(words.iter().flat_map(|w| w.to_le_bytes()).collect())
+}
+
+/// Reads the capabilities of a file in a tar archive from its PAX extended
+/// header, where tools like GNU tar store extended attributes.
+pub(crate) fn from_tar_entry<R: Read>(
+	entry: &mut tar::Entry<'_, R>,
+) -> std::io::Result<Option<Vec<u8>>> {
+	let Some(extensions) = entry.pax_extensions()? else {
+		return Ok(None);
+	};
+	for extension in extensions {
+		let extension = extension?;
+		if extension.key().is_ok_and(|k| k == PAX_KEY) {
+			return Ok(Some(extension.value_bytes().to_owned()));
+		}
+	}
+	Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{from_tar_entry, from_text, to_text};
+
+	#[test]
+	fn test_text_round_trip() {
+		// `setcap cap_net_raw=ep` on a typical `ping`.
+		let raw = [
+			0x01, 0x00, 0x00, 0x02, 0x00, 0x20, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+			0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+		];
+		assert_eq!(to_text(&raw).as_deref(), Some("cap_net_raw=ep"));
+		assert_eq!(from_text("cap_net_raw=ep").as_deref(), Some(&raw[..]));
+		assert_eq!(from_text("cap_net_raw+ep").as_deref(), Some(&raw[..]));
+
+		let raw = from_text("cap_chown,cap_kill=ip cap_bpf=i").unwrap();
+		assert_eq!(
+			to_text(&raw).as_deref(),
+			Some("cap_chown,cap_kill=ip cap_bpf=i")
+		);
+
+		assert_eq!(from_text("cap_chown=ep cap_chown-p"), None);
+		assert_eq!(from_text("cap_xenomorph=ep"), None);
+		assert_eq!(to_text(&[0x00, 0x00, 0x00, 0x05]), None);
+	}
+
+	#[test]
+	fn test_from_tar_entry() -> eyre::Result<()> {
+		let raw = from_text("cap_net_raw=ep").unwrap();
+		let mut builder = tar::Builder::new(vec![]);
+		builder.append_pax_extensions([("SCHILY.xattr.security.capability", &raw[..])])?;
+		let mut header = tar::Header::new_gnu();
+		header.set_size(0);
+		header.set_mode(0o755);
+		header.set_cksum();
+		builder.append_data(&mut header, "usr/bin/ping", &[][..])?;
+		let mut header = tar::Header::new_gnu();
+		header.set_size(0);
+		header.set_cksum();
+		builder.append_data(&mut header, "usr/bin/true", &[][..])?;
+		let archive = builder.into_inner()?;
+
+		let mut archive = tar::Archive::new(archive.as_slice());
+		let mut entries = archive.entries()?;
+		let mut ping = entries.next().unwrap()?;
+		assert_eq!(from_tar_entry(&mut ping)?, Some(raw));
+		let mut other = entries.next().unwrap()?;
+		assert_eq!(from_tar_entry(&mut other)?, None);
+
+		Ok(())
+	}
+}
@@ -1,39 +1,166 @@
 pub use source::DebSource;
 pub use target::DebTarget;
 
-use crate::util::{ExecExt, Verbosity};
+use crate::util::spawn_install;
 use eyre::Result;
-use std::path::Path;
+use flate2::write::GzEncoder;
+use liblzma::write::XzEncoder;
+use std::{fmt::Display, io::Write, path::Path, str::FromStr};
 use subprocess::Exec;
 
 pub mod source;
 pub mod target;
 
-pub fn install(deb: &Path) -> Result<()> {
-	Exec::cmd("dpkg")
+pub fn install(deb: &Path, dry_run: bool) -> Result<()> {
+	let cmd = Exec::cmd("dpkg")
 		.args(&["--no-force-overwrite", "-i"])
-		.arg(deb)
-		.log_and_spawn(Verbosity::VeryVerbose)
+		.arg(deb);
+	spawn_install(cmd, dry_run)
 }
 
-fn set_version_and_release(info: &mut super::PackageInfo, version: &str) {
-	let (version, release) = if let Some((version, release)) = version.split_once('-') {
+/// Compression algorithms `dpkg-deb` can use for the package's data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebCompression {
+	Gzip,
+	Xz,
+	Zstd,
+	None,
+}
+impl FromStr for DebCompression {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"gzip" => Ok(Self::Gzip),
+			"xz" => Ok(Self::Xz),
+			"zstd" => Ok(Self::Zstd),
+			"none" => Ok(Self::None),
+			_ => Err(format!(
+				"Unknown compression algorithm `{s}`. Expected one of gzip, xz, zstd or none."
+			)),
+		}
+	}
+}
+impl DebCompression {
+	/// The extension of archive members compressed with this algorithm.
+	pub(crate) fn extension(self) -> &'static str {
+		match self {
+			Self::Gzip => ".gz",
+			Self::Xz => ".xz",
+			Self::Zstd => ".zst",
+			Self::None => "",
+		}
+	}
+
+	/// Compresses `data` with this algorithm.
+	pub(crate) fn compress(self, data: Vec<u8>) -> std::io::Result<Vec<u8>> {
+		match self {
+			Self::Gzip => {
+				let mut encoder = GzEncoder::new(vec![], flate2::Compression::default());
+				encoder.write_all(&data)?;
+				encoder.finish()
+			}
+			Self::Xz => {
+				let mut encoder = XzEncoder::new(vec![], 6);
+				encoder.write_all(&data)?;
+				encoder.finish()
+			}
+			Self::Zstd => zstd::encode_all(data.as_slice(), 0),
+			Self::None => Ok(data),
+		}
+	}
+}
+impl Display for DebCompression {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str(match self {
+			Self::Gzip => "gzip",
+			Self::Xz => "xz",
+			Self::Zstd => "zstd",
+			Self::None => "none",
+		})
+	}
+}
+
+/// The Debian name of the architecture `xenomorph` was built for.
+pub(crate) fn host_arch() -> &'static str {
+	match std::env::consts::ARCH {
+		"x86" => "i386",
+		"x86_64" => "amd64",
+		"aarch64" => "arm64",
+		"arm" => "armhf",
+		"powerpc" => "powerpc",
+		"powerpc64" if cfg!(target_endian = "little") => "ppc64el",
+		"powerpc64" => "ppc64",
+		"mips64" if cfg!(target_endian = "little") => "mips64el",
+		"loongarch64" => "loong64",
+		// s390x, riscv64 and others are named the same.
+		arch => arch,
+	}
+}
+
+pub(crate) fn set_version_and_release(info: &mut super::PackageInfo, version: &str) {
+	// Upstream versions may have dashes of their own, so the Debian revision
+	// is whatever comes after the last one.
+	let (version, release) = if let Some((version, release)) = version.rsplit_once('-') {
 		(version, release)
 	} else {
 		(version, "1")
 	};
 
-	// Ignore epochs.
-	let version = version
-		.split_once(':')
-		.map_or(version, |(_epoch, version)| version);
+	let (epoch, version) = match version.split_once(':') {
+		Some((epoch, version)) => (epoch.parse().ok(), version),
+		None => (None, version),
+	};
 
+	info.epoch = epoch;
 	info.version = version.to_owned();
 	info.release = release.to_owned();
 }
 
+/// Splits a single relation of a dependency in Debian's syntax, like
+/// `libc6:any (>> 2.31)`, into the name, the comparison and the version.
+///
+/// The comparison is spelled the way most other formats do, as one of
+/// `<`, `<=`, `=`, `>=` and `>`, and is empty if there's no version.
+/// Architecture qualifiers, like `:any`, mean nothing to other formats,
+/// so they are dropped.
+pub(crate) fn split_relation(relation: &str) -> (&str, &str, &str) {
+	let (name, constraint) = relation.split_once('(').unwrap_or((relation, ""));
+	let name = name.trim();
+	let name = name.split_once(':').map_or(name, |(name, _)| name);
+
+	let constraint = constraint.trim().trim_end_matches(')');
+	let version = constraint.trim_start_matches(['<', '>', '=']);
+	let op = match &constraint[..constraint.len() - version.len()] {
+		">>" => ">",
+		"<<" => "<",
+		// The obsolete `>` and `<` meant the same as `>=` and `<=`.
+		">" => ">=",
+		"<" => "<=",
+		op => op,
+	};
+	(name, op, version.trim())
+}
+
 #[cfg(test)]
 mod tests {
+	#[test]
+	fn test_split_relation() {
+		use super::split_relation;
+
+		assert_eq!(split_relation("lsb"), ("lsb", "", ""));
+		assert_eq!(split_relation(" lsb (>= 3.0)"), ("lsb", ">=", "3.0"));
+		assert_eq!(
+			split_relation("libc6:any (>> 2.31)"),
+			("libc6", ">", "2.31")
+		);
+		assert_eq!(split_relation("libc6 (< 2.31)"), ("libc6", "<=", "2.31"));
+		assert_eq!(
+			split_relation("libc6 (= 1:2.31-1) "),
+			("libc6", "=", "1:2.31-1")
+		);
+	}
+
 	#[test]
 	fn test_set_version_and_release() {
 		let mut info = crate::PackageInfo::default();
@@ -41,6 +168,7 @@ mod tests {
 		super::set_version_and_release(&mut info, "1.0.0");
 		assert_eq!(info.version, "1.0.0");
 		assert_eq!(info.release, "1");
+		assert_eq!(info.epoch, None);
 
 		// With revision
 		super::set_version_and_release(&mut info, "1.0.0-2");
@@ -51,5 +179,16 @@ mod tests {
 		super::set_version_and_release(&mut info, "3:1.0.0-2");
 		assert_eq!(info.version, "1.0.0");
 		assert_eq!(info.release, "2");
+		assert_eq!(info.epoch, Some(3));
+
+		// With dashes in the upstream version
+		super::set_version_and_release(&mut info, "1.2-beta-3");
+		assert_eq!(info.version, "1.2-beta");
+		assert_eq!(info.release, "3");
+
+		// Without epoch again
+		super::set_version_and_release(&mut info, "1.0.1-1");
+		assert_eq!(info.version, "1.0.1");
+		assert_eq!(info.epoch, None);
 	}
 }
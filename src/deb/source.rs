@@ -2,25 +2,33 @@ use std::{
 	collections::HashMap,
 	fmt::Debug,
 	fs::File,
-	io::{Cursor, Read, Seek},
+	io::{Cursor, Read, Seek, SeekFrom, Write},
 	path::{Path, PathBuf},
 };
 
 use bzip2::read::BzDecoder;
 use flate2::read::GzDecoder;
-use liblzma::read::XzDecoder;
+use liblzma::{read::XzDecoder, stream::Stream as LzmaStream};
+use zstd::stream::read::Decoder as ZstdDecoder;
 
-use eyre::{bail, Result};
+use eyre::{bail, Context, Result};
 use subprocess::{Exec, NullFile};
 
 use crate::{
-	util::{make_unpack_work_dir, ExecExt, Verbosity},
-	Args, Format, PackageInfo, Script, SourcePackage,
+	caps,
+	util::{
+		installed_size, make_unpack_work_dir, normalize_archive_path, unpack_archive, ExecExt,
+		Verbosity,
+	},
+	Args, Format, MultiArch, PackageInfo, Script, SourcePackage,
 };
 
 pub struct DebSource {
 	info: PackageInfo,
 	data: Data,
+	verbosity: Verbosity,
+	/// Where to unpack the package, if not the current directory.
+	build_dir: Option<PathBuf>,
 }
 impl DebSource {
 	#[must_use]
@@ -40,12 +48,16 @@ impl DebSource {
 		let DebArchive {
 			mut data,
 			mut control_files,
-		} = DebArchive::extract(&info.file)?;
+		} = DebArchive::extract(&info.file, args.low_mem, args.verbosity)?;
 
 		let Some(control) = control_files.remove("control") else {
 			bail!("Control file not found!");
 		};
 		read_control(&mut info, &control);
+		info.arch = concrete_arch(&info.arch, super::host_arch());
+		if !args.keep_depends {
+			retain_portable_dependencies(&mut info.dependencies);
+		}
 
 		info.copyright = format!("see /usr/share/doc/{}/copyright", info.name);
 		if info.group.is_empty() {
@@ -54,10 +66,10 @@ impl DebSource {
 		info.binary_info = control;
 
 		if let Some(conffiles) = control_files.remove("conffiles") {
-			info.conffiles.extend(conffiles.lines().map(PathBuf::from));
+			info.conffiles.extend(parse_conffiles(&conffiles));
 		};
 
-		info.files.extend(data.files()?);
+		data.read_files(&mut info)?;
 
 		info.scripts = control_files
 			.into_iter()
@@ -68,7 +80,12 @@ impl DebSource {
 			info.arch.clone_from(arch);
 		}
 
-		Ok(Self { info, data })
+		Ok(Self {
+			info,
+			data,
+			verbosity: args.verbosity,
+			build_dir: args.build_dir.clone(),
+		})
 	}
 }
 impl SourcePackage for DebSource {
@@ -82,8 +99,12 @@ impl SourcePackage for DebSource {
 		self.info
 	}
 	fn unpack(&mut self) -> Result<PathBuf> {
-		let work_dir = make_unpack_work_dir(&self.info)?;
+		let work_dir = make_unpack_work_dir(&self.info, self.build_dir.as_deref(), self.verbosity)?;
 		self.data.unpack(&work_dir)?;
+
+		if self.info.installed_size.is_none() {
+			self.info.installed_size = Some(installed_size(&work_dir)?);
+		}
 		Ok(work_dir)
 	}
 }
@@ -96,33 +117,100 @@ impl Debug for DebSource {
 }
 
 //= Utilties
-struct Data(tar::Archive<Cursor<Vec<u8>>>);
+pub(super) struct Data(tar::Archive<Spool>);
 
 impl Data {
-	// In the tar file, the files are all prefixed with "./", but we want them
-	// to be just "/". So, we gotta do this!
-	fn files(&mut self) -> Result<impl Iterator<Item = PathBuf> + '_> {
-		let entries = self.0.entries()?;
+	pub(super) fn read_files(&mut self, info: &mut PackageInfo) -> Result<()> {
+		for entry in self.0.entries()? {
+			let Ok(mut entry) = entry else {
+				continue;
+			};
+			let Ok(path) = entry.path() else {
+				continue;
+			};
+			let path = normalize_archive_path(&path);
+			if let Some(caps) = caps::from_tar_entry(&mut entry)? {
+				info.capabilities.insert(path.clone(), caps);
+			}
 
-		Ok(entries.filter_map(|entry| {
-			let entry = entry.ok()?;
-			let path = entry.path().ok()?;
-			Some(Path::new("/").join(path.strip_prefix(".").unwrap_or(&path)))
-		}))
+			// The root directory belongs to the system, not the package.
+			if entry.header().entry_type().is_dir() && path != Path::new("/") {
+				info.dirs.push(path.clone());
+			}
+			info.files.push(path);
+		}
+		Ok(())
 	}
 
 	fn unpack(&mut self, dst: &Path) -> std::io::Result<()> {
 		// to unpack tar files, apparently we have to rewind first...
 		let mut inner =
-			std::mem::replace(&mut self.0, tar::Archive::new(Cursor::new(vec![]))).into_inner();
+			std::mem::replace(&mut self.0, tar::Archive::new(Spool::default())).into_inner();
 		inner.rewind()?;
-		tar::Archive::new(inner).unpack(dst)
+		unpack_archive(&mut tar::Archive::new(inner), dst)
 	}
 }
 
-struct DebArchive {
-	data: Data,
-	control_files: HashMap<&'static str, String>,
+/// Somewhere to keep a decompressed archive while the package is converted.
+#[derive(Debug)]
+pub(super) enum Spool {
+	Memory(Cursor<Vec<u8>>),
+	/// An anonymous temporary file, for archives too big to keep in memory.
+	Disk(File),
+}
+impl Spool {
+	/// Copies everything from `reader` into a new spool, on disk if `on_disk`
+	/// is set, and rewinds it.
+	fn fill(mut reader: impl Read, on_disk: bool) -> std::io::Result<Self> {
+		let mut spool = if on_disk {
+			Self::Disk(tempfile::tempfile()?)
+		} else {
+			Self::default()
+		};
+		std::io::copy(&mut reader, &mut spool)?;
+		spool.rewind()?;
+		Ok(spool)
+	}
+}
+impl Default for Spool {
+	fn default() -> Self {
+		Self::Memory(Cursor::default())
+	}
+}
+impl Read for Spool {
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		match self {
+			Self::Memory(c) => c.read(buf),
+			Self::Disk(f) => f.read(buf),
+		}
+	}
+}
+impl Write for Spool {
+	fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+		match self {
+			Self::Memory(c) => c.write(buf),
+			Self::Disk(f) => f.write(buf),
+		}
+	}
+	fn flush(&mut self) -> std::io::Result<()> {
+		match self {
+			Self::Memory(c) => c.flush(),
+			Self::Disk(f) => f.flush(),
+		}
+	}
+}
+impl Seek for Spool {
+	fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+		match self {
+			Self::Memory(c) => c.seek(pos),
+			Self::Disk(f) => f.seek(pos),
+		}
+	}
+}
+
+pub(super) struct DebArchive {
+	pub(super) data: Data,
+	pub(super) control_files: HashMap<&'static str, String>,
 }
 
 impl DebArchive {
@@ -135,28 +223,41 @@ impl DebArchive {
 		"prerm",
 	];
 
-	fn extract(deb_file: &Path) -> Result<Self> {
-		if let Ok(dpkg_deb) = which::which("dpkg-deb") {
-			Self::extract_with_dpkg_deb(&dpkg_deb, deb_file)
-		} else {
-			Self::extract_manually(File::open(deb_file)?)
+	/// Packages at least this big are always spooled to disk, as if with `--low-mem`.
+	const LOW_MEM_SIZE: u64 = 256 * 1024 * 1024;
+
+	/// Extracts the control files and data archive from a package.
+	///
+	/// If `low_mem` is set, or the package is bigger than [`Self::LOW_MEM_SIZE`],
+	/// the data archive is kept in a temporary file instead of in memory.
+	fn extract(deb_file: &Path, low_mem: bool, verbosity: Verbosity) -> Result<Self> {
+		let low_mem = low_mem || std::fs::metadata(deb_file)?.len() >= Self::LOW_MEM_SIZE;
+		match which::which("dpkg-deb") {
+			// `dpkg-deb` would output the whole data archive into memory.
+			Ok(dpkg_deb) if !low_mem => Self::extract_with_dpkg_deb(&dpkg_deb, deb_file, verbosity),
+			_ => Self::extract_manually(File::open(deb_file)?, low_mem),
 		}
 	}
 
-	fn extract_with_dpkg_deb(dpkg_deb: &Path, deb_file: &Path) -> Result<Self> {
+	fn extract_with_dpkg_deb(
+		dpkg_deb: &Path,
+		deb_file: &Path,
+		verbosity: Verbosity,
+	) -> Result<Self> {
 		// HACK(pluie): You can't query subprocess's stdout settings once set,
 		// and we really don't want dpkg-deb spilling bytes from tar files
 		// into readable stdout, so we want to limit the output to commands only,
 		// even in very verbose mode.
-		let mut verbosity = Verbosity::get();
-		if verbosity == Verbosity::VeryVerbose {
-			verbosity = Verbosity::Verbose;
-		}
+		let data_verbosity = match verbosity {
+			Verbosity::VeryVerbose => Verbosity::Verbose,
+			v => v,
+		};
 
 		let data = Exec::cmd(dpkg_deb)
 			.arg("--fsys-tarfile")
 			.arg(deb_file)
-			.log_and_output(verbosity)?
+			.log_and_output(data_verbosity)
+			.wrap_err("Unable to extract data.tar with dpkg-deb")?
 			.stdout;
 
 		let mut control_files = HashMap::new();
@@ -167,7 +268,7 @@ impl DebArchive {
 				.arg(deb_file)
 				.arg(file)
 				.stderr(NullFile)
-				.log_and_output_without_checking(None)?;
+				.log_and_output_without_checking(verbosity)?;
 
 			if out.success() {
 				control_files.insert(*file, out.stdout_str());
@@ -175,12 +276,14 @@ impl DebArchive {
 		}
 
 		Ok(Self {
-			data: Data(tar::Archive::new(Cursor::new(data))),
+			data: Data(tar::Archive::new(Spool::Memory(Cursor::new(data)))),
 			control_files,
 		})
 	}
 
-	fn extract_manually<R: Read>(source: R) -> Result<Self> {
+	/// Extracts the control files and data archive from a package without
+	/// `dpkg-deb`, spooling the data archive to disk if `low_mem` is set.
+	pub(super) fn extract_manually<R: Read>(source: R, low_mem: bool) -> Result<Self> {
 		let mut ar = ar::Archive::new(source);
 		let mut control = None;
 		let mut data = None;
@@ -189,10 +292,10 @@ impl DebArchive {
 			let mut entry = entry?;
 
 			if control.is_none() {
-				control = Self::try_read_tar(&mut entry, "control.tar")?;
+				control = Self::try_read_tar(&mut entry, "control.tar", false)?;
 			}
 			if data.is_none() {
-				data = Self::try_read_tar(&mut entry, "data.tar")?;
+				data = Self::try_read_tar(&mut entry, "data.tar", low_mem)?;
 			}
 		}
 
@@ -230,33 +333,115 @@ impl DebArchive {
 		})
 	}
 
+	/// Decompresses `entry` into a spool if it's the tarball `file`, with
+	/// any extension, keeping it on disk if `on_disk` is set.
 	fn try_read_tar<R: Read>(
 		entry: &mut ar::Entry<'_, R>,
 		file: &str,
-	) -> Result<Option<tar::Archive<Cursor<Vec<u8>>>>> {
+		on_disk: bool,
+	) -> Result<Option<tar::Archive<Spool>>> {
 		let id = entry.header().identifier();
 		if let Some(ext) = id.strip_prefix(file.as_bytes()) {
-			let mut tar = vec![];
-			match ext {
-				b".gz" => GzDecoder::new(entry).read_to_end(&mut tar)?,
-				b".bz2" => BzDecoder::new(entry).read_to_end(&mut tar)?,
-				b".xz" | b".lzma" => XzDecoder::new(entry).read_to_end(&mut tar)?,
+			let member = String::from_utf8_lossy(id).into_owned();
+			let spool = match ext {
+				b".gz" => Spool::fill(GzDecoder::new(entry), on_disk),
+				b".bz2" => Spool::fill(BzDecoder::new(entry), on_disk),
+				b".xz" => Spool::fill(XzDecoder::new(entry), on_disk),
+				// Very old versions of dpkg used the legacy `.lzma` format,
+				// which isn't wrapped in an xz container.
+				b".lzma" => LzmaStream::new_lzma_decoder(u64::MAX)
+					.map_err(std::io::Error::from)
+					.and_then(|s| Spool::fill(XzDecoder::new_stream(entry, s), on_disk)),
+				b".zst" => ZstdDecoder::new(entry).and_then(|d| Spool::fill(d, on_disk)),
 				// it's already a tarball
-				b"" => entry.read_to_end(&mut tar)?,
+				b"" => Spool::fill(entry, on_disk),
 				_ => bail!(
 					"{file} is compressed with unknown compression algorithm ({:?})!",
 					std::str::from_utf8(ext)
 				),
-			};
-			let tar = tar::Archive::new(Cursor::new(tar));
-			Ok(Some(tar))
+			}
+			.wrap_err_with(|| format!("Unable to decompress {member}"))?;
+			Ok(Some(tar::Archive::new(spool)))
 		} else {
 			Ok(None)
 		}
 	}
 }
 
-fn read_control(info: &mut PackageInfo, control: &str) {
+/// Reads the paths in a `conffiles` control file.
+///
+/// Some tools write an md5sum after each path, and newer versions of dpkg
+/// allow flags like `remove-on-upgrade` before it, so only the path is kept.
+fn parse_conffiles(conffiles: &str) -> impl Iterator<Item = PathBuf> + '_ {
+	conffiles.lines().filter_map(|line| {
+		line.split_whitespace()
+			.find(|field| field.starts_with('/'))
+			.map(PathBuf::from)
+	})
+}
+
+/// Splits a comma-separated list of package relationships.
+fn split_relations(value: &str) -> impl Iterator<Item = String> + '_ {
+	value
+		.split(',')
+		.map(str::trim)
+		.filter(|s| !s.is_empty())
+		.map(str::to_owned)
+}
+
+/// Dependencies that can be expected to exist on all target distributions.
+const PORTABLE_DEPENDENCIES: &[&str] = &["lsb"];
+
+/// Drops all dependencies that aren't known to exist on other distributions.
+///
+/// Dependencies may be in Debian's syntax, like `lsb (>= 3.0)`, or in
+/// apk's, like `lsb>=3.0`.
+/// See [`PackageInfo::dependencies`] for more.
+pub(crate) fn retain_portable_dependencies(dependencies: &mut Vec<String>) {
+	dependencies.retain(|dep| {
+		let name = dep
+			.split(|c: char| c.is_whitespace() || matches!(c, '(' | '<' | '>' | '=' | '~'))
+			.next()
+			.unwrap_or(dep);
+		PORTABLE_DEPENDENCIES.contains(&name)
+	});
+}
+
+/// Picks a single architecture to convert the package for, if its
+/// `Architecture` field is a wildcard or a list of architectures.
+///
+/// Wildcards become the host's architecture, and lists become the host's
+/// architecture if it's listed, or the first one otherwise.
+fn concrete_arch(arch: &str, host: &str) -> String {
+	let arches: Vec<_> = arch.split_whitespace().collect();
+	let concrete = match &arches[..] {
+		[] => return arch.to_owned(),
+		["all"] => return "all".into(),
+		[arch] if !is_wildcard(arch) => return (*arch).to_owned(),
+		_ if arches.iter().any(|a| *a == host || is_wildcard(a)) => host,
+		[first, ..] => first,
+	};
+	eprintln!("Warning: The package is for architectures `{arch}`; converting it for {concrete}.");
+	concrete.to_owned()
+}
+
+/// Whether `arch` is an architecture wildcard, like `any` or `linux-any`.
+fn is_wildcard(arch: &str) -> bool {
+	arch == "any" || arch.starts_with("any-") || arch.ends_with("-any")
+}
+
+/// The list of relationships that a control field holds, if any.
+fn relations<'a>(info: &'a mut PackageInfo, field: &str) -> Option<&'a mut Vec<String>> {
+	match field {
+		"depends" => Some(&mut info.dependencies),
+		"recommends" => Some(&mut info.recommends),
+		"suggests" => Some(&mut info.suggests),
+		"enhances" => Some(&mut info.enhances),
+		_ => None,
+	}
+}
+
+pub(super) fn read_control(info: &mut PackageInfo, control: &str) {
 	let mut field = String::new();
 
 	for c in control.lines() {
@@ -267,6 +452,9 @@ fn read_control(info: &mut PackageInfo, control: &str) {
 				info.description.push_str(c);
 			}
 			info.description.push('\n');
+		} else if let (true, Some(relations)) = (c.starts_with(' '), relations(info, &field)) {
+			// Handle folded dependency lists
+			relations.extend(split_relations(c));
 		} else if let Some((f, value)) = c.split_once(':') {
 			let value = value.trim().to_owned();
 			field = f.to_ascii_lowercase();
@@ -276,10 +464,16 @@ fn read_control(info: &mut PackageInfo, control: &str) {
 				"version" => super::set_version_and_release(info, &value),
 				"architecture" => info.arch = value,
 				"maintainer" => info.maintainer = value,
+				"homepage" => info.url = value,
 				"section" => info.group = value,
 				"description" => info.summary = value,
-				// TODO: think more about handling dependencies
-				// "depends" => info.dependencies = value.split(", ").map(|s| s.to_owned()).collect(),
+				"depends" | "recommends" | "suggests" | "enhances" => {
+					if let Some(relations) = relations(info, &field) {
+						relations.extend(split_relations(&value));
+					}
+				}
+				"installed-size" => info.installed_size = value.parse().ok(),
+				"multi-arch" => info.multi_arch = MultiArch::from_deb_value(&value),
 				_ => { /* ignore */ }
 			}
 		}
@@ -291,6 +485,13 @@ mod tests {
 	use eyre::Result;
 
 	fn test_deb_archive() -> Result<Vec<u8>> {
+		test_deb_archive_with("", Ok)
+	}
+
+	fn test_deb_archive_with(
+		ext: &str,
+		compress: impl Fn(Vec<u8>) -> std::io::Result<Vec<u8>>,
+	) -> Result<Vec<u8>> {
 		let control = b"
 Package: xenomorph
 Version: 0.1.0-2
@@ -306,18 +507,22 @@ Description:
 		header.set_size(control.len() as u64);
 		header.set_cksum();
 		control_files.append_data(&mut header, "control", &control[..])?;
-		let control_tar = control_files.into_inner()?;
+		let control_tar = compress(control_files.into_inner()?)?;
 
-		let data_files = tar::Builder::new(vec![]);
-		let data_tar = data_files.into_inner()?;
+		let mut data_files = tar::Builder::new(vec![]);
+		let mut header = tar::Header::new_gnu();
+		header.set_mode(0o755);
+		header.set_size(9);
+		data_files.append_data(&mut header, "./usr/bin/xenomorph", &b"#!/bin/sh"[..])?;
+		let data_tar = compress(data_files.into_inner()?)?;
 
 		let mut deb_archive = ar::Builder::new(vec![]);
 		deb_archive.append(
-			&ar::Header::new(b"control.tar".into(), control_tar.len() as u64),
+			&ar::Header::new(format!("control.tar{ext}").into(), control_tar.len() as u64),
 			control_tar.as_slice(),
 		)?;
 		deb_archive.append(
-			&ar::Header::new(b"data.tar".into(), data_tar.len() as u64),
+			&ar::Header::new(format!("data.tar{ext}").into(), data_tar.len() as u64),
 			data_tar.as_slice(),
 		)?;
 
@@ -326,7 +531,8 @@ Description:
 
 	#[test]
 	fn test_deb_archive_extract_manually() -> Result<()> {
-		let deb_archive = super::DebArchive::extract_manually(test_deb_archive()?.as_slice())?;
+		let deb_archive =
+			super::DebArchive::extract_manually(test_deb_archive()?.as_slice(), false)?;
 		let control = deb_archive.control_files.get("control").unwrap();
 		let mut info = crate::PackageInfo::default();
 		super::read_control(&mut info, &control);
@@ -341,4 +547,193 @@ Description:
 
 		Ok(())
 	}
+	#[test]
+	fn test_deb_archive_extract_zstd() -> Result<()> {
+		let deb = test_deb_archive_with(".zst", |tar| zstd::encode_all(tar.as_slice(), 0))?;
+		let deb_archive = super::DebArchive::extract_manually(deb.as_slice(), false)?;
+		let control = deb_archive.control_files.get("control").unwrap();
+		let mut info = crate::PackageInfo::default();
+		super::read_control(&mut info, control);
+
+		assert_eq!(info.name, "xenomorph");
+		assert_eq!(info.version, "0.1.0");
+		assert_eq!(info.release, "2");
+
+		Ok(())
+	}
+	#[test]
+	fn test_deb_archive_extract_lzma() -> Result<()> {
+		let deb = test_deb_archive_with(".lzma", |tar| {
+			let options = liblzma::stream::LzmaOptions::new_preset(6)?;
+			let stream = liblzma::stream::Stream::new_lzma_encoder(&options)?;
+			let mut encoder = liblzma::write::XzEncoder::new_stream(vec![], stream);
+			std::io::Write::write_all(&mut encoder, &tar)?;
+			encoder.finish()
+		})?;
+		let deb_archive = super::DebArchive::extract_manually(deb.as_slice(), false)?;
+		let control = deb_archive.control_files.get("control").unwrap();
+		let mut info = crate::PackageInfo::default();
+		super::read_control(&mut info, control);
+
+		assert_eq!(info.name, "xenomorph");
+		assert_eq!(info.version, "0.1.0");
+		assert_eq!(info.release, "2");
+
+		Ok(())
+	}
+	#[test]
+	fn test_deb_archive_extract_low_mem() -> Result<()> {
+		let deb = test_deb_archive_with(".xz", |tar| {
+			let mut encoder = liblzma::write::XzEncoder::new(vec![], 6);
+			std::io::Write::write_all(&mut encoder, &tar)?;
+			encoder.finish()
+		})?;
+		let deb_archive = super::DebArchive::extract_manually(deb.as_slice(), true)?;
+		assert!(matches!(
+			deb_archive.data.0.into_inner(),
+			super::Spool::Disk(_)
+		));
+
+		let deb_archive = super::DebArchive::extract_manually(deb.as_slice(), true)?;
+		let mut data = deb_archive.data;
+		let mut info = crate::PackageInfo::default();
+		data.read_files(&mut info)?;
+		assert_eq!(info.files, [std::path::PathBuf::from("/usr/bin/xenomorph")]);
+
+		// The spool is rewound to be read a second time.
+		let unpacked_dir = tempfile::tempdir()?;
+		data.unpack(unpacked_dir.path())?;
+		assert!(unpacked_dir.path().join("usr/bin/xenomorph").is_file());
+
+		Ok(())
+	}
+	#[test]
+	fn test_deb_archive_truncated_member() -> Result<()> {
+		let deb = test_deb_archive_with(".gz", |tar| {
+			let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+			std::io::Write::write_all(&mut encoder, &tar)?;
+			let mut gz = encoder.finish()?;
+			gz.truncate(gz.len() / 2);
+			Ok(gz)
+		})?;
+		let Err(err) = super::DebArchive::extract_manually(deb.as_slice(), false) else {
+			panic!("a truncated control.tar.gz should fail to extract");
+		};
+		assert_eq!(err.to_string(), "Unable to decompress control.tar.gz");
+
+		Ok(())
+	}
+	#[test]
+	fn test_read_files_empty_dir() -> Result<()> {
+		let mut data_files = tar::Builder::new(vec![]);
+		for dir in [
+			"./",
+			"./usr/",
+			"./usr/bin/",
+			"./var/",
+			"./var/cache/xenomorph/",
+		] {
+			let mut header = tar::Header::new_gnu();
+			header.set_entry_type(tar::EntryType::Directory);
+			header.set_mode(0o755);
+			header.set_size(0);
+			data_files.append_data(&mut header, dir, std::io::empty())?;
+		}
+		let mut header = tar::Header::new_gnu();
+		header.set_mode(0o755);
+		header.set_size(0);
+		data_files.append_data(&mut header, "./usr/bin/xenomorph", std::io::empty())?;
+		let mut data = super::Data(tar::Archive::new(super::Spool::Memory(
+			std::io::Cursor::new(data_files.into_inner()?),
+		)));
+
+		let mut info = crate::PackageInfo::default();
+		data.read_files(&mut info)?;
+		assert!(info.dirs.contains(&"/var/cache/xenomorph".into()));
+		assert!(!info.dirs.contains(&"/".into()));
+
+		let unpacked_dir = tempfile::tempdir()?;
+		data.unpack(unpacked_dir.path())?;
+		assert!(unpacked_dir.path().join("var/cache/xenomorph").is_dir());
+
+		// The empty directory has to be listed as a directory, or else it'd
+		// be left out of the converted package.
+		let file_list = crate::rpm::RpmTarget::file_list(
+			&info,
+			unpacked_dir.path(),
+			true,
+			crate::util::Verbosity::Normal,
+		)?;
+		assert!(file_list.contains("%dir \"/var/cache/xenomorph\"\n"));
+		assert!(file_list.contains("\n\"/usr/bin/xenomorph\"\n"));
+
+		Ok(())
+	}
+	#[test]
+	fn test_read_control_depends() {
+		let mut info = crate::PackageInfo::default();
+		super::read_control(
+			&mut info,
+			"Package: xenomorph\nDepends: libc6 (>= 2.31), zlib1g\n",
+		);
+		assert_eq!(info.dependencies, vec!["libc6 (>= 2.31)", "zlib1g"]);
+
+		let mut dependencies = vec!["libc6 (>= 2.31)".to_owned(), "lsb (>= 3.0)".to_owned()];
+		super::retain_portable_dependencies(&mut dependencies);
+		assert_eq!(dependencies, vec!["lsb (>= 3.0)"]);
+	}
+	#[test]
+	fn test_concrete_arch() {
+		use super::concrete_arch;
+
+		assert_eq!(concrete_arch("any", "arm64"), "arm64");
+		assert_eq!(concrete_arch("linux-any", "arm64"), "arm64");
+		assert_eq!(concrete_arch("all", "arm64"), "all");
+		assert_eq!(concrete_arch("amd64", "arm64"), "amd64");
+
+		assert_eq!(concrete_arch("amd64 arm64", "arm64"), "arm64");
+		assert_eq!(concrete_arch("amd64 arm64", "riscv64"), "amd64");
+	}
+	#[test]
+	fn test_parse_conffiles() {
+		let conffiles = "\
+/etc/xenomorph.conf
+/etc/xenomorph/formats.conf d41d8cd98f00b204e9800998ecf8427e
+
+remove-on-upgrade /etc/xenomorph/old.conf
+";
+		assert_eq!(
+			super::parse_conffiles(conffiles).collect::<Vec<_>>(),
+			[
+				"/etc/xenomorph.conf",
+				"/etc/xenomorph/formats.conf",
+				"/etc/xenomorph/old.conf",
+			]
+			.map(std::path::PathBuf::from)
+		);
+	}
+	#[test]
+	fn test_read_control_weak_dependencies() {
+		let mut info = crate::PackageInfo::default();
+		super::read_control(
+			&mut info,
+			"Package: xenomorph\nRecommends: rpm,\n dpkg-dev\nSuggests: lintian\nEnhances: alien\n",
+		);
+		assert_eq!(info.recommends, ["rpm", "dpkg-dev"]);
+		assert_eq!(info.suggests, ["lintian"]);
+		assert_eq!(info.enhances, ["alien"]);
+		assert!(info.dependencies.is_empty());
+	}
+	#[test]
+	fn test_read_control_multi_arch() {
+		let mut info = crate::PackageInfo::default();
+		super::read_control(&mut info, "Package: xenomorph\nMulti-Arch: foreign\n");
+		assert_eq!(info.multi_arch, Some(crate::MultiArch::Foreign));
+	}
+	#[test]
+	fn test_read_control_installed_size() {
+		let mut info = crate::PackageInfo::default();
+		super::read_control(&mut info, "Package: xenomorph\nInstalled-Size: 2048\n");
+		assert_eq!(info.installed_size, Some(2048));
+	}
 }
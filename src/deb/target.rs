@@ -1,7 +1,7 @@
 use std::{
-	collections::HashMap,
+	collections::{BTreeSet, HashMap},
 	fmt::Write as _,
-	fs::File,
+	fs::{DirEntry, File},
 	io::{BufRead, BufReader, Read, Write},
 	os::unix::prelude::OpenOptionsExt,
 	path::{Path, PathBuf},
@@ -10,15 +10,23 @@ use std::{
 use eyre::{bail, Context, Result};
 use flate2::read::GzDecoder;
 use fs_extra::dir::CopyOptions;
+use md5::{Digest, Md5};
 use subprocess::{Exec, Redirection};
-use time::{format_description::well_known::Rfc2822, OffsetDateTime};
+use time::format_description::well_known::Rfc2822;
 
+use super::DebCompression;
 use crate::{
-	util::{chmod, fetch_email_address, mkdir, ExecExt},
-	Args, PackageInfo, Script, TargetPackage,
+	caps,
+	rpm::rpm_group_to_deb_section,
+	tgz::target::append_entry,
+	util::{
+		check_arch_independent, chmod, fetch_build_date, fetch_email_address, installed_size,
+		mkdir, move_file, DebArgs, ExecExt, Verbosity,
+	},
+	Args, ChangelogEntry, Format, PackageInfo, Relation, Script, TargetPackage, VersionOp,
 };
 
-// FIXME: Use custom patch dirs (maybe break compat with alien?)
+/// Where to look for patches after any given with `--patch` or `--patch-dir`.
 const PATCH_DIRS: &[&str] = &["/var/lib/alien", "/usr/share/alien/patches"];
 
 #[derive(Debug)]
@@ -27,56 +35,89 @@ pub struct DebTarget {
 	unpacked_dir: PathBuf,
 	debian_dir: PathBuf,
 	dir_map: HashMap<&'static Path, &'static Path>,
+	/// The copy of the unpacked directory used as the `.orig` tree, if any.
+	orig_dir: Option<PathBuf>,
+	/// Whether to build even if the architecture doesn't match this system's.
+	force_arch: bool,
+	/// The maintainer in the package's control file.
+	maintainer: String,
+	section: String,
+	priority: String,
+	/// Whether to assemble the package directly instead of with debhelper.
+	no_debhelper: bool,
+	compression: Option<DebCompression>,
+	clamp_mtime: Option<u64>,
+	verbosity: Verbosity,
 }
 impl DebTarget {
 	pub fn new(mut info: PackageInfo, unpacked_dir: PathBuf, args: &Args) -> Result<Self> {
-		Self::sanitize_info(&mut info)?;
-
-		// Make .orig.tar.gz directory?
-		if !args.deb_args.single && !args.generate {
-			let option = CopyOptions {
-				overwrite: true,
-				..Default::default()
-			};
-			let mut target = unpacked_dir.as_os_str().to_owned();
-			target.push(".orig");
-			let target = PathBuf::from(target);
+		Self::sanitize_info(&mut info, !args.no_conversion_notice, args.keep_release)?;
+		if args.check_arch {
+			check_arch_independent(&info, &unpacked_dir, args.verbosity)?;
+		}
 
-			if !target.exists() {
-				mkdir(&target)?;
-			}
-			fs_extra::dir::copy(&unpacked_dir, target, &option)?;
+		if args.deb_args.binary_only && args.deb_args.patch.is_some() {
+			bail!(
+				"--patch cannot be used with --binary-only, since patches need a .orig directory"
+			);
 		}
+		if args.deb_args.no_debhelper && args.deb_args.patch.is_some() {
+			bail!("--patch cannot be used with --no-debhelper, since patched packages are built with debian/rules");
+		}
+		let orig_dir = Self::make_orig_dir(
+			&unpacked_dir,
+			&args.output_dir,
+			&args.deb_args,
+			args.generate,
+			args.verbosity,
+		)?;
 
-		let patch_file = if args.deb_args.nopatch {
-			None
-		} else {
-			match &args.deb_args.patch {
-				Some(o) => Some(o.clone()),
-				None => get_patch(&info, args.deb_args.anypatch, PATCH_DIRS),
-			}
-		};
+		let patch_file =
+			if args.deb_args.nopatch || args.deb_args.binary_only || args.deb_args.no_debhelper {
+				None
+			} else {
+				match &args.deb_args.patch {
+					Some(o) if !o.is_dir() => Some(o.clone()),
+					_ => get_patch(&info, args.deb_args.anypatch, &patch_dirs(&args.deb_args)),
+				}
+			};
 
 		let debian_dir = unpacked_dir.join("debian");
-		mkdir(&debian_dir)?;
+		mkdir(&debian_dir, args.verbosity)?;
 
 		// Use a patch file to debianize?
 		if let Some(patch) = &patch_file {
-			return Self::patch(info, unpacked_dir, patch, debian_dir);
+			let mut target = Self::patch(info, unpacked_dir, patch, debian_dir, args.verbosity)?;
+			target.orig_dir = orig_dir;
+			target.force_arch = args.deb_args.force_arch;
+			return Ok(target);
 		}
 
 		// Automatic debianization.
-		let mut writer = DebWriter::new(debian_dir, info)?;
+		let mut writer = DebWriter::new(debian_dir, info, args.maintainer.as_deref())?;
+		writer.section = args.section.clone().unwrap_or(writer.section);
+		writer.priority = args.priority.clone().unwrap_or(writer.priority);
 
-		writer.write_changelog()?;
+		writer.write_changelog(!args.no_conversion_notice, args.changelog_entry.as_deref())?;
 		writer.write_control()?;
 		writer.write_copyright()?;
-		writer.write_conffiles()?;
+		writer.write_conffiles(args.deb_args.explicit_conffiles || args.deb_args.no_debhelper)?;
 		writer.write_compat(7)?; // Use debhelper v7
-		writer.write_rules(args.deb_args.fixperms)?;
+		writer.write_source_format(
+			args.deb_args.single || args.deb_args.binary_only,
+			args.verbosity,
+		)?;
+		writer.write_rules(&args.deb_args)?;
 		writer.write_scripts()?;
 
-		let DebWriter { info, dir, .. } = writer;
+		let DebWriter {
+			info,
+			dir,
+			maintainer,
+			section,
+			priority,
+			..
+		} = writer;
 
 		// Move files to FHS-compliant locations, if possible.
 		// Note: no trailing slashes on these directory names!
@@ -98,7 +139,7 @@ impl DebTarget {
 				Exec::cmd("install")
 					.arg("-d")
 					.arg(dir_base)
-					.log_and_spawn(None)?;
+					.log_and_spawn(args.verbosity)?;
 
 				fs_extra::dir::move_dir(&prefixed_old_dir, &prefixed_new_dir, &CopyOptions::new())?;
 				if prefixed_old_dir.is_dir() {
@@ -115,14 +156,49 @@ impl DebTarget {
 			unpacked_dir,
 			debian_dir: dir,
 			dir_map,
+			orig_dir,
+			force_arch: args.deb_args.force_arch,
+			maintainer,
+			section,
+			priority,
+			no_debhelper: args.deb_args.no_debhelper,
+			compression: args.deb_args.compression,
+			clamp_mtime: args.clamp_mtime,
+			verbosity: args.verbosity,
 		})
 	}
 
+	/// Copies the unpacked directory into a `.orig` directory next to it in
+	/// `output_dir`, unless the package is only going to be built as a binary.
+	fn make_orig_dir(
+		unpacked_dir: &Path,
+		output_dir: &Path,
+		deb_args: &DebArgs,
+		generate: bool,
+		verbosity: Verbosity,
+	) -> Result<Option<PathBuf>> {
+		if deb_args.single || deb_args.binary_only || generate {
+			return Ok(None);
+		}
+		let option = CopyOptions {
+			overwrite: true,
+			..Default::default()
+		};
+		let target = orig_dir(unpacked_dir, output_dir);
+
+		if !target.exists() {
+			mkdir(&target, verbosity)?;
+		}
+		fs_extra::dir::copy(unpacked_dir, &target, &option)?;
+		Ok(Some(target))
+	}
+
 	fn patch(
 		mut info: PackageInfo,
 		unpacked_dir: PathBuf,
 		patch: &Path,
 		debian_dir: PathBuf,
+		verbosity: Verbosity,
 	) -> Result<Self> {
 		let mut data = vec![];
 		let mut unzipped = GzDecoder::new(File::open(patch)?);
@@ -132,7 +208,7 @@ impl DebTarget {
 			.arg("-p1")
 			.cwd(&unpacked_dir)
 			.stdin(data)
-			.log_and_output(None)
+			.log_and_output(verbosity)
 			.wrap_err("Patch error")?;
 
 		// If any .rej file exists, we dun goof'd
@@ -142,7 +218,7 @@ impl DebTarget {
 		for orig in glob::glob("*.orig").unwrap() {
 			std::fs::remove_file(orig?)?;
 		}
-		chmod(debian_dir.join("rules"), 0o755)?;
+		chmod(debian_dir.join("rules"), 0o755, verbosity)?;
 
 		if let Ok(changelog) = File::open(debian_dir.join("changelog")) {
 			let mut changelog = BufReader::new(changelog);
@@ -162,9 +238,99 @@ impl DebTarget {
 			unpacked_dir,
 			debian_dir,
 			dir_map: HashMap::new(),
+			orig_dir: None,
+			force_arch: false,
+			// Patched packages are always built with debhelper.
+			maintainer: String::new(),
+			section: String::new(),
+			priority: String::new(),
+			no_debhelper: false,
+			compression: None,
+			clamp_mtime: None,
+			verbosity,
 		})
 	}
-	fn sanitize_info(info: &mut PackageInfo) -> Result<()> {
+
+	/// Assembles the package from the unpacked directory and the generated
+	/// control files, without debhelper or `dpkg-deb`.
+	///
+	/// None of debhelper's other steps, like stripping binaries or working
+	/// out shared library dependencies, are taken.
+	fn build_without_debhelper(&self, output_dir: &Path) -> Result<PathBuf> {
+		let compression = self.compression.unwrap_or(DebCompression::Gzip);
+		let size =
+			installed_size(&self.unpacked_dir)?.saturating_sub(installed_size(&self.debian_dir)?);
+
+		let mut control = tar::Builder::new(vec![]);
+		let control_file = binary_control(self, size)?;
+		append_control_file(&mut control, "control", control_file.as_bytes(), 0o644)?;
+
+		// debhelper would list conffiles in /etc on its own, so they aren't
+		// in the generated conffiles file.
+		let mut conffiles = String::new();
+		for conffile in &self.info.conffiles {
+			let path = self
+				.unpacked_dir
+				.join(conffile.strip_prefix("/").unwrap_or(conffile));
+			if path.is_file() {
+				writeln!(conffiles, "{}", conffile.display())?;
+			}
+		}
+		if !conffiles.is_empty() {
+			append_control_file(&mut control, "conffiles", conffiles.as_bytes(), 0o644)?;
+		}
+		let md5sums = md5sums(&self.unpacked_dir)?;
+		append_control_file(&mut control, "md5sums", md5sums.as_bytes(), 0o644)?;
+		for script in Script::ALL {
+			if let Ok(data) = std::fs::read(self.debian_dir.join(script.deb_name())) {
+				append_control_file(&mut control, script.deb_name(), &data, 0o755)?;
+			}
+		}
+
+		let mut data = tar::Builder::new(vec![]);
+		let mut entries = std::fs::read_dir(&self.unpacked_dir)?.collect::<Result<Vec<_>, _>>()?;
+		entries.retain(|e| e.file_name() != "debian");
+		entries.sort_by_key(DirEntry::file_name);
+		let mut inodes = HashMap::new();
+		for entry in entries {
+			let name = Path::new(".").join(entry.file_name());
+			append_entry(
+				&mut data,
+				&entry.path(),
+				&name,
+				self.clamp_mtime,
+				&mut inodes,
+			)?;
+		}
+
+		let ext = compression.extension();
+		let members = [
+			("debian-binary".to_owned(), b"2.0\n".to_vec()),
+			(
+				format!("control.tar{ext}"),
+				compression.compress(control.into_inner()?)?,
+			),
+			(
+				format!("data.tar{ext}"),
+				compression.compress(data.into_inner()?)?,
+			),
+		];
+
+		let dest = output_dir.join(file_name(&self.info));
+		let mut deb = ar::Builder::new(File::create(&dest)?);
+		for (member, data) in members {
+			let mut header = ar::Header::new(member.into_bytes(), data.len() as u64);
+			header.set_mode(0o100_644);
+			deb.append(&header, data.as_slice())?;
+		}
+		Ok(dest)
+	}
+
+	pub(crate) fn sanitize_info(
+		info: &mut PackageInfo,
+		conversion_notice: bool,
+		keep_release: bool,
+	) -> Result<()> {
 		// Version
 
 		// filter out some characters not allowed in debian versions
@@ -186,11 +352,22 @@ impl DebTarget {
 		};
 
 		// Release
-		// Make sure the release contains digits.
-		if info.release.parse::<u32>().is_err() {
+		if keep_release {
+			// Hyphens and colons are only allowed in the upstream version.
+			info.release = info
+				.release
+				.chars()
+				.filter(|&c| valid_version_characters(c) && !matches!(c, '-' | ':'))
+				.collect();
+		} else if info.release.parse::<u32>().is_err() {
+			// Make sure the release contains digits.
 			info.release.push_str("1");
 		}
 
+		// Summary
+		// The summary is the synopsis line of the description field, so it can't span lines.
+		info.summary = single_line(&info.summary);
+
 		// Description
 
 		let mut desc = String::new();
@@ -204,15 +381,20 @@ impl DebTarget {
 		}
 		// remove leading blank lines
 		let mut desc = String::from(desc.trim_start_matches('\n'));
-		if !desc.is_empty() {
-			desc.push_str(" .\n");
+		if conversion_notice {
+			if !desc.is_empty() {
+				desc.push_str(" .\n");
+			}
+			write!(
+				desc,
+				" (Converted from a {} package by xenomorph version {}.)",
+				info.original_format,
+				env!("CARGO_PKG_VERSION")
+			)?;
+		} else if let Some(stripped) = desc.strip_suffix('\n') {
+			// The last line is left without a newline, as with the notice.
+			desc.truncate(stripped.len());
 		}
-		write!(
-			desc,
-			" (Converted from a {} package by xenomorph version {}.)",
-			info.original_format,
-			env!("CARGO_PKG_VERSION")
-		)?;
 
 		info.description = desc;
 
@@ -232,7 +414,7 @@ impl TargetPackage for DebTarget {
 				Exec::cmd("install")
 					.arg("-d")
 					.arg(dir_base)
-					.log_and_spawn(None)?;
+					.log_and_spawn(self.verbosity)?;
 
 				fs_extra::dir::move_dir(&prefixed_new_dir, &prefixed_old_dir, &CopyOptions::new())?;
 				if prefixed_new_dir.is_dir() {
@@ -241,39 +423,57 @@ impl TargetPackage for DebTarget {
 			}
 		}
 		std::fs::remove_dir_all(&self.debian_dir)?;
+		if let Some(orig_dir) = &self.orig_dir {
+			if orig_dir.exists() {
+				std::fs::remove_dir_all(orig_dir)?;
+			}
+		}
 		Ok(())
 	}
 
-	fn build(&mut self) -> Result<PathBuf> {
-		let PackageInfo {
-			arch,
-			name,
-			version,
-			release,
-			..
-		} = &self.info;
+	fn build(&mut self, output_dir: &Path) -> Result<PathBuf> {
+		let arch = &self.info.arch;
 
 		// Detect architecture mismatch and abort with a comprehensible error message.
-		if arch != "all"
-			&& !Exec::cmd("dpkg-architecture")
+		let arch_matches = if arch == "all" {
+			true
+		} else if self.no_debhelper {
+			arch == super::host_arch()
+		} else {
+			Exec::cmd("dpkg-architecture")
 				.arg("-i")
 				.arg(arch)
-				.log_and_output_without_checking(None)
+				.log_and_output_without_checking(self.verbosity)
 				.wrap_err("dpkg-architecture not found - have you installed dpkg-dev?")?
 				.success()
-		{
-			bail!(
-				"{} is for architecture {}; the package cannot be built on this system",
+		};
+		if !arch_matches {
+			if !self.force_arch {
+				bail!(
+					"{} is for architecture {}; the package cannot be built on this system",
+					self.info.file.display(),
+					arch
+				);
+			}
+			eprintln!(
+				"Warning: {} is for architecture {arch}, which does not match this system.",
 				self.info.file.display(),
-				arch
 			);
+			eprintln!("Warning: Building anyway, but the package's architecture may not match its contents.");
+		}
+		if self.no_debhelper {
+			return self.build_without_debhelper(output_dir);
 		}
 
-		let log = Exec::cmd("debian/rules")
+		let mut rules = Exec::cmd("debian/rules")
 			.cwd(&self.unpacked_dir)
 			.arg("binary")
-			.stderr(Redirection::Merge)
-			.log_and_output_without_checking(None)?;
+			.stderr(Redirection::Merge);
+		if self.force_arch && arch != "all" {
+			// Otherwise, dpkg-gencontrol refuses to build for another architecture.
+			rules = rules.env("DEB_HOST_ARCH", arch);
+		}
+		let log = rules.log_and_output_without_checking(self.verbosity)?;
 		if !log.success() {
 			if log.stderr.is_empty() {
 				bail!("Package build failed; could not run generated debian/rules file.");
@@ -284,8 +484,13 @@ impl TargetPackage for DebTarget {
 			);
 		}
 
-		let path = format!("{name}_{version}-{release}_{arch}.deb");
-		Ok(PathBuf::from(path))
+		// dh_builddeb places the package next to the unpacked directory.
+		let path = file_name(&self.info);
+		let built = self.unpacked_dir.with_file_name(&path);
+		let dest = output_dir.join(&path);
+		move_file(&built, &dest)
+			.wrap_err_with(|| format!("unable to move package to {}", dest.display()))?;
+		Ok(dest)
 	}
 	fn test(&mut self, file_name: &Path) -> Result<Vec<String>> {
 		let Ok(lintian) = which::which("lintian") else {
@@ -294,14 +499,12 @@ impl TargetPackage for DebTarget {
 
 		let output = Exec::cmd(lintian)
 			.arg(file_name)
-			.log_and_output(None)?
+			.log_and_output(self.verbosity)?
 			.stdout;
 
 		let strings = output
 			.lines()
 			.filter_map(|s| s.ok())
-			// Lintian doesn't know about our custom section
-			.filter(|s| !s.contains("unknown-section xenomorph"))
 			.map(|s| s.trim().to_owned())
 			.collect();
 
@@ -312,60 +515,98 @@ impl TargetPackage for DebTarget {
 struct DebWriter {
 	dir: PathBuf,
 	info: PackageInfo,
-	realname: String,
-	email: String,
+	/// Who signs the changelog, in the form of `Name <email>`.
+	converter: String,
+	maintainer: String,
+	section: String,
+	priority: String,
 	date: String,
 }
 impl DebWriter {
-	fn new(dir: PathBuf, info: PackageInfo) -> Result<Self> {
-		let realname = whoami::realname();
-		let email = fetch_email_address();
-		let date = OffsetDateTime::now_local()
-			.unwrap_or_else(|_| OffsetDateTime::now_utc())
-			.format(&Rfc2822)?;
+	fn new(dir: PathBuf, info: PackageInfo, maintainer: Option<&str>) -> Result<Self> {
+		let converter = match maintainer {
+			Some(maintainer) => maintainer.to_owned(),
+			None => format!("{} <{}>", whoami::realname(), fetch_email_address()?),
+		};
+		// Otherwise, prefer the original package's maintainer, if it has one.
+		let maintainer = match maintainer {
+			Some(maintainer) => single_line(maintainer),
+			None if info.maintainer.trim().is_empty() => converter.clone(),
+			None => single_line(&info.maintainer),
+		};
+		let date = fetch_build_date()?.format(&Rfc2822)?;
+		let section = deb_section(&info);
 
 		Ok(Self {
 			dir,
 			info,
-			realname,
-			email,
+			converter,
+			maintainer,
+			section,
+			priority: "optional".into(),
 			date,
 		})
 	}
 
-	fn write_changelog(&mut self) -> Result<()> {
+	fn write_changelog(
+		&mut self,
+		conversion_notice: bool,
+		changelog_entry: Option<&str>,
+	) -> Result<()> {
 		let Self {
 			dir,
 			info,
-			realname,
-			email,
+			converter,
 			date,
+			..
 		} = self;
 		let PackageInfo {
 			name,
 			version,
+			epoch,
 			release,
 			original_format,
 			changelog: changelog_text,
+			changelog_entries,
 			..
 		} = info;
+		let epoch = epoch.map(|e| format!("{e}:")).unwrap_or_default();
+		let version = format!("{epoch}{version}-{release}");
 
 		dir.push("changelog");
 		let mut file = File::create(&dir)?;
 
-		#[rustfmt::skip]
-		writeln!(
-			file,
-r#"{name} ({version}-{release}) experimental; urgency=low
-
-  * Converted from {original_format} format to .deb by xenomorph version {xenomorph_version}
+		writeln!(file, "{name} ({version}) experimental; urgency=low\n")?;
+		if conversion_notice {
+			writeln!(
+				file,
+				"  * Converted from {original_format} format to .deb by xenomorph version {}",
+				env!("CARGO_PKG_VERSION")
+			)?;
+		}
+		if let Some(entry) = changelog_entry {
+			// Continuation lines are indented to line up with the bullet's text.
+			let mut lines = entry.lines().map(str::trim).filter(|l| !l.is_empty());
+			if let Some(first) = lines.next() {
+				writeln!(file, "  * {first}")?;
+			}
+			for line in lines {
+				writeln!(file, "    {line}")?;
+			}
+		}
+		if conversion_notice || changelog_entry.is_some() {
+			writeln!(file)?;
+		}
 
-  {changelog_text}
+		// Fall back to the raw changelog if it couldn't be parsed into entries.
+		if changelog_entries.is_empty() && !changelog_text.trim().is_empty() {
+			writeln!(file, "  {changelog_text}\n")?;
+		}
+		writeln!(file, " -- {converter}  {date}")?;
 
-  -- {realname} <{email}>  {date}
-"#,
-			xenomorph_version = env!("CARGO_PKG_VERSION")
-		)?;
+		for entry in changelog_entries.iter() {
+			write!(file, "\n{}", changelog_stanza(name, &version, entry)?)?;
+		}
 
 		dir.pop();
 		Ok(())
@@ -375,42 +616,61 @@ r#"{name} ({version}-{release}) experimental; urgency=low
 		let Self {
 			dir,
 			info,
-			realname,
-			email,
+			maintainer,
+			section,
+			priority,
 			..
 		} = self;
 		let PackageInfo {
 			name,
 			arch,
-			dependencies: depends,
+			multi_arch,
 			summary,
 			description,
+			url,
 			..
-		} = info;
+		} = &*info;
 
 		dir.push("control");
 		let mut file = File::create(&dir)?;
 
+		#[rustfmt::skip]
+		writeln!(
+			file,
+r"Source: {name}
+Section: {section}
+Priority: {priority}
+Maintainer: {maintainer}"
+		)?;
+		if !url.is_empty() {
+			writeln!(file, "Homepage: {url}")?;
+		}
 		#[rustfmt::skip]
 		write!(
 			file,
-r#"Source: {name}
-Section: xenomorph
-Priority: extra
-Maintainer: {realname} <{email}>
-
+r#"
 Package: {name}
 Architecture: {arch}
 Depends: ${{shlibs:Depends}}"#
 	)?;
-		for dep in depends {
+		for dep in depends(info) {
 			write!(file, ", {dep}")?;
 		}
+		writeln!(file)?;
+		if let Some(multi_arch) = multi_arch {
+			writeln!(file, "Multi-Arch: {}", multi_arch.deb_value())?;
+		}
+
+		for (field, relations) in relationship_fields(info) {
+			if !relations.is_empty() {
+				writeln!(file, "{field}: {}", relations.join(", "))?;
+			}
+		}
+
 		#[rustfmt::skip]
 		writeln!(
 			file,
-r#"
-Description: {summary}
+r#"Description: {summary}
 {description}
 "#,
 		)?;
@@ -450,7 +710,9 @@ Information from the binary package:
 		Ok(())
 	}
 
-	fn write_conffiles(&mut self) -> Result<()> {
+	/// Writes the conffiles that debhelper won't find on its own, or all of
+	/// them if `explicit` is set.
+	fn write_conffiles(&mut self, explicit: bool) -> Result<()> {
 		self.dir.push("conffiles");
 
 		let mut conffiles = self
@@ -458,7 +720,7 @@ Information from the binary package:
 			.conffiles
 			.iter()
 			// `debhelper` takes care of files in /etc.
-			.filter(|s| !s.starts_with("/etc"))
+			.filter(|s| explicit || !s.starts_with("/etc"))
 			.peekable();
 
 		if conffiles.peek().is_some() {
@@ -482,9 +744,31 @@ Information from the binary package:
 		Ok(())
 	}
 
-	fn write_rules(&mut self, fix_perms: bool) -> Result<()> {
+	fn write_source_format(&mut self, single: bool, verbosity: Verbosity) -> Result<()> {
+		// Without a .orig directory to diff against, the package has to stay 1.0.
+		let format = if single { "1.0" } else { "3.0 (quilt)" };
+
+		self.dir.push("source");
+		if !self.dir.exists() {
+			mkdir(&self.dir, verbosity)?;
+		}
+		self.dir.push("format");
+		std::fs::write(&self.dir, format!("{format}\n"))?;
+
+		self.dir.pop();
+		self.dir.pop();
+		Ok(())
+	}
+
+	fn write_rules(&mut self, deb_args: &DebArgs) -> Result<()> {
 		self.dir.push("rules");
 
+		let builddeb_args = deb_args
+			.compression
+			.map(|c| format!(" -- -Z{c}"))
+			.unwrap_or_default();
+		let comment_out = |enabled| if enabled { "" } else { "#" };
+
 		let mut file = File::options()
 			.write(true)
 			.create(true)
@@ -527,21 +811,24 @@ binary-arch: build
 #
 
 
-# This has been known to break on some wacky binaries.
-#	dh_strip
-	dh_compress
-{}	dh_fixperms
+# Stripping has been known to break on some wacky binaries.
+# dh_strip itself does nothing if DEB_BUILD_OPTIONS contains nostrip.
+{strip}	dh_strip
+{compress}	dh_compress
+{fixperms}	dh_fixperms
 	dh_makeshlibs
 	dh_installdeb
 	-dh_shlibdeps
 	dh_gencontrol
 	dh_md5sums
-	dh_builddeb
+	dh_builddeb{builddeb_args}
 
 binary: binary-indep binary-arch
 .PHONY: build clean binary-indep binary-arch binary
 "#,
-			if fix_perms { "" } else { "#" }
+			strip = comment_out(deb_args.strip),
+			compress = comment_out(!deb_args.no_compress),
+			fixperms = comment_out(deb_args.fixperms),
 		)?;
 
 		self.dir.pop();
@@ -579,9 +866,13 @@ binary: binary-indep binary-arch
 		Ok(())
 	}
 	fn patch_postinst(&self, old: &mut String) {
-		let PackageInfo { file_info, .. } = &self.info;
+		let PackageInfo {
+			file_info,
+			capabilities,
+			..
+		} = &self.info;
 
-		if file_info.is_empty() {
+		if file_info.is_empty() && capabilities.is_empty() {
 			return;
 		}
 
@@ -595,7 +886,7 @@ binary: binary-indep binary-arch
 
 		if let Some(s) = first_line.strip_prefix("#!") {
 			let s = s.trim_start();
-			if let "/bin/bash" | "/bin/sh" = s {
+			if !matches!(s, "/bin/bash" | "/bin/sh") {
 				eprintln!("warning: unable to add ownership fixup code to postinst as the postinst is not a shell script!");
 				return;
 			}
@@ -603,48 +894,870 @@ binary: binary-indep binary-arch
 
 		let mut injection = String::from("\n# xenomorph added permissions fixup code");
 
-		for (file, file_info) in file_info {
+		// Sorted, so that the same package always gets the same postinst.
+		let files: BTreeSet<_> = file_info.keys().chain(capabilities.keys()).collect();
+		for file in files {
 			// no single quotes in single quotes...
 			let escaped_file = file.to_string_lossy().replace('\'', r#"'"'"'"#);
-			let own_info = &file_info.owner;
-			write!(injection, "\nchown '{own_info}' '{escaped_file}'").unwrap();
+			if let Some(file_info) = file_info.get(file) {
+				let own_info = &file_info.owner;
+				if !own_info.is_empty() {
+					write!(injection, "\nchown '{own_info}' '{escaped_file}'").unwrap();
+				}
 
-			if let Some(mode_info) = file_info.mode {
-				write!(injection, "\nchmod '{mode_info}' '{escaped_file}'").unwrap();
+				if let Some(mode_info) = file_info.mode {
+					write!(injection, "\nchmod '{mode_info:o}' '{escaped_file}'").unwrap();
+				}
+			}
+
+			// Capabilities go last, since chown clears them.
+			if let Some(caps) = capabilities.get(file).and_then(|c| caps::to_text(c)) {
+				write!(injection, "\nsetcap '{caps}' '{escaped_file}'").unwrap();
 			}
 		}
 		old.insert_str(index, &injection);
 	}
 }
 
-fn get_patch(info: &PackageInfo, anypatch: bool, dirs: &[&str]) -> Option<PathBuf> {
-	let mut patches: Vec<_> = dirs
+/// The name of the file the package is built into.
+pub(crate) fn file_name(info: &PackageInfo) -> String {
+	let PackageInfo {
+		name,
+		version,
+		release,
+		arch,
+		..
+	} = info;
+	format!("{name}_{version}-{release}_{arch}.deb")
+}
+
+/// The packages the package depends on, as listed in its `Depends` field.
+fn depends(info: &PackageInfo) -> impl Iterator<Item = String> + '_ {
+	info.dependencies
 		.iter()
-		.flat_map(|dir| {
-			let p = format!(
-				"{}/{}_{}-{}*.diff.gz",
-				dir, info.name, info.version, info.release
-			);
-			glob::glob(&p).unwrap()
-		})
+		.cloned()
+		.chain(info.requires.iter().filter_map(deb_relation))
+}
+
+/// The package's relationships to other packages, other than its dependencies,
+/// along with the control fields they go in.
+fn relationship_fields(info: &PackageInfo) -> [(&'static str, Vec<String>); 5] {
+	let conflicts = info.conflicts.iter().filter_map(deb_relation).collect();
+	// rpm packages provide their own name, which dpkg does implicitly.
+	let provides = info
+		.provides
+		.iter()
+		.filter(|p| !p.name.eq_ignore_ascii_case(&info.name))
+		.filter_map(deb_relation)
 		.collect();
+	[
+		("Recommends", info.recommends.clone()),
+		("Suggests", info.suggests.clone()),
+		("Enhances", info.enhances.clone()),
+		("Conflicts", conflicts),
+		("Provides", provides),
+	]
+}
+
+/// Picks the section of the generated package, carrying over the original
+/// package's section or mapping its rpm group where possible.
+fn deb_section(info: &PackageInfo) -> String {
+	let section = match info.original_format {
+		Format::Deb if info.group != "unknown" => Some(info.group.clone()),
+		Format::Rpm | Format::Lsb => rpm_group_to_deb_section(&info.group),
+		_ => None,
+	};
+	match section {
+		Some(section) if !section.trim().is_empty() => section,
+		_ => "misc".into(),
+	}
+}
+
+/// Writes the control file of the binary package, which `dpkg-gencontrol`
+/// would otherwise make from the generated source package's control file.
+fn binary_control(target: &DebTarget, installed_size: u64) -> Result<String> {
+	let DebTarget {
+		info,
+		maintainer,
+		section,
+		priority,
+		..
+	} = target;
+	let PackageInfo {
+		name,
+		version,
+		epoch,
+		release,
+		arch,
+		multi_arch,
+		summary,
+		description,
+		url,
+		..
+	} = info;
+	let epoch = epoch.map(|e| format!("{e}:")).unwrap_or_default();
+
+	let mut control = String::new();
+	#[rustfmt::skip]
+	writeln!(
+		control,
+r"Package: {name}
+Version: {epoch}{version}-{release}
+Architecture: {arch}
+Maintainer: {maintainer}
+Installed-Size: {installed_size}
+Section: {section}
+Priority: {priority}"
+	)?;
+	if !url.is_empty() {
+		writeln!(control, "Homepage: {url}")?;
+	}
+	if let Some(multi_arch) = multi_arch {
+		writeln!(control, "Multi-Arch: {}", multi_arch.deb_value())?;
+	}
+	let depends: Vec<_> = depends(info).collect();
+	let fields = std::iter::once(("Depends", depends)).chain(relationship_fields(info));
+	for (field, relations) in fields {
+		if !relations.is_empty() {
+			writeln!(control, "{field}: {}", relations.join(", "))?;
+		}
+	}
+	writeln!(control, "Description: {summary}\n{description}")?;
+	Ok(control)
+}
+
+/// Adds a file to the control archive of a package.
+fn append_control_file(
+	control: &mut tar::Builder<Vec<u8>>,
+	name: &str,
+	data: &[u8],
+	mode: u32,
+) -> Result<()> {
+	let mut header = tar::Header::new_gnu();
+	header.set_size(data.len() as u64);
+	header.set_mode(mode);
+	header.set_cksum();
+	control.append_data(&mut header, Path::new(".").join(name), data)?;
+	Ok(())
+}
+
+/// Where the `.orig` copy of the unpacked directory goes in `output_dir`.
+pub(crate) fn orig_dir(unpacked_dir: &Path, output_dir: &Path) -> PathBuf {
+	let mut orig_name = unpacked_dir
+		.file_name()
+		.unwrap_or(unpacked_dir.as_os_str())
+		.to_owned();
+	orig_name.push(".orig");
+	output_dir.join(orig_name)
+}
+
+/// Lists the checksum of every file under `root`, other than the `debian`
+/// directory, as `dh_md5sums` would for `DEBIAN/md5sums`.
+///
+/// Symlinks, including those to directories, aren't followed.
+fn md5sums(root: &Path) -> Result<String> {
+	fn walk(dir: &Path, prefix: &Path, md5sums: &mut String) -> Result<()> {
+		let mut entries = std::fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+		entries.sort_by_key(DirEntry::file_name);
+		for entry in entries {
+			let path = prefix.join(entry.file_name());
+			let file_type = entry.file_type()?;
+
+			if path == Path::new("debian") {
+				continue;
+			}
+			if file_type.is_dir() {
+				walk(&entry.path(), &path, md5sums)?;
+			} else if file_type.is_file() {
+				let mut hasher = Md5::new();
+				std::io::copy(&mut File::open(entry.path())?, &mut hasher)
+					.wrap_err_with(|| format!("Unable to checksum {}", path.display()))?;
+				writeln!(md5sums, "{:x}  {}", hasher.finalize(), path.display())?;
+			}
+		}
+		Ok(())
+	}
+
+	let mut md5sums = String::new();
+	walk(root, Path::new(""), &mut md5sums)?;
+	Ok(md5sums)
+}
+
+/// Formats a relation to another package for a Debian control field.
+///
+/// Returns `None` if the relation's name can't be a Debian package name,
+/// as with rpm's file dependencies (`/bin/sh`) and capabilities
+/// (`libc.so.6()(64bit)`).
+fn deb_relation(relation: &Relation) -> Option<String> {
+	let name = relation.name.to_lowercase().replace('_', "-");
+	let valid = name.len() >= 2
+		&& name.starts_with(|c: char| c.is_ascii_alphanumeric())
+		&& name
+			.chars()
+			.all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+	if !valid {
+		return None;
+	}
+
+	let Some(op) = relation.op else {
+		return Some(name);
+	};
+	let op = match op {
+		VersionOp::Less => "<<",
+		VersionOp::LessEqual => "<=",
+		VersionOp::Equal => "=",
+		VersionOp::GreaterEqual => ">=",
+		VersionOp::Greater => ">>",
+	};
+	Some(format!("{name} ({op} {})", relation.version))
+}
+
+/// Joins the lines of `s` with spaces, for use in a single-line control field.
+fn single_line(s: &str) -> String {
+	s.lines()
+		.map(str::trim)
+		.filter(|l| !l.is_empty())
+		.collect::<Vec<_>>()
+		.join(" ")
+}
+
+/// The directories to search for patches in, in order: a directory given
+/// with `--patch`, then those given with `--patch-dir`, then [`PATCH_DIRS`].
+fn patch_dirs(deb_args: &DebArgs) -> Vec<PathBuf> {
+	deb_args
+		.patch
+		.iter()
+		.filter(|p| p.is_dir())
+		.chain(&deb_args.patch_dir)
+		.cloned()
+		.chain(PATCH_DIRS.iter().map(PathBuf::from))
+		.collect()
+}
+
+fn get_patch(info: &PackageInfo, anypatch: bool, dirs: &[PathBuf]) -> Option<PathBuf> {
+	let find = |pattern: String| {
+		dirs.iter().flat_map(move |dir| {
+			let p = dir.join(&pattern);
+			glob::glob(&p.to_string_lossy()).into_iter().flatten()
+		})
+	};
+	let mut patches: Vec<_> = find(format!(
+		"{}_{}-{}*.diff.gz",
+		info.name, info.version, info.release
+	))
+	.collect();
 
 	if patches.is_empty() {
 		// Try not matching the release, see if that helps.
-		patches.extend(dirs.iter().flat_map(|dir| {
-			let p = format!("{dir}/{}_{}*.diff.gz", info.name, info.version);
-			glob::glob(&p).unwrap()
-		}));
+		patches.extend(find(format!("{}_{}*.diff.gz", info.name, info.version)));
 
 		if !patches.is_empty() && anypatch {
 			// Fall back to anything that matches the name.
-			patches.extend(dirs.iter().flat_map(|dir| {
-				let p = format!("{dir}/{}_*.diff.gz", info.name);
-				glob::glob(&p).unwrap()
-			}));
+			patches.extend(find(format!("{}_*.diff.gz", info.name)));
 		}
 	}
 
 	// just get the first one
 	patches.into_iter().find_map(|p| p.ok())
 }
+
+/// Formats an entry from the original package's changelog as a Debian changelog stanza.
+fn changelog_stanza(name: &str, version: &str, entry: &ChangelogEntry) -> Result<String> {
+	// rpm changelog authors often end with the version the entry is for,
+	// as in `Leah Amelia Chen <hi@pluie.me> - 0.1.0-1`.
+	let (author, version) = match entry.author.rsplit_once(" - ") {
+		Some((author, version)) if !version.trim().contains(char::is_whitespace) => {
+			(author.trim(), version.trim())
+		}
+		_ => (entry.author.trim(), version),
+	};
+
+	let mut stanza = format!("{name} ({version}) experimental; urgency=low\n\n");
+	for line in entry.text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+		if let Some(item) = line.strip_prefix('-').or_else(|| line.strip_prefix('*')) {
+			writeln!(stanza, "  * {}", item.trim_start())?;
+		} else {
+			writeln!(stanza, "    {line}")?;
+		}
+	}
+	let date = entry.date.format(&Rfc2822)?;
+	writeln!(stanza, "\n -- {author}  {date}")?;
+
+	Ok(stanza)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{collections::HashMap, path::PathBuf};
+
+	use time::OffsetDateTime;
+
+	use super::{deb_relation, file_name, get_patch, md5sums, patch_dirs, DebTarget, DebWriter};
+	use crate::{
+		deb::source::{read_control, DebArchive},
+		override_version,
+		util::{DebArgs, Verbosity},
+		ChangelogEntry, FileInfo, Format, PackageInfo, Relation, Script, TargetPackage, VersionOp,
+	};
+
+	#[test]
+	fn test_sanitize_description() -> eyre::Result<()> {
+		let info = PackageInfo {
+			version: "1.0".into(),
+			release: "1".into(),
+			description: "Shapeshift between package formats.\n\nWritten in Rust.".into(),
+			..Default::default()
+		};
+
+		let mut with_notice = info.clone();
+		DebTarget::sanitize_info(&mut with_notice, true, false)?;
+		assert_eq!(
+			with_notice.description,
+			format!(
+				" Shapeshift between package formats.\n .\n Written in Rust.\n .\n \
+				(Converted from a deb package by xenomorph version {}.)",
+				env!("CARGO_PKG_VERSION")
+			)
+		);
+
+		let mut without_notice = info;
+		DebTarget::sanitize_info(&mut without_notice, false, false)?;
+		assert_eq!(
+			without_notice.description,
+			" Shapeshift between package formats.\n .\n Written in Rust."
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_override_version() -> eyre::Result<()> {
+		let mut info = PackageInfo {
+			name: "xenomorph".into(),
+			version: "0.1.0".into(),
+			release: "2".into(),
+			arch: "amd64".into(),
+			..Default::default()
+		};
+		override_version(&mut info, Some("2024.1_beta"), Some("internal"));
+		DebTarget::sanitize_info(&mut info, true, false)?;
+		// Underscores aren't allowed in Debian versions, and releases need digits.
+		assert_eq!(file_name(&info), "xenomorph_2024.1beta-internal1_amd64.deb");
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_keep_release() -> eyre::Result<()> {
+		let mut info = PackageInfo {
+			name: "xenomorph".into(),
+			version: "0.1.0".into(),
+			release: "el_7.beta".into(),
+			arch: "amd64".into(),
+			..Default::default()
+		};
+		DebTarget::sanitize_info(&mut info, true, true)?;
+		// Nothing is added, but the underscore still has to go.
+		assert_eq!(file_name(&info), "xenomorph_0.1.0-el7.beta_amd64.deb");
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_make_orig_dir() -> eyre::Result<()> {
+		let dir = tempfile::tempdir()?;
+		let unpacked_dir = dir.path().join("xenomorph-0.1.0");
+		let output_dir = dir.path().join("out");
+		std::fs::create_dir_all(unpacked_dir.join("usr/bin"))?;
+		std::fs::write(unpacked_dir.join("usr/bin/xenomorph"), "")?;
+		std::fs::create_dir(&output_dir)?;
+
+		let mut deb_args = DebArgs {
+			patch: None,
+			patch_dir: vec![],
+			nopatch: false,
+			anypatch: false,
+			single: false,
+			binary_only: true,
+			fixperms: false,
+			compression: None,
+			strip: false,
+			no_compress: false,
+			force_arch: false,
+			no_debhelper: false,
+			explicit_conffiles: false,
+		};
+		let orig_dir = DebTarget::make_orig_dir(
+			&unpacked_dir,
+			&output_dir,
+			&deb_args,
+			false,
+			Verbosity::Normal,
+		)?;
+		assert_eq!(orig_dir, None);
+		assert_eq!(std::fs::read_dir(&output_dir)?.count(), 0);
+
+		deb_args.binary_only = false;
+		let orig_dir = DebTarget::make_orig_dir(
+			&unpacked_dir,
+			&output_dir,
+			&deb_args,
+			false,
+			Verbosity::Normal,
+		)?;
+		assert_eq!(orig_dir, Some(output_dir.join("xenomorph-0.1.0.orig")));
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_patch_dir() -> eyre::Result<()> {
+		let dir = tempfile::tempdir()?;
+		let patch_dir = dir.path().join("patches");
+		let unpacked_dir = dir.path().join("xenomorph-0.1.0");
+		let debian_dir = unpacked_dir.join("debian");
+		std::fs::create_dir(&patch_dir)?;
+		std::fs::create_dir_all(&debian_dir)?;
+
+		let diff = b"\
+--- /dev/null
++++ b/debian/rules
+@@ -0,0 +1,2 @@
++#!/usr/bin/make -f
++# Patched in.
+";
+		let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+		std::io::Write::write_all(&mut encoder, diff)?;
+		let patch = patch_dir.join("xenomorph_0.1.0-2.diff.gz");
+		std::fs::write(&patch, encoder.finish()?)?;
+
+		let deb_args = DebArgs {
+			patch: None,
+			patch_dir: vec![patch_dir],
+			nopatch: false,
+			anypatch: false,
+			single: false,
+			binary_only: false,
+			fixperms: false,
+			compression: None,
+			strip: false,
+			no_compress: false,
+			force_arch: false,
+			no_debhelper: false,
+			explicit_conffiles: false,
+		};
+		let info = PackageInfo {
+			name: "xenomorph".into(),
+			version: "0.1.0".into(),
+			release: "2".into(),
+			..Default::default()
+		};
+		let found = get_patch(&info, false, &patch_dirs(&deb_args));
+		assert_eq!(found.as_ref(), Some(&patch));
+
+		DebTarget::patch(
+			info,
+			unpacked_dir,
+			&patch,
+			debian_dir.clone(),
+			Verbosity::Normal,
+		)?;
+		assert_eq!(
+			std::fs::read_to_string(debian_dir.join("rules"))?,
+			"#!/usr/bin/make -f\n# Patched in.\n"
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_write_control_homepage() -> eyre::Result<()> {
+		let dir = tempfile::tempdir()?;
+		let info = PackageInfo {
+			name: "xenomorph".into(),
+			arch: "amd64".into(),
+			summary: "Shapeshift between package formats".into(),
+			url: "https://github.com/pluiedev/xenomorph".into(),
+			..Default::default()
+		};
+		let mut writer = DebWriter::new(dir.path().to_owned(), info, Some("Leah <hi@pluie.me>"))?;
+
+		writer.write_control()?;
+		let control = std::fs::read_to_string(dir.path().join("control"))?;
+		assert!(control.starts_with(
+			"\
+Source: xenomorph
+Section: misc
+Priority: optional
+Maintainer: Leah <hi@pluie.me>
+Homepage: https://github.com/pluiedev/xenomorph
+
+Package: xenomorph
+"
+		));
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_write_control_section() -> eyre::Result<()> {
+		let dir = tempfile::tempdir()?;
+		let section_of = |original_format, group: &str| -> eyre::Result<String> {
+			let info = PackageInfo {
+				name: "xenomorph".into(),
+				arch: "amd64".into(),
+				group: group.into(),
+				original_format,
+				..Default::default()
+			};
+			let mut writer =
+				DebWriter::new(dir.path().to_owned(), info, Some("Leah <hi@pluie.me>"))?;
+			writer.write_control()?;
+			let control = std::fs::read_to_string(dir.path().join("control"))?;
+			let section = control.lines().find_map(|l| l.strip_prefix("Section: "));
+			Ok(section.unwrap_or_default().to_owned())
+		};
+
+		assert_eq!(section_of(Format::Deb, "utils")?, "utils");
+		assert_eq!(section_of(Format::Rpm, "Applications/Internet")?, "net");
+		assert_eq!(section_of(Format::Deb, "unknown")?, "misc");
+		assert_eq!(section_of(Format::Rpm, "Unspecified")?, "misc");
+		assert_eq!(section_of(Format::Tgz, "unknown")?, "misc");
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_write_conffiles() -> eyre::Result<()> {
+		let dir = tempfile::tempdir()?;
+		let info = PackageInfo {
+			name: "xenomorph".into(),
+			conffiles: vec![
+				PathBuf::from("/etc/foo.conf"),
+				PathBuf::from("/opt/xenomorph/bar.conf"),
+			],
+			..Default::default()
+		};
+		let mut writer = DebWriter::new(dir.path().to_owned(), info, Some("Leah <hi@pluie.me>"))?;
+
+		writer.write_conffiles(false)?;
+		let conffiles = std::fs::read_to_string(dir.path().join("conffiles"))?;
+		assert_eq!(conffiles, "/opt/xenomorph/bar.conf\n");
+
+		writer.write_conffiles(true)?;
+		let conffiles = std::fs::read_to_string(dir.path().join("conffiles"))?;
+		assert_eq!(conffiles, "/etc/foo.conf\n/opt/xenomorph/bar.conf\n");
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_write_control_recommends() -> eyre::Result<()> {
+		let dir = tempfile::tempdir()?;
+		let info = PackageInfo {
+			name: "xenomorph".into(),
+			arch: "amd64".into(),
+			recommends: vec!["rpm".into(), "dpkg-dev".into()],
+			..Default::default()
+		};
+		let mut writer = DebWriter::new(dir.path().to_owned(), info, Some("Leah <hi@pluie.me>"))?;
+
+		writer.write_control()?;
+		let control = std::fs::read_to_string(dir.path().join("control"))?;
+		assert!(control.contains("\nRecommends: rpm, dpkg-dev\n"));
+		assert!(!control.contains("Suggests:"));
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_deb_relation() {
+		let relation = |name: &str, op, version: &str| Relation {
+			name: name.into(),
+			op,
+			version: version.into(),
+		};
+
+		let cases = [
+			(VersionOp::Less, "glibc (<< 2.34)"),
+			(VersionOp::LessEqual, "glibc (<= 2.34)"),
+			(VersionOp::Equal, "glibc (= 2.34)"),
+			(VersionOp::GreaterEqual, "glibc (>= 2.34)"),
+			(VersionOp::Greater, "glibc (>> 2.34)"),
+		];
+		for (op, expected) in cases {
+			let dep = deb_relation(&relation("glibc", Some(op), "2.34"));
+			assert_eq!(dep.as_deref(), Some(expected));
+		}
+
+		assert_eq!(
+			deb_relation(&relation("Perl_Foo", None, "")).as_deref(),
+			Some("perl-foo")
+		);
+		// Neither files nor rpm capabilities can be depended on in Debian.
+		assert_eq!(deb_relation(&relation("/bin/sh", None, "")), None);
+		assert_eq!(
+			deb_relation(&relation("libc.so.6()(64bit)", None, "")),
+			None
+		);
+	}
+
+	#[test]
+	fn test_write_control_multiline_summary() -> eyre::Result<()> {
+		let dir = tempfile::tempdir()?;
+		let mut info = PackageInfo {
+			name: "xenomorph".into(),
+			arch: "amd64".into(),
+			version: "1.0".into(),
+			summary: "Shapeshift between\n package formats\n".into(),
+			description: "Written in Rust.".into(),
+			maintainer: "Leah Amelia Chen\n <hi@pluie.me>".into(),
+			..Default::default()
+		};
+		DebTarget::sanitize_info(&mut info, false, false)?;
+		let mut writer = DebWriter::new(dir.path().to_owned(), info, None)?;
+
+		writer.write_control()?;
+		let control = std::fs::read_to_string(dir.path().join("control"))?;
+		assert!(control.contains("\nMaintainer: Leah Amelia Chen <hi@pluie.me>\n"));
+		assert!(control.ends_with(
+			"\
+Description: Shapeshift between package formats
+ Written in Rust.
+
+"
+		));
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_write_source_format() -> eyre::Result<()> {
+		let dir = tempfile::tempdir()?;
+		let mut writer = DebWriter::new(
+			dir.path().to_owned(),
+			PackageInfo::default(),
+			Some("Leah Amelia Chen <hi@pluie.me>"),
+		)?;
+
+		writer.write_source_format(false, Verbosity::Normal)?;
+		let format = std::fs::read_to_string(dir.path().join("source/format"))?;
+		assert_eq!(format, "3.0 (quilt)\n");
+
+		writer.write_source_format(true, Verbosity::Normal)?;
+		let format = std::fs::read_to_string(dir.path().join("source/format"))?;
+		assert_eq!(format, "1.0\n");
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_write_scripts_owner_fixup() -> eyre::Result<()> {
+		let dir = tempfile::tempdir()?;
+		let info = PackageInfo {
+			file_info: HashMap::from([
+				(
+					PathBuf::from("/usr/bin/xenomorph"),
+					FileInfo {
+						owner: "xenomorph:xenomorph".into(),
+						mode: None,
+					},
+				),
+				(
+					PathBuf::from("/usr/bin/xenomorph-helper"),
+					FileInfo {
+						owner: "root:xenomorph".into(),
+						mode: Some(0o4755),
+					},
+				),
+			]),
+			..Default::default()
+		};
+		let mut writer = DebWriter::new(dir.path().to_owned(), info, Some("Leah <hi@pluie.me>"))?;
+
+		writer.write_scripts()?;
+		let postinst = std::fs::read_to_string(dir.path().join("postinst"))?;
+		assert_eq!(
+			postinst,
+			"#!/bin/sh
+# xenomorph added permissions fixup code
+chown 'xenomorph:xenomorph' '/usr/bin/xenomorph'
+chown 'root:xenomorph' '/usr/bin/xenomorph-helper'
+chmod '4755' '/usr/bin/xenomorph-helper'
+"
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_write_scripts_capabilities() -> eyre::Result<()> {
+		let dir = tempfile::tempdir()?;
+		let info = PackageInfo {
+			file_info: HashMap::from([(
+				PathBuf::from("/usr/bin/ping"),
+				FileInfo {
+					owner: "root:root".into(),
+					mode: None,
+				},
+			)]),
+			capabilities: HashMap::from([(
+				PathBuf::from("/usr/bin/ping"),
+				crate::caps::from_text("cap_net_raw=ep").unwrap(),
+			)]),
+			..Default::default()
+		};
+		let mut writer = DebWriter::new(dir.path().to_owned(), info, Some("Leah <hi@pluie.me>"))?;
+
+		writer.write_scripts()?;
+		let postinst = std::fs::read_to_string(dir.path().join("postinst"))?;
+		assert_eq!(
+			postinst,
+			"#!/bin/sh
+# xenomorph added permissions fixup code
+chown 'root:root' '/usr/bin/ping'
+setcap 'cap_net_raw=ep' '/usr/bin/ping'
+"
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_md5sums() -> eyre::Result<()> {
+		let dir = tempfile::tempdir()?;
+		let debian_dir = dir.path().join("debian");
+		std::fs::create_dir_all(dir.path().join("usr/bin"))?;
+		std::fs::create_dir(&debian_dir)?;
+		std::fs::write(dir.path().join("usr/bin/hello"), "hello\n")?;
+		std::fs::write(dir.path().join("usr/empty"), "")?;
+		std::os::unix::fs::symlink("hello", dir.path().join("usr/bin/hi"))?;
+		std::os::unix::fs::symlink("usr/bin", dir.path().join("bin"))?;
+		std::fs::write(debian_dir.join("control"), "Source: hello\n")?;
+
+		assert_eq!(
+			md5sums(dir.path())?,
+			"\
+b1946ac92492d2347c6235b4d2611184  usr/bin/hello
+d41d8cd98f00b204e9800998ecf8427e  usr/empty
+"
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_build_without_debhelper() -> eyre::Result<()> {
+		let dir = tempfile::tempdir()?;
+		let unpacked_dir = dir.path().join("xenomorph-0.1.0");
+		let debian_dir = unpacked_dir.join("debian");
+		std::fs::create_dir_all(unpacked_dir.join("usr/bin"))?;
+		std::fs::create_dir(&debian_dir)?;
+		std::fs::write(unpacked_dir.join("usr/bin/xenomorph"), "ELF")?;
+
+		let info = PackageInfo {
+			name: "xenomorph".into(),
+			version: "0.1.0".into(),
+			release: "2".into(),
+			arch: "all".into(),
+			summary: "Shapeshift between package formats".into(),
+			description: " Written in Rust.".into(),
+			dependencies: vec!["libc6".into()],
+			scripts: HashMap::from([(Script::AfterInstall, "#!/bin/sh\necho hi\n".into())]),
+			..Default::default()
+		};
+		let mut writer =
+			DebWriter::new(debian_dir.clone(), info.clone(), Some("Leah <hi@pluie.me>"))?;
+		writer.write_scripts()?;
+
+		let mut target = DebTarget {
+			info,
+			unpacked_dir: unpacked_dir.clone(),
+			debian_dir,
+			dir_map: HashMap::new(),
+			orig_dir: None,
+			force_arch: false,
+			maintainer: "Leah <hi@pluie.me>".into(),
+			section: "utils".into(),
+			priority: "optional".into(),
+			no_debhelper: true,
+			compression: None,
+			clamp_mtime: None,
+			verbosity: Verbosity::Normal,
+		};
+		let deb = target.build(dir.path())?;
+		assert_eq!(deb, dir.path().join("xenomorph_0.1.0-2_all.deb"));
+
+		let mut archive = DebArchive::extract_manually(std::fs::File::open(&deb)?, false)?;
+		let mut read = PackageInfo::default();
+		read_control(&mut read, &archive.control_files["control"]);
+		archive.data.read_files(&mut read)?;
+		assert_eq!(read.name, "xenomorph");
+		assert_eq!(read.version, "0.1.0");
+		assert_eq!(read.release, "2");
+		assert_eq!(read.dependencies, ["libc6"]);
+		assert_eq!(read.group, "utils");
+		assert_eq!(
+			read.files,
+			["/usr", "/usr/bin", "/usr/bin/xenomorph"].map(PathBuf::from)
+		);
+		assert_eq!(read.dirs, ["/usr", "/usr/bin"].map(PathBuf::from));
+		assert_eq!(archive.control_files["postinst"], "#!/bin/sh\necho hi\n");
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_write_changelog_entry() -> eyre::Result<()> {
+		let dir = tempfile::tempdir()?;
+		let info = PackageInfo {
+			name: "xenomorph".into(),
+			version: "0.1.0".into(),
+			release: "2".into(),
+			..Default::default()
+		};
+		let mut writer = DebWriter::new(dir.path().to_owned(), info, Some("Leah <hi@pluie.me>"))?;
+
+		writer.write_changelog(
+			true,
+			Some("Rebuilt against the new libfoo.\nNo other changes."),
+		)?;
+		let changelog = std::fs::read_to_string(dir.path().join("changelog"))?;
+		assert!(changelog.starts_with(&format!(
+			"\
+xenomorph (0.1.0-2) experimental; urgency=low
+
+  * Converted from deb format to .deb by xenomorph version {}
+  * Rebuilt against the new libfoo.
+    No other changes.
+
+ -- Leah <hi@pluie.me>  ",
+			env!("CARGO_PKG_VERSION")
+		)));
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_changelog_stanza() -> eyre::Result<()> {
+		let entry = ChangelogEntry {
+			author: "Leah Amelia Chen <hi@pluie.me> - 0.1.0-2".into(),
+			date: OffsetDateTime::from_unix_timestamp(1_709_294_400)?,
+			text: "- Fix the frobnicator\n- Add a flux capacitor,\n  which was long overdue".into(),
+		};
+
+		assert_eq!(
+			super::changelog_stanza("xenomorph", "0.1.0-3", &entry)?,
+			"\
+xenomorph (0.1.0-2) experimental; urgency=low
+
+  * Fix the frobnicator
+  * Add a flux capacitor,
+    which was long overdue
+
+ -- Leah Amelia Chen <hi@pluie.me>  Fri, 01 Mar 2024 12:00:00 +0000
+"
+		);
+		Ok(())
+	}
+}
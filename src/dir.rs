@@ -0,0 +1,159 @@
+//! A plain directory holding the package's files, for when the files
+//! are wanted without any packaging around them.
+
+use std::path::{Path, PathBuf};
+
+use eyre::{bail, Context, Result};
+use nix::unistd::{chown, geteuid, Group, User};
+use subprocess::Exec;
+
+use crate::{
+	util::{chmod, ExecExt, Verbosity},
+	Args, FileInfo, PackageInfo, TargetPackage,
+};
+
+#[derive(Debug)]
+pub struct DirTarget {
+	info: PackageInfo,
+	unpacked_dir: PathBuf,
+	verbosity: Verbosity,
+}
+impl DirTarget {
+	#[must_use]
+	pub fn new(info: PackageInfo, unpacked_dir: PathBuf, args: &Args) -> Self {
+		Self {
+			info,
+			unpacked_dir,
+			verbosity: args.verbosity,
+		}
+	}
+
+	/// Gives a file in the copied tree the ownership and mode that couldn't
+	/// be represented in the unpacked tree.
+	fn apply_file_info(file: &Path, file_info: &FileInfo, verbosity: Verbosity) -> Result<()> {
+		let FileInfo { owner, mode } = file_info;
+
+		// Only root can give files away, and the owners may not exist here anyway.
+		if !owner.is_empty() && geteuid().is_root() {
+			let (user, group) = owner.split_once(':').unwrap_or((owner, ""));
+			let uid = match user {
+				"" => None,
+				user => User::from_name(user)?.map(|u| u.uid),
+			};
+			let gid = match group {
+				"" => None,
+				group => Group::from_name(group)?.map(|g| g.gid),
+			};
+			if uid.is_none() && gid.is_none() {
+				eprintln!(
+					"Warning: {owner} does not exist, so {} keeps its owner.",
+					file.display()
+				);
+			} else {
+				chown(file, uid, gid)
+					.wrap_err_with(|| format!("failed chowning {} to {owner}", file.display()))?;
+			}
+		}
+		// Changing the owner clears the setuid bit, so the mode has to come after.
+		if let Some(mode) = mode {
+			chmod(file, *mode, verbosity)?;
+		}
+		Ok(())
+	}
+}
+impl TargetPackage for DirTarget {
+	fn build(&mut self, output_dir: &Path) -> Result<PathBuf> {
+		let dest = output_dir.join(file_name(&self.info));
+		if dest.exists() {
+			bail!("{} already exists", dest.display());
+		}
+
+		// `cp -a` keeps links, modes and (as root) owners intact.
+		Exec::cmd("cp")
+			.arg("-a")
+			.arg(&self.unpacked_dir)
+			.arg(&dest)
+			.log_and_spawn(self.verbosity)
+			.wrap_err_with(|| format!("Unable to copy files to {}", dest.display()))?;
+
+		for (path, file_info) in &self.info.file_info {
+			let file = dest.join(path.strip_prefix("/").unwrap_or(path));
+			// Ghost files are listed, but never unpacked.
+			if file.symlink_metadata().is_ok() {
+				Self::apply_file_info(&file, file_info, self.verbosity)?;
+			}
+		}
+
+		Ok(dest)
+	}
+}
+
+/// The name of the directory the tree is copied to.
+pub(crate) fn file_name(info: &PackageInfo) -> String {
+	format!("{}-{}", info.name, info.version)
+}
+
+/// Directory trees can't be installed, since there's no package manager to
+/// keep track of their files.
+pub fn install(dir: &Path, _dry_run: bool) -> Result<()> {
+	bail!(
+		"{} is a directory tree, not a package, and cannot be installed",
+		dir.display()
+	)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{collections::HashMap, os::unix::fs::PermissionsExt, path::PathBuf};
+
+	use super::DirTarget;
+	use crate::{util::Verbosity, FileInfo, PackageInfo, TargetPackage};
+
+	#[test]
+	fn test_build() -> eyre::Result<()> {
+		let unpacked_dir = tempfile::tempdir()?;
+		let output_dir = tempfile::tempdir()?;
+
+		let bin_dir = unpacked_dir.path().join("usr/bin");
+		std::fs::create_dir_all(&bin_dir)?;
+		std::fs::write(bin_dir.join("xenomorph"), "#!/bin/sh\n")?;
+		std::fs::write(bin_dir.join("xenomorph-helper"), "#!/bin/sh\n")?;
+
+		let info = PackageInfo {
+			name: "xenomorph".into(),
+			version: "0.1.0".into(),
+			file_info: HashMap::from([(
+				PathBuf::from("/usr/bin/xenomorph-helper"),
+				FileInfo {
+					owner: String::new(),
+					mode: Some(0o4755),
+				},
+			)]),
+			..Default::default()
+		};
+		let mut target = DirTarget {
+			info,
+			unpacked_dir: unpacked_dir.path().to_owned(),
+			verbosity: Verbosity::Normal,
+		};
+		let dir = target.build(output_dir.path())?;
+
+		assert_eq!(dir, output_dir.path().join("xenomorph-0.1.0"));
+		assert_eq!(
+			std::fs::read_to_string(dir.join("usr/bin/xenomorph"))?,
+			"#!/bin/sh\n"
+		);
+		let mode = std::fs::metadata(dir.join("usr/bin/xenomorph-helper"))?
+			.permissions()
+			.mode();
+		assert_eq!(mode & 0o7777, 0o4755);
+
+		// Nothing but the package's files is in there.
+		let entries: Vec<_> = std::fs::read_dir(&dir)?
+			.map(|e| e.map(|e| e.file_name()))
+			.collect::<Result<_, _>>()?;
+		assert_eq!(entries, ["usr"]);
+
+		Ok(())
+	}
+}
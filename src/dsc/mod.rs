@@ -0,0 +1,3 @@
+pub use source::DscSource;
+
+pub mod source;
@@ -0,0 +1,363 @@
+use std::{
+	fs::File,
+	io::{BufReader, Read},
+	path::{Path, PathBuf},
+};
+
+use bzip2::read::BzDecoder;
+use eyre::{bail, Context, Result};
+use flate2::read::GzDecoder;
+use liblzma::read::XzDecoder;
+use zstd::stream::read::Decoder as ZstdDecoder;
+
+use crate::{
+	deb::host_arch,
+	util::{make_unpack_work_dir, unpack_archive, Verbosity},
+	Args, Format, PackageInfo, SourcePackage,
+};
+
+/// A Debian source package, described by a `.dsc` file that refers to
+/// the upstream (`.orig.tar.*`) and Debian (`.debian.tar.*`) tarballs.
+///
+/// Since there's no format for source packages to convert into,
+/// these are treated as if they were `.deb` packages. They can't be
+/// converted into `.deb`s, though, since that would need them to be built.
+#[derive(Debug)]
+pub struct DscSource {
+	info: PackageInfo,
+	/// The tarballs referred to by the `.dsc` file, in the order they should be unpacked.
+	tarballs: Vec<PathBuf>,
+	verbosity: Verbosity,
+	/// Where to unpack the package, if not the current directory.
+	build_dir: Option<PathBuf>,
+}
+impl DscSource {
+	/// `.dsc` files are usually signed, but may also start right away
+	/// with the `Format` field.
+	#[must_use]
+	pub fn check_file(file: &Path) -> bool {
+		let Ok(file) = File::open(file) else {
+			return false;
+		};
+		let mut start = Vec::with_capacity(64);
+		if file.take(64).read_to_end(&mut start).is_err() {
+			return false;
+		}
+		start.starts_with(b"-----BEGIN PGP SIGNED MESSAGE-----") || start.starts_with(b"Format:")
+	}
+
+	pub fn new(file: PathBuf, args: &Args) -> Result<Self> {
+		let dsc = std::fs::read_to_string(&file)
+			.wrap_err_with(|| format!("Unable to read {}", file.display()))?;
+
+		let mut info = PackageInfo {
+			file,
+			group: "source".into(),
+			copyright: "unknown".into(),
+			distribution: "Debian".into(),
+			original_format: Format::Deb,
+			binary_info: dsc.clone(),
+			..Default::default()
+		};
+		let (arch, file_names) = parse_dsc(&mut info, &dsc);
+
+		if info.name.is_empty() {
+			bail!(
+				"Error reading {}: source name not found!",
+				info.file.display()
+			);
+		}
+		info.summary = format!("Converted Debian source package {}", info.name);
+		info.description.clone_from(&info.summary);
+
+		info.arch = if let Some(target) = &args.target {
+			target.clone()
+		} else if arch == "all" {
+			arch
+		} else {
+			// Source packages are built for whatever the host is.
+			host_arch().to_owned()
+		};
+
+		// The tarballs are expected to sit right next to the .dsc file.
+		let dir = info.file.parent().unwrap_or(Path::new("."));
+		let mut tarballs = vec![];
+		for name in file_names {
+			let path = dir.join(&name);
+			if !path.exists() {
+				bail!(
+					"{name}, referred to by {}, cannot be found",
+					info.file.display()
+				);
+			}
+			if name.contains(".diff.") {
+				eprintln!("Warning: Ignoring {name}; 1.0-style diffs are not supported.");
+				continue;
+			}
+			tarballs.push(path);
+		}
+		// Unpack the upstream sources first, and the Debian directory on top of them.
+		tarballs.sort_by_key(|p| p.to_string_lossy().contains(".debian.tar."));
+
+		Ok(Self {
+			info,
+			tarballs,
+			verbosity: args.verbosity,
+			build_dir: args.build_dir.clone(),
+		})
+	}
+}
+impl SourcePackage for DscSource {
+	fn info(&self) -> &PackageInfo {
+		&self.info
+	}
+	fn info_mut(&mut self) -> &mut PackageInfo {
+		&mut self.info
+	}
+	fn into_info(self) -> PackageInfo {
+		self.info
+	}
+
+	fn unpack(&mut self) -> Result<PathBuf> {
+		let work_dir = make_unpack_work_dir(&self.info, self.build_dir.as_deref(), self.verbosity)?;
+
+		for tarball in &self.tarballs {
+			unpack_tarball(tarball, &work_dir)
+				.wrap_err_with(|| format!("Unpacking of {} failed", tarball.display()))?;
+		}
+		list_files(&work_dir, Path::new("/"), &mut self.info.files)?;
+
+		Ok(work_dir)
+	}
+}
+
+/// Unpacks one of the tarballs of the source package into `work_dir`.
+fn unpack_tarball(tarball: &Path, work_dir: &Path) -> Result<()> {
+	let mut tar = open_tarball(tarball)?;
+	// The Debian tarball only contains `debian/`.
+	if tarball.to_string_lossy().contains(".debian.tar.") {
+		unpack_archive(&mut tar, work_dir)?;
+		return Ok(());
+	}
+
+	// Upstream tarballs put everything under a top-level directory,
+	// which is unpacked on the side, and then moved into place.
+	let staging = tempfile::Builder::new()
+		.prefix(".upstream")
+		.tempdir_in(work_dir)?;
+	unpack_archive(&mut tar, staging.path())?;
+	for top in std::fs::read_dir(staging.path())? {
+		let top = top?;
+		if !top.file_type()?.is_dir() {
+			continue;
+		}
+		for entry in std::fs::read_dir(top.path())? {
+			let entry = entry?;
+			std::fs::rename(entry.path(), work_dir.join(entry.file_name()))?;
+		}
+	}
+	Ok(())
+}
+
+/// Adds everything under `dir` to `files`, as installed under `prefix`.
+fn list_files(dir: &Path, prefix: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+	let mut entries = std::fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+	entries.sort_by_key(|e| e.file_name());
+	for entry in entries {
+		let path = prefix.join(entry.file_name());
+		files.push(path.clone());
+		if entry.file_type()?.is_dir() {
+			list_files(&entry.path(), &path, files)?;
+		}
+	}
+	Ok(())
+}
+
+/// Opens a tarball, decompressing it according to its extension.
+fn open_tarball(path: &Path) -> Result<tar::Archive<Box<dyn Read>>> {
+	let file = BufReader::new(File::open(path)?);
+	let reader: Box<dyn Read> = match path.extension().and_then(|e| e.to_str()) {
+		Some("gz") => Box::new(GzDecoder::new(file)),
+		Some("xz") => Box::new(XzDecoder::new(file)),
+		Some("bz2") => Box::new(BzDecoder::new(file)),
+		Some("zst") => Box::new(ZstdDecoder::new(file)?),
+		Some("tar") => Box::new(file),
+		_ => bail!("Unknown compression for tarball {}", path.display()),
+	};
+	Ok(tar::Archive::new(reader))
+}
+
+/// Reads the fields of a `.dsc` file into the package info.
+///
+/// Returns the source architecture, along with the names of all files listed in it.
+fn parse_dsc(info: &mut PackageInfo, dsc: &str) -> (String, Vec<String>) {
+	let mut arch = String::new();
+	let mut files = vec![];
+
+	let mut lines = dsc.lines();
+	// Skip the armor header of signed files.
+	if dsc.starts_with("-----BEGIN PGP SIGNED MESSAGE-----") {
+		lines
+			.by_ref()
+			.take_while(|l| !l.trim().is_empty())
+			.for_each(drop);
+	}
+
+	let mut field = String::new();
+	for line in lines {
+		if line.starts_with("-----BEGIN PGP SIGNATURE-----") {
+			break;
+		}
+		if let Some(cont) = line.strip_prefix([' ', '\t']) {
+			// Continuation lines. The only multiline field we care about is `Files`.
+			if field == "files" {
+				if let Some(name) = cont.split_whitespace().nth(2) {
+					files.push(name.to_owned());
+				}
+			} else if field == "build-depends" {
+				push_build_dependencies(info, cont);
+			}
+			continue;
+		}
+		let Some((name, value)) = line.split_once(':') else {
+			continue;
+		};
+		field = name.trim().to_ascii_lowercase();
+		let value = value.trim();
+
+		match field.as_str() {
+			"source" => info.name = value.to_owned(),
+			"version" => crate::deb::set_version_and_release(info, value),
+			"maintainer" => info.maintainer = value.to_owned(),
+			"architecture" => arch = value.to_owned(),
+			"build-depends" => push_build_dependencies(info, value),
+			_ => { /* ignore */ }
+		}
+	}
+
+	(arch, files)
+}
+
+fn push_build_dependencies(info: &mut PackageInfo, value: &str) {
+	info.build_dependencies.extend(
+		value
+			.split(',')
+			.map(str::trim)
+			.filter(|s| !s.is_empty())
+			.map(|s| s.to_owned()),
+	);
+}
+
+#[cfg(test)]
+mod tests {
+	use std::path::{Path, PathBuf};
+
+	use flate2::{write::GzEncoder, Compression};
+
+	use super::{list_files, unpack_tarball};
+	use crate::PackageInfo;
+
+	/// Writes a gzipped tarball holding the given symlinks, followed by the given files.
+	fn tarball(path: &Path, files: &[(&str, &str)], links: &[(&str, &str)]) -> eyre::Result<()> {
+		let gz = GzEncoder::new(std::fs::File::create(path)?, Compression::default());
+		let mut tar = tar::Builder::new(gz);
+		for (name, target) in links {
+			let mut header = tar::Header::new_gnu();
+			header.set_entry_type(tar::EntryType::Symlink);
+			tar.append_link(&mut header, name, target)?;
+		}
+		for (name, data) in files {
+			let mut header = tar::Header::new_gnu();
+			header.set_size(data.len() as u64);
+			header.set_mode(0o644);
+			tar.append_data(&mut header, name, data.as_bytes())?;
+		}
+		tar.into_inner()?.finish()?;
+		Ok(())
+	}
+
+	#[test]
+	fn test_unpack_tarball() -> eyre::Result<()> {
+		let dir = tempfile::tempdir()?;
+		let work_dir = dir.path().join("xenomorph-0.1.0");
+		std::fs::create_dir(&work_dir)?;
+
+		// Tarballs don't always have entries for the directories files are in.
+		let orig = dir.path().join("xenomorph_0.1.0.orig.tar.gz");
+		tarball(
+			&orig,
+			&[("xenomorph-0.1.0/src/main.rs", "fn main() {}")],
+			&[],
+		)?;
+		unpack_tarball(&orig, &work_dir)?;
+		let debian = dir.path().join("xenomorph_0.1.0-2.debian.tar.gz");
+		tarball(&debian, &[("debian/control", "Source: xenomorph")], &[])?;
+		unpack_tarball(&debian, &work_dir)?;
+
+		let mut files = vec![];
+		list_files(&work_dir, Path::new("/"), &mut files)?;
+		assert_eq!(
+			files,
+			["/debian", "/debian/control", "/src", "/src/main.rs"].map(PathBuf::from)
+		);
+
+		// Nothing may be unpacked through a symlink pointing out of the tree.
+		let outside = dir.path().join("outside");
+		std::fs::create_dir(&outside)?;
+		let evil = dir.path().join("evil_0.1.0.orig.tar.gz");
+		tarball(
+			&evil,
+			&[("evil-0.1.0/escape/pwned", "")],
+			&[("evil-0.1.0/escape", &outside.to_string_lossy())],
+		)?;
+		assert!(unpack_tarball(&evil, &work_dir).is_err());
+		assert!(!outside.join("pwned").exists());
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_parse_dsc() {
+		let dsc = "\
+-----BEGIN PGP SIGNED MESSAGE-----
+Hash: SHA256
+
+Format: 3.0 (quilt)
+Source: xenomorph
+Binary: xenomorph
+Architecture: any
+Version: 1:0.1.0-2
+Maintainer: Leah Amelia Chen <hi@pluie.me>
+Build-Depends: debhelper-compat (= 13),
+ cargo
+Files:
+ 0123456789abcdef0123456789abcdef 1234 xenomorph_0.1.0.orig.tar.gz
+ fedcba9876543210fedcba9876543210 567 xenomorph_0.1.0-2.debian.tar.xz
+
+-----BEGIN PGP SIGNATURE-----
+
+iQIzBAEBCAAdFiEE
+-----END PGP SIGNATURE-----
+";
+		let mut info = PackageInfo::default();
+		let (arch, files) = super::parse_dsc(&mut info, dsc);
+
+		assert_eq!(info.name, "xenomorph");
+		assert_eq!(info.version, "0.1.0");
+		assert_eq!(info.release, "2");
+		assert_eq!(info.epoch, Some(1));
+		assert_eq!(info.maintainer, "Leah Amelia Chen <hi@pluie.me>");
+		assert_eq!(
+			info.build_dependencies,
+			vec!["debhelper-compat (= 13)", "cargo"]
+		);
+		assert_eq!(arch, "any");
+		assert_eq!(
+			files,
+			vec![
+				"xenomorph_0.1.0.orig.tar.gz",
+				"xenomorph_0.1.0-2.debian.tar.xz"
+			]
+		);
+	}
+}
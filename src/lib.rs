@@ -11,22 +11,35 @@
 
 use std::{
 	collections::HashMap,
-	fmt::Display,
+	fmt::{Display, Write as _},
+	os::unix::fs::PermissionsExt,
 	path::{Path, PathBuf},
+	str::FromStr,
 };
 
+use apk::{ApkSource, ApkTarget};
 use enum_dispatch::enum_dispatch;
-use eyre::{bail, Result};
+use enumflags2::BitFlags;
+use eyre::{bail, Context, Result};
 use pkg::{PkgSource, PkgTarget};
-use util::Args;
+use subprocess::Exec;
+use time::OffsetDateTime;
+use util::{Args, ExecExt, Verbosity};
 
 use deb::{DebSource, DebTarget};
+use dir::DirTarget;
+use dsc::DscSource;
 use lsb::{LsbSource, LsbTarget};
 use rpm::{RpmSource, RpmTarget};
 use tgz::{TgzSource, TgzTarget};
 
+pub mod apk;
+pub mod caps;
 pub mod deb;
+pub mod dir;
+pub mod dsc;
 pub mod lsb;
+pub mod metainfo;
 pub mod pkg;
 pub mod rpm;
 pub mod tgz;
@@ -49,18 +62,26 @@ pub trait SourcePackage {
 
 	/// Increments the release field of the package by the specified bump value.
 	///
-	/// If the release field is not a valid number, then it is set to the bump value.
+	/// Only the trailing number of the release is incremented, so that `1.2ubuntu3`
+	/// becomes `1.2ubuntu4`. If the release doesn't end in a number at all,
+	/// then it is set to the bump value.
 	fn increment_release(&mut self, bump: u32) {
 		let release = &mut self.info_mut().release;
+		*release = bump_release(release, bump);
+	}
+}
 
-		*release = if let Ok(num) = release.parse::<u32>() {
-			(num + bump).to_string()
-		} else {
-			// Perl's string-number addition thing is... cursed.
-			// If a string doesn't parse to a number, then it is treated as 0.
-			// So, we will just set the release to the bump here.
-			bump.to_string()
-		};
+fn bump_release(release: &str, bump: u32) -> String {
+	let prefix = release.trim_end_matches(|c: char| c.is_ascii_digit());
+	let number = &release[prefix.len()..];
+
+	if let Ok(num) = number.parse::<u32>() {
+		format!("{prefix}{}", num.saturating_add(bump))
+	} else {
+		// Perl's string-number addition thing is... cursed.
+		// If a string doesn't parse to a number, then it is treated as 0.
+		// So, we will just set the release to the bump here.
+		bump.to_string()
 	}
 }
 
@@ -74,10 +95,10 @@ pub trait TargetPackage {
 	}
 
 	/// Builds a package from the completed unpacked directory,
-	/// which is then placed in the current directory.
+	/// which is then placed in the given output directory.
 	///
 	/// Returns the path to the built package.
-	fn build(&mut self) -> Result<PathBuf>;
+	fn build(&mut self, output_dir: &Path) -> Result<PathBuf>;
 
 	/// Tests the given package file, and returns the test results as a list of lines.
 	#[allow(unused_variables)]
@@ -94,25 +115,73 @@ pub enum AnySourcePackage {
 	Deb(DebSource),
 	Tgz(TgzSource),
 	Pkg(PkgSource),
+	Apk(ApkSource),
+	Dsc(DscSource),
 }
 impl AnySourcePackage {
 	pub fn new(file: PathBuf, args: &Args) -> Result<Self> {
-		if LsbSource::check_file(&file) {
-			LsbSource::new(file, args).map(Self::Lsb)
-		} else if RpmSource::check_file(&file) {
-			RpmSource::new(file, args).map(Self::Rpm)
-		} else if DebSource::check_file(&file) {
-			DebSource::new(file, args).map(Self::Deb)
-		} else if TgzSource::check_file(&file) {
-			TgzSource::new(file).map(Self::Tgz)
-		} else if PkgSource::check_file(&file) {
-			PkgSource::new(file).map(Self::Pkg)
-		} else {
-			bail!("Unknown type of package, {}", file.display());
+		if let Some(format) = args.from {
+			let name = file.display().to_string();
+			return Self::with_format(format, file, args)
+				.wrap_err_with(|| format!("{name} could not be read as a {format} package"));
+		}
+
+		match detect(&file)? {
+			Some(format) => Self::with_format(format, file, args),
+			None if DscSource::check_file(&file) => DscSource::new(file, args).map(Self::Dsc),
+			None => bail!("Unknown type of package, {}", file.display()),
+		}
+	}
+
+	/// Reads the file as a package of the given format, without checking
+	/// whether it looks like one first.
+	pub fn with_format(format: Format, file: PathBuf, args: &Args) -> Result<Self> {
+		util::preflight(format, true)?;
+		match format {
+			Format::Apk => ApkSource::new(file, args).map(Self::Apk),
+			Format::Deb => DebSource::new(file, args).map(Self::Deb),
+			Format::Dir => bail!("Packages can be converted to directory trees, but not from them"),
+			Format::Lsb => LsbSource::new(file, args).map(Self::Lsb),
+			Format::Pkg => PkgSource::new(file, args).map(Self::Pkg),
+			Format::Rpm => RpmSource::new(file, args).map(Self::Rpm),
+			Format::Tgz => TgzSource::new(file, args).map(Self::Tgz),
 		}
 	}
 }
 
+/// Guesses the format of a package file, from its name and contents,
+/// without reading the whole package.
+///
+/// Returns `None` if the format is unknown, or if the file is a Debian
+/// source package, since those have no [`Format`] of their own.
+#[must_use]
+pub fn detect_format(file: &Path) -> Option<Format> {
+	detect(file).ok().flatten()
+}
+
+fn detect(file: &Path) -> std::io::Result<Option<Format>> {
+	// LSB packages are also rpms, so they have to be checked for first.
+	let format = if LsbSource::check_file(file) {
+		Format::Lsb
+	} else if RpmSource::check_file(file) {
+		Format::Rpm
+	} else if DebSource::check_file(file) {
+		Format::Deb
+	} else if ApkSource::check_file(file) {
+		Format::Apk
+	} else if DscSource::check_file(file) {
+		return Ok(None);
+	} else if TgzSource::check_file(file) {
+		Format::Tgz
+	} else if PkgSource::check_file(file) {
+		Format::Pkg
+	} else {
+		// The file name gives us nothing to go by, so look at its contents.
+		return util::sniff(file);
+	};
+	Ok(Some(format))
+}
+
 #[enum_dispatch(TargetPackage)]
 #[derive(Debug)]
 pub enum AnyTargetPackage {
@@ -121,27 +190,518 @@ pub enum AnyTargetPackage {
 	Deb(DebTarget),
 	Tgz(TgzTarget),
 	Pkg(PkgTarget),
+	Apk(ApkTarget),
+	Dir(DirTarget),
 }
 impl AnyTargetPackage {
+	/// Prepares a package of the given format to be built from `info` and
+	/// the files in `unpacked_dir`.
+	///
+	/// `info` doesn't have to come from a [`SourcePackage`]. Every field of
+	/// [`PackageInfo`] is public, so it can be filled in by hand to package
+	/// a file tree that was prepared some other way:
+	///
+	/// ```
+	/// use bpaf::Parser;
+	/// use xenomorph::{util::args, AnyTargetPackage, Format, PackageInfo, TargetPackage};
+	///
+	/// # fn main() -> eyre::Result<()> {
+	/// let dir = tempfile::tempdir()?;
+	/// let unpacked_dir = dir.path().join("hello-1.0");
+	/// std::fs::create_dir_all(unpacked_dir.join("usr/bin"))?;
+	/// std::fs::write(unpacked_dir.join("usr/bin/hello"), "#!/bin/sh\necho hello\n")?;
+	///
+	/// let info = PackageInfo {
+	///     name: "hello".into(),
+	///     version: "1.0".into(),
+	///     release: "1".into(),
+	///     arch: "all".into(),
+	///     summary: "Says hello".into(),
+	///     files: vec!["/usr/bin/hello".into()],
+	///     ..Default::default()
+	/// };
+	/// // The files to convert are ignored; only the build options matter.
+	/// let args = args().to_options().run_inner(&["hello-1.0"]).unwrap();
+	///
+	/// let mut target = AnyTargetPackage::new(Format::Tgz, info, unpacked_dir, &args)?;
+	/// let package = target.build(dir.path())?;
+	/// target.clean_tree()?;
+	/// assert!(package.is_file());
+	/// # Ok(())
+	/// # }
+	/// ```
 	pub fn new(
 		format: Format,
 		info: PackageInfo,
 		unpacked_dir: PathBuf,
 		args: &Args,
 	) -> Result<Self> {
+		// Nothing is built when only generating the build tree.
+		let builds = !(args.generate || args.deb_args.single);
+		if builds && !(format == Format::Deb && args.deb_args.no_debhelper) {
+			util::preflight(format, false)?;
+		}
 		let target = match format {
-			Format::Lsb => Self::Lsb(LsbTarget::new(info, unpacked_dir)?),
-			Format::Rpm => Self::Rpm(RpmTarget::new(info, unpacked_dir)?),
+			Format::Lsb => Self::Lsb(LsbTarget::new(info, unpacked_dir, args)?),
+			Format::Rpm => Self::Rpm(RpmTarget::new(info, unpacked_dir, args)?),
 			Format::Deb => Self::Deb(DebTarget::new(info, unpacked_dir, args)?),
-			Format::Tgz => Self::Tgz(TgzTarget::new(info, unpacked_dir)?),
-			Format::Pkg => Self::Pkg(PkgTarget::new(info, unpacked_dir)?),
+			Format::Tgz => Self::Tgz(TgzTarget::new(info, unpacked_dir, args)?),
+			Format::Pkg => Self::Pkg(PkgTarget::new(info, unpacked_dir, args)?),
+			Format::Apk => Self::Apk(ApkTarget::new(info, unpacked_dir, args)?),
+			Format::Dir => Self::Dir(DirTarget::new(info, unpacked_dir, args)),
 		};
 		Ok(target)
 	}
 }
 
+/// Opens a package for conversion, applying the script and version
+/// settings from the given arguments to its info.
+pub fn read_package(file: &Path, args: &Args) -> Result<AnySourcePackage> {
+	if !file.try_exists()? {
+		bail!("File \"{}\" not found.", file.display());
+	}
+	let mut pkg = AnySourcePackage::new(file.to_owned(), args)?;
+	let info = pkg.info_mut();
+	if info.original_version.is_empty() {
+		info.original_version.clone_from(&info.version);
+	}
+
+	let scripts = &pkg.info().scripts;
+	if !pkg.info().use_scripts && !scripts.is_empty() {
+		if !args.scripts && !args.quiet {
+			eprint!(
+				"Warning: Skipping conversion of scripts in package {}:",
+				pkg.info().name,
+			);
+			for (k, v) in scripts {
+				if !v.is_empty() {
+					eprint!(" {}", k.deb_name());
+				}
+			}
+			eprintln!(".");
+			eprintln!("Warning: Use the --scripts parameter to include the scripts.");
+		}
+		pkg.info_mut().use_scripts = args.scripts;
+	}
+
+	if !args.keep_version && !args.keep_release {
+		pkg.increment_release(args.bump);
+	}
+	override_version(
+		pkg.info_mut(),
+		args.set_version.as_deref(),
+		args.set_release.as_deref(),
+	);
+	if let Some(name) = &args.rename {
+		// This has to come before any target-specific munging of the name,
+		// like the `lsb-` prefix.
+		pkg.info_mut().name.clone_from(name);
+	}
+	pkg.info_mut()
+		.recommends
+		.extend_from_slice(&args.recommends);
+	pkg.info_mut().suggests.extend_from_slice(&args.suggests);
+
+	Ok(pkg)
+}
+
+/// Replaces the version and release of the package with those given,
+/// taking precedence over any bump to the release.
+pub(crate) fn override_version(
+	info: &mut PackageInfo,
+	version: Option<&str>,
+	release: Option<&str>,
+) {
+	if let Some(version) = version {
+		version.clone_into(&mut info.version);
+		// Targets that prefer the original version should get the new one too.
+		version.clone_into(&mut info.original_version);
+	}
+	if let Some(release) = release {
+		release.clone_into(&mut info.release);
+	}
+}
+
+/// Converts a package into each of the given formats.
+///
+/// Returns the paths to the generated packages, which are placed in the
+/// output directory given in `args`. Packages that are installed right
+/// after being built (with `--install`) are removed, and so are not returned.
+///
+/// For a dry run, nothing is unpacked or built, and nothing is returned.
+/// What would be done is printed instead.
+pub fn convert(file: &Path, formats: BitFlags<Format>, args: &Args) -> Result<Vec<PathBuf>> {
+	let mut pkg = read_package(file, args)?;
+	// Otherwise they'd be taken to be debs already, and silently skipped.
+	if matches!(pkg, AnySourcePackage::Dsc(_)) && formats.contains(Format::Deb) {
+		bail!(
+			"{} is a Debian source package, which cannot be converted to deb. Use dpkg-buildpackage to build it instead.",
+			file.display()
+		);
+	}
+	if args.dry_run {
+		dry_run(file, pkg.info(), formats, args)?;
+		return Ok(vec![]);
+	}
+
+	let unpacked = pkg.unpack()?;
+	let mut info = pkg.into_info();
+
+	let res = prepare(&mut info, &unpacked, formats, args)
+		.and_then(|()| generate(file, &info, &unpacked, formats, args));
+	if res.is_err() && args.keep_tree_on_error {
+		eprintln!(
+			"Conversion failed. Leaving unpacked tree at {} for inspection.",
+			unpacked.display()
+		);
+		let orig_dir = deb::target::orig_dir(&unpacked, &args.output_dir);
+		if formats.contains(Format::Deb) && orig_dir.exists() {
+			eprintln!("Its .orig copy is also left at {}.", orig_dir.display());
+		}
+		return res;
+	}
+	cleanup(&unpacked)?;
+	res
+}
+
+/// Makes the changes asked for in `args` to the unpacked package, before it
+/// is converted into any format.
+///
+/// Errors are handled like those from converting the package, so that the
+/// unpacked tree is still cleaned up, or kept for `--keep-tree-on-error`.
+fn prepare(
+	info: &mut PackageInfo,
+	unpacked: &Path,
+	formats: BitFlags<Format>,
+	args: &Args,
+) -> Result<()> {
+	if args.report_metainfo {
+		metainfo::MetainfoReport::scan(unpacked)?.print(args.verbosity);
+	}
+	if !args.exclude.is_empty() {
+		exclude(info, unpacked, &args.exclude)?;
+	}
+	if args.merge_usr {
+		merge_usr(info, unpacked)?;
+	}
+	// Other formats find hard links themselves while archiving.
+	if formats.contains(Format::Pkg) {
+		info.hard_links = util::find_hard_links(unpacked)?;
+	}
+	if let Some(owner) = &args.owner {
+		set_owner(info, unpacked, owner)?;
+	}
+	Ok(())
+}
+
+/// Deletes files matching any of `patterns` from the unpacked package, and
+/// forgets everything about them.
+///
+/// Everything under a matching directory is deleted along with it.
+fn exclude(info: &mut PackageInfo, unpacked: &Path, patterns: &[glob::Pattern]) -> Result<()> {
+	let excluded: Vec<_> = info
+		.files
+		.iter()
+		.filter(|f| patterns.iter().any(|p| p.matches_path(f)))
+		.cloned()
+		.collect();
+	let is_excluded = |file: &PathBuf| excluded.iter().any(|e| file.starts_with(e));
+
+	for file in &excluded {
+		let path = unpacked.join(file.strip_prefix("/").unwrap_or(file));
+		let res = match path.symlink_metadata() {
+			Ok(meta) if meta.is_dir() => std::fs::remove_dir_all(&path),
+			Ok(_) => std::fs::remove_file(&path),
+			// Already deleted along with a directory, or a ghost file.
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+			Err(e) => Err(e),
+		};
+		res.wrap_err_with(|| format!("Failed to exclude {}", file.display()))?;
+	}
+
+	info.files.retain(|f| !is_excluded(f));
+	info.dirs.retain(|f| !is_excluded(f));
+	info.conffiles.retain(|f| !is_excluded(f));
+	info.file_info.retain(|f, _| !is_excluded(f));
+	info.capabilities.retain(|f, _| !is_excluded(f));
+	info.file_flags.retain(|f, _| !is_excluded(f));
+	Ok(())
+}
+
+/// Top-level directories that are symlinks into `/usr` on merged-`/usr` systems.
+const MERGED_DIRS: &[&str] = &["bin", "lib", "lib64", "sbin"];
+
+/// Moves the contents of `/bin`, `/lib`, `/lib64` and `/sbin` into their
+/// counterparts in `/usr`, and updates the package's info to match.
+fn merge_usr(info: &mut PackageInfo, unpacked: &Path) -> Result<()> {
+	let mut merged = false;
+	for dir in MERGED_DIRS {
+		let from = unpacked.join(dir);
+		// Packages made for merged-`/usr` systems may ship the symlinks themselves.
+		if !from.symlink_metadata().is_ok_and(|m| m.is_dir()) {
+			continue;
+		}
+		merge_dir(&from, &unpacked.join("usr").join(dir))
+			.wrap_err_with(|| format!("Failed to merge /{dir} into /usr/{dir}"))?;
+		merged = true;
+	}
+	if !merged {
+		return Ok(());
+	}
+
+	let merged_path = |path: &Path| {
+		if MERGED_DIRS
+			.iter()
+			.any(|d| path.starts_with(Path::new("/").join(d)))
+		{
+			Path::new("/usr").join(path.strip_prefix("/").unwrap_or(path))
+		} else {
+			path.to_owned()
+		}
+	};
+	let dedup = |paths: &mut Vec<PathBuf>| {
+		let mut seen = std::collections::HashSet::new();
+		paths.retain(|p| seen.insert(p.clone()));
+	};
+	let had_root_dirs = info.dirs.iter().any(|d| d.parent() == Some(Path::new("/")));
+
+	for path in info
+		.files
+		.iter_mut()
+		.chain(&mut info.dirs)
+		.chain(&mut info.conffiles)
+	{
+		*path = merged_path(path);
+	}
+	info.file_info = std::mem::take(&mut info.file_info)
+		.into_iter()
+		.map(|(path, file_info)| (merged_path(&path), file_info))
+		.collect();
+	info.capabilities = std::mem::take(&mut info.capabilities)
+		.into_iter()
+		.map(|(path, caps)| (merged_path(&path), caps))
+		.collect();
+	info.file_flags = std::mem::take(&mut info.file_flags)
+		.into_iter()
+		.map(|(path, flags)| (merged_path(&path), flags))
+		.collect();
+	info.file_classes = std::mem::take(&mut info.file_classes)
+		.into_iter()
+		.map(|(path, class)| (merged_path(&path), class))
+		.collect();
+
+	// `/usr` may not have been in the package before.
+	let usr = PathBuf::from("/usr");
+	if had_root_dirs && !info.dirs.contains(&usr) {
+		info.dirs.insert(0, usr.clone());
+		info.files.insert(0, usr);
+	}
+	dedup(&mut info.files);
+	dedup(&mut info.dirs);
+	Ok(())
+}
+
+/// Moves everything in `from` into `to`, merging directories that exist
+/// in both, then removes `from`.
+fn merge_dir(from: &Path, to: &Path) -> Result<()> {
+	std::fs::create_dir_all(to)?;
+	for entry in std::fs::read_dir(from)? {
+		let entry = entry?;
+		let dest = to.join(entry.file_name());
+		match dest.symlink_metadata() {
+			Ok(meta) if meta.is_dir() && entry.file_type()?.is_dir() => {
+				merge_dir(&entry.path(), &dest)?;
+			}
+			Ok(_) => bail!("{} already exists", dest.display()),
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+				std::fs::rename(entry.path(), &dest)?;
+			}
+			Err(e) => return Err(e.into()),
+		}
+	}
+	std::fs::remove_dir(from)?;
+	Ok(())
+}
+
+/// Makes `owner` the owner of every file in the package, so that the
+/// files are chowned at install time no matter who built the package.
+fn set_owner(info: &mut PackageInfo, unpacked: &Path, owner: &str) -> Result<()> {
+	for file in &info.files {
+		let path = unpacked.join(file.strip_prefix("/").unwrap_or(file));
+		// Directories are usually shared with other packages, and chowning
+		// a symlink would chown whatever it points to instead.
+		match path.symlink_metadata() {
+			Ok(meta) if meta.is_dir() || meta.is_symlink() => continue,
+			Ok(_) => {}
+			// Ghost files are listed, but never unpacked.
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+			Err(e) => return Err(e.into()),
+		}
+		let file_info = info.file_info.entry(file.clone()).or_default();
+		owner.clone_into(&mut file_info.owner);
+	}
+	Ok(())
+}
+
+fn generate(
+	file: &Path,
+	info: &PackageInfo,
+	unpacked: &Path,
+	formats: BitFlags<Format>,
+	args: &Args,
+) -> Result<Vec<PathBuf>> {
+	let mut generated = vec![];
+
+	for format in formats {
+		// Convert package
+		if args.generate || info.original_format != format {
+			let mut pkg =
+				AnyTargetPackage::new(format, info.clone(), unpacked.to_path_buf(), args)?;
+
+			if args.generate {
+				let tree = unpacked.display();
+				if format == Format::Deb && !args.deb_args.single && !args.deb_args.binary_only {
+					println!("Directories {tree} and {tree}.orig prepared.");
+				} else {
+					println!("Directory {tree} prepared.");
+				}
+				// Make sure `package` does not wipe out the
+				// directory when it is destroyed.
+				// unpacked.clear();
+				continue;
+			}
+
+			match build(&mut pkg, format, args) {
+				Ok(new_file) => generated.extend(new_file),
+				Err(e) => {
+					if !args.keep_tree_on_error {
+						// The build error is more useful than any error from cleaning up.
+						_ = pkg.clean_tree();
+					}
+					return Err(e);
+				}
+			}
+
+			pkg.clean_tree()?;
+		} else if args.install {
+			// Don't convert the package, but do install it.
+			format.install(file, false)?;
+			// Note I don't remove it. I figure that might annoy
+			// people, since it was an input file.
+		}
+	}
+	Ok(generated)
+}
+
+/// Builds a package, then tests, runs `--post-build` on and installs it as
+/// asked for in `args`.
+///
+/// Returns the path to the package, unless it was installed and removed.
+fn build(pkg: &mut AnyTargetPackage, format: Format, args: &Args) -> Result<Option<PathBuf>> {
+	let new_file = pkg.build(&args.output_dir)?;
+
+	if args.test {
+		let results = pkg.test(&new_file)?;
+		if !results.is_empty() {
+			println!("Test results for {format} package {}:", new_file.display());
+			for result in results {
+				println!("\t{result}");
+			}
+		}
+	}
+	if let Some(cmd) = &args.post_build {
+		run_post_build(cmd, &new_file, args.verbosity, false)?;
+	}
+	if args.install {
+		format.install(&new_file, false)?;
+		std::fs::remove_file(&new_file)?;
+		return Ok(None);
+	}
+	Ok(Some(new_file))
+}
+
+/// Prints which packages would be generated from the given package, and the
+/// commands that would be run on them.
+fn dry_run(file: &Path, info: &PackageInfo, formats: BitFlags<Format>, args: &Args) -> Result<()> {
+	println!(
+		"{} is a {} package: {} {}-{}",
+		file.display(),
+		info.original_format,
+		info.name,
+		info.version,
+		info.release
+	);
+
+	for format in formats {
+		if args.generate || info.original_format != format {
+			let new_file = args.output_dir.join(format.package_file_name(info, args)?);
+			println!("Would generate {}", new_file.display());
+
+			if let Some(cmd) = &args.post_build {
+				run_post_build(cmd, &new_file, args.verbosity, true)?;
+			}
+			if args.install {
+				format.install(&new_file, true)?;
+			}
+		} else if args.install {
+			format.install(file, true)?;
+		}
+	}
+
+	Ok(())
+}
+
+/// Runs the `--post-build` shell command `cmd` on a generated package.
+///
+/// For a dry run, the command is only printed.
+fn run_post_build(cmd: &str, package: &Path, verbosity: Verbosity, dry_run: bool) -> Result<()> {
+	// Hand the path over as a positional parameter, so that it never needs quoting.
+	let script = if cmd.contains("{}") {
+		cmd.replace("{}", r#""$1""#)
+	} else {
+		format!(r#"{cmd} "$1""#)
+	};
+	Exec::cmd("sh")
+		.arg("-c")
+		.arg(script)
+		.arg("xenomorph")
+		.arg(package)
+		.log_and_spawn_unless_dry_run(verbosity, dry_run)
+		.wrap_err_with(|| format!("Post-build command failed on {}", package.display()))
+}
+
+/// Removes a directory that a package has been unpacked into.
+pub fn cleanup(unpacked: &Path) -> Result<()> {
+	if !unpacked.as_os_str().is_empty() {
+		// This should never happen, but it pays to check.
+		if unpacked.as_os_str() == "/" {
+			bail!(
+				"xenomorph internal error: unpacked_tree is set to '/'. Please file a bug report!"
+			);
+		}
+		if unpacked.is_dir() {
+			// Just in case some dir perms are too screwed up to remove
+			// and we're not running as root.
+			for path in glob::glob("*").unwrap() {
+				let path = path?;
+				if path.is_dir() {
+					let mut perms = std::fs::metadata(&path)?.permissions();
+					perms.set_mode(0o755);
+					std::fs::set_permissions(&path, perms)?;
+				}
+			}
+			std::fs::remove_dir_all(unpacked)?;
+		}
+	}
+	Ok(())
+}
+
 /// Extracted information about a package.
+///
+/// It can also be filled in by hand, to build a package from a file tree
+/// that didn't come from a [`SourcePackage`]. See [`AnyTargetPackage::new`].
 #[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PackageInfo {
 	/// The path to the package.
 	pub file: PathBuf,
@@ -150,17 +710,68 @@ pub struct PackageInfo {
 	pub name: String,
 	/// The package's upstream version.
 	pub version: String,
+	/// The upstream version exactly as the source package had it, before it
+	/// was changed to fit the rules of the target format.
+	pub original_version: String,
+	/// The package's epoch, if any.
+	///
+	/// Epochs take precedence over the version when versions are compared,
+	/// so they have to be kept for upgrades to work as expected.
+	pub epoch: Option<u32>,
 	/// The package's distribution-specific release number.
 	pub release: String,
 	/// The package's architecture, in the format used by Debian.
 	pub arch: String,
+	/// Whether the package can be installed alongside the same package for
+	/// other architectures, if known.
+	///
+	/// Only known for `.deb` packages.
+	pub multi_arch: Option<MultiArch>,
+	/// The operating system the package was built for, if known.
+	///
+	/// Only known for `.rpm` packages.
+	pub os: Option<String>,
 	/// The package's maintainer.
 	pub maintainer: String,
+	/// The organization that distributes the package.
+	pub vendor: String,
+	/// The upstream homepage of the package's software.
+	pub url: String,
 	/// The package's dependencies.
 	///
 	/// Only dependencies that should exist on all target distributions
 	/// can be put in here though, such as `lsb`.
 	pub dependencies: Vec<String>,
+	/// The package's build dependencies.
+	///
+	/// Only known for source packages, and never carried over into generated packages.
+	pub build_dependencies: Vec<String>,
+	/// Packages, or capabilities of other packages, that the package requires.
+	///
+	/// Only known for rpm packages. Unlike [`Self::dependencies`], these are
+	/// carried over whether or not they exist on the target distribution.
+	pub requires: Vec<Relation>,
+	/// Capabilities that the package provides to other packages.
+	///
+	/// Only known for rpm packages.
+	pub provides: Vec<Relation>,
+	/// Packages that cannot be installed alongside the package.
+	///
+	/// Only known for rpm packages.
+	pub conflicts: Vec<Relation>,
+	/// Packages that are usually installed along with the package,
+	/// though it works without them.
+	///
+	/// Only known for `.deb` packages, or if given with `--recommends`.
+	pub recommends: Vec<String>,
+	/// Packages that may be useful along with the package.
+	///
+	/// Only known for `.deb` packages, or if given with `--suggests`.
+	pub suggests: Vec<String>,
+	/// Packages that the package is useful along with.
+	///
+	/// Only known for `.deb` packages.
+	pub enhances: Vec<String>,
 	/// The section the package is in.
 	pub group: String,
 	/// A one-line description of the package.
@@ -182,8 +793,29 @@ pub struct PackageInfo {
 	pub conffiles: Vec<PathBuf>,
 	/// A list of all files in the package.
 	pub files: Vec<PathBuf>,
+	/// The files in [`files`](Self::files) that are directories.
+	///
+	/// These are tracked separately so that directories, especially empty
+	/// ones, are still packaged as directories after conversion.
+	pub dirs: Vec<PathBuf>,
+	/// Groups of files in the unpacked package that are hard links to each other.
+	///
+	/// The other files in each group are links to the first one. This is only
+	/// filled in when converting to pkg.
+	pub hard_links: Vec<Vec<PathBuf>>,
+	/// The directory that the package's relative paths are installed under,
+	/// if it isn't `/`.
+	///
+	/// Only relocatable Solaris pkgs have this.
+	pub base_dir: Option<PathBuf>,
+	/// The total size of the package's files once installed, in KiB.
+	pub installed_size: Option<u64>,
 	/// The text of the changelog.
+	///
+	/// Only used when the changelog could not be parsed into [`Self::changelog_entries`].
 	pub changelog: String,
+	/// The entries of the changelog, newest first.
+	pub changelog_entries: Vec<ChangelogEntry>,
 
 	/// When generating the package, only use the [`Self::scripts`] field
 	/// if this is set to a true value.
@@ -197,10 +829,152 @@ pub struct PackageInfo {
 	/// store to preserve their ownership information (as well as mode information
 	/// for `setuid` files) externally in this map.
 	pub file_info: HashMap<PathBuf, FileInfo>,
+	/// A map of file paths to the raw value of their `security.capability`
+	/// extended attribute, which package managers don't keep when unpacking,
+	/// so it's set again at install time.
+	///
+	/// See [`caps::to_text`] for the textual form.
+	pub capabilities: HashMap<PathBuf, Vec<u8>>,
+	/// A map of file paths to how they're marked in an rpm's `%files` list.
+	///
+	/// Only known for `.rpm` packages. Files that are marked as nothing are
+	/// not listed, and `%config` is tracked by [`Self::conffiles`] instead.
+	pub file_flags: HashMap<PathBuf, BitFlags<FileFlag>>,
+	/// A map of file paths, relative to [`Self::base_dir`], to the Solaris pkg
+	/// installation class they belong to.
+	///
+	/// Files in the default `none` class are not listed.
+	pub file_classes: HashMap<PathBuf, String>,
+}
+
+impl PackageInfo {
+	/// Formats a summary of the package for people to read, as aligned
+	/// `Key: value` lines.
+	#[must_use]
+	pub fn display_summary(&self) -> String {
+		let epoch = self.epoch.map(|e| format!("{e}:")).unwrap_or_default();
+		let scripts: Vec<_> = Script::ALL
+			.into_iter()
+			.filter(|s| self.scripts.get(s).is_some_and(|d| !d.trim().is_empty()))
+			.map(|s| s.deb_name().to_owned())
+			.collect();
+		let scripts = if scripts.is_empty() {
+			"none".to_owned()
+		} else {
+			scripts.join(", ")
+		};
+
+		let fields = [
+			("Name", self.name.clone()),
+			(
+				"Version",
+				format!("{epoch}{}-{}", self.version, self.release),
+			),
+			("Architecture", self.arch.clone()),
+			("Format", self.original_format.to_string()),
+			("Maintainer", self.maintainer.clone()),
+			(
+				"Dependencies",
+				(self.dependencies.len() + self.requires.len()).to_string(),
+			),
+			("Files", self.files.len().to_string()),
+			("Conffiles", self.conffiles.len().to_string()),
+			("Scripts", scripts),
+		];
+		let mut summary = String::new();
+		for (key, value) in fields {
+			_ = writeln!(summary, "{:<14}{value}", format!("{key}:"));
+		}
+		summary
+	}
+}
+
+/// An entry in a package's changelog. See [`PackageInfo::changelog_entries`] for more.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChangelogEntry {
+	/// The author of the entry, usually in the form of `Name <email>`.
+	pub author: String,
+	/// When the entry was written.
+	#[cfg_attr(feature = "serde", serde(with = "time::serde::rfc3339"))]
+	pub date: OffsetDateTime,
+	/// The text of the entry.
+	pub text: String,
+}
+
+/// How a package interacts with the same package for other architectures.
+/// See [`PackageInfo::multi_arch`] for more.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MultiArch {
+	/// The package can't be installed alongside itself for other architectures.
+	No,
+	/// The package can be installed alongside itself for other architectures.
+	Same,
+	/// The package can satisfy dependencies of packages for other architectures.
+	Foreign,
+	/// Packages for other architectures can choose to depend on this one
+	/// with `:any`.
+	Allowed,
+}
+impl MultiArch {
+	/// Gets a multi-arch setting from the value of a Debian `Multi-Arch` field.
+	#[must_use]
+	pub fn from_deb_value(s: &str) -> Option<Self> {
+		match s {
+			"no" => Some(Self::No),
+			"same" => Some(Self::Same),
+			"foreign" => Some(Self::Foreign),
+			"allowed" => Some(Self::Allowed),
+			_ => None,
+		}
+	}
+
+	/// The value of a Debian `Multi-Arch` field with this setting.
+	#[must_use]
+	pub fn deb_value(self) -> &'static str {
+		match self {
+			Self::No => "no",
+			Self::Same => "same",
+			Self::Foreign => "foreign",
+			Self::Allowed => "allowed",
+		}
+	}
+}
+
+/// A relationship to another package, possibly of a certain version.
+/// See [`PackageInfo::requires`] for more.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Relation {
+	/// The name of the other package, or of a capability it provides.
+	pub name: String,
+	/// How the other package's version is compared to [`Self::version`],
+	/// if the relationship is versioned at all.
+	pub op: Option<VersionOp>,
+	/// The version to compare against. Empty if [`Self::op`] is `None`.
+	pub version: String,
+}
+
+/// A comparison between two package versions. See [`Relation::op`] for more.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum VersionOp {
+	/// Strictly earlier than the given version.
+	Less,
+	/// Earlier than or equal to the given version.
+	LessEqual,
+	/// Exactly the given version.
+	Equal,
+	/// Later than or equal to the given version.
+	GreaterEqual,
+	/// Strictly later than the given version.
+	Greater,
 }
 
 /// Special information about files. See [`PackageInfo::file_info`] for more.
 #[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FileInfo {
 	/// The owner of the file.
 	owner: String,
@@ -208,18 +982,36 @@ pub struct FileInfo {
 	mode: Option<u32>,
 }
 
+/// How a file is marked in an rpm's `%files` list. See [`PackageInfo::file_flags`] for more.
+#[enumflags2::bitflags]
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FileFlag {
+	/// Documentation, which may be left out when installing.
+	Doc,
+	/// A file that belongs to the package, but isn't in it, such as a log file.
+	Ghost,
+	/// A license text, which is always installed, even without documentation.
+	License,
+	/// A conffile that is left alone on upgrade if it was changed, with the
+	/// new version installed next to it.
+	NoReplace,
+}
+
 /// Scripts that may be run in the build process. See [`PackageInfo::scripts`] for more.
 ///
 /// Due to historical reasons, there are many names for these scripts across
 /// different package managers. Here's a table linking all of them together:
 ///
-/// | `xenomorph` name          | Debian-style name | RPM scriptlet name | RPM query key | `tgz` script name | `pkg` script name |
-/// |---------------------------|-------------------|--------------------|---------------|-------------------|-------------------|
-/// | [`Self::BeforeInstall`]   | `preinst`         | `%pre`             | `%{PREIN}`    | `predoinst.sh`    | `preinstall`      |
-/// | [`Self::AfterInstall`]    | `postinst`        | `%post`            | `%{POSTIN}`   | `doinst.sh`       | `postinstall`     |
-/// | [`Self::BeforeUninstall`] | `prerm`           | `%preun`           | `%{PREUN}`    | `predelete.sh`    | `preremove`       |
-/// | [`Self::AfterInstall`]    | `postrm`          | `%postun`          | `%{POSTUN}`   | `delete.sh`       | `postremove`      |
+/// | `xenomorph` name          | Debian-style name | RPM scriptlet name | RPM query key | `tgz` script name | `pkg` script name | `apk` script name |
+/// |---------------------------|-------------------|--------------------|---------------|-------------------|-------------------|-------------------|
+/// | [`Self::BeforeInstall`]   | `preinst`         | `%pre`             | `%{PREIN}`    | `predoinst.sh`    | `preinstall`      | `.pre-install`    |
+/// | [`Self::AfterInstall`]    | `postinst`        | `%post`            | `%{POSTIN}`   | `doinst.sh`       | `postinstall`     | `.post-install`   |
+/// | [`Self::BeforeUninstall`] | `prerm`           | `%preun`           | `%{PREUN}`    | `predelete.sh`    | `preremove`       | `.pre-deinstall`  |
+/// | [`Self::AfterInstall`]    | `postrm`          | `%postun`          | `%{POSTUN}`   | `delete.sh`       | `postremove`      | `.post-deinstall` |
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Script {
 	/// Script that will be run before install.
 	BeforeInstall,
@@ -347,17 +1139,54 @@ impl Script {
 			Self::AfterUninstall => "postremove",
 		}
 	}
+	/// Gets a script from its `apk`-style script name.
+	///
+	/// See the [type-level documentation](Self) for the mapping between
+	/// `apk`-style script names and [`Script`] variants.
+	#[must_use]
+	pub fn from_apk_script_name(s: &str) -> Option<Self> {
+		match s {
+			".pre-install" => Some(Self::BeforeInstall),
+			".post-install" => Some(Self::AfterInstall),
+			".pre-deinstall" => Some(Self::BeforeUninstall),
+			".post-deinstall" => Some(Self::AfterUninstall),
+			_ => None,
+		}
+	}
+	/// Returns the script's `apk`-style script name.
+	///
+	/// See the [type-level documentation](Self) for the mapping between
+	/// `apk`-style script names and [`Script`] variants.
+	#[must_use]
+	pub fn apk_script_name(&self) -> &str {
+		match self {
+			Self::BeforeInstall => ".pre-install",
+			Self::AfterInstall => ".post-install",
+			Self::BeforeUninstall => ".pre-deinstall",
+			Self::AfterUninstall => ".post-deinstall",
+		}
+	}
 }
 
 /// Format of a package.
 #[enumflags2::bitflags]
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(
+	feature = "serde",
+	derive(serde::Serialize, serde::Deserialize),
+	serde(rename_all = "lowercase")
+)]
 pub enum Format {
+	/// The `.apk` format, used by Alpine Linux's `apk-tools`.
+	Apk,
 	/// The `.deb` format, used by `dpkg` and default for Debian-
 	/// and Ubuntu-derived distributions.
 	#[default]
 	Deb,
+	/// A plain directory tree with the package's files, and no packaging
+	/// information at all.
+	Dir,
 	/// The package format used by Linux Standard Base.
 	/// Basically an [`rpm` file](Self::Rpm) with a `lsb-` prefix
 	/// and a dependency on the `lsb` package.
@@ -372,19 +1201,57 @@ pub enum Format {
 	Tgz,
 }
 impl Format {
-	pub fn install(self, path: &Path) -> Result<()> {
+	/// Installs the package at `path`, or only prints how it would be
+	/// installed if `dry_run` is set.
+	pub fn install(self, path: &Path, dry_run: bool) -> Result<()> {
 		match self {
-			Format::Deb => deb::install(path),
-			Format::Lsb | Format::Rpm => rpm::install(path),
-			Format::Pkg => pkg::install(path),
-			Format::Tgz => tgz::install(path),
+			Format::Apk => apk::install(path, dry_run),
+			Format::Deb => deb::install(path, dry_run),
+			Format::Dir => dir::install(path, dry_run),
+			Format::Lsb | Format::Rpm => rpm::install(path, dry_run),
+			Format::Pkg => pkg::install(path, dry_run),
+			Format::Tgz => tgz::install(path, dry_run),
 		}
 	}
+
+	/// The name of the package file that converting a package with
+	/// the given info to this format would produce.
+	///
+	/// The info is first changed the way the target for this format
+	/// would change it, so that the name matches the one actually built.
+	pub fn package_file_name(self, info: &PackageInfo, args: &Args) -> Result<String> {
+		let mut info = info.clone();
+		match self {
+			Format::Apk => ApkTarget::sanitize_info(&mut info),
+			Format::Deb => {
+				DebTarget::sanitize_info(&mut info, !args.no_conversion_notice, args.keep_release)?;
+			}
+			Format::Lsb => {
+				LsbTarget::sanitize_info(&mut info);
+				RpmTarget::sanitize_info(&mut info);
+			}
+			Format::Rpm => RpmTarget::sanitize_info(&mut info),
+			Format::Dir | Format::Pkg | Format::Tgz => {}
+		}
+
+		Ok(match self {
+			Format::Apk => apk::target::file_name(&info),
+			Format::Deb => deb::target::file_name(&info),
+			Format::Dir => dir::file_name(&info),
+			Format::Lsb | Format::Rpm => rpm::target::file_name(&info),
+			Format::Pkg => pkg::target::file_name(&info),
+			Format::Tgz => {
+				tgz::target::file_name(&info, args.tgz_args.tgz_compression.unwrap_or_default())
+			}
+		})
+	}
 }
 impl Display for Format {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		f.write_str(match self {
+			Format::Apk => "apk",
 			Format::Deb => "deb",
+			Format::Dir => "dir",
 			Format::Lsb => "lsb",
 			Format::Pkg => "pkg",
 			Format::Rpm => "rpm",
@@ -392,3 +1259,432 @@ impl Display for Format {
 		})
 	}
 }
+impl FromStr for Format {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"apk" => Ok(Format::Apk),
+			"deb" | "debian" => Ok(Format::Deb),
+			"dir" => Ok(Format::Dir),
+			"lsb" => Ok(Format::Lsb),
+			"pkg" | "solaris" => Ok(Format::Pkg),
+			"rpm" | "redhat" => Ok(Format::Rpm),
+			"tgz" | "slackware" => Ok(Format::Tgz),
+			_ => Err(format!(
+				"Unknown package format `{s}`. Expected one of apk, deb, dir, lsb, pkg, rpm or tgz."
+			)),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{
+		collections::HashMap,
+		path::{Path, PathBuf},
+	};
+
+	use bpaf::Parser;
+
+	use super::{
+		bump_release, convert, detect_format, exclude, generate, merge_usr, rpm, run_post_build,
+		set_owner,
+		util::{args, Verbosity},
+		FileInfo, Format, LsbTarget, PackageInfo, RpmTarget, Script,
+	};
+
+	#[test]
+	fn test_detect_format() -> eyre::Result<()> {
+		let dir = tempfile::tempdir()?;
+		let fixture = |name: &str, contents: &[u8]| -> std::io::Result<_> {
+			let path = dir.path().join(name);
+			std::fs::write(&path, contents)?;
+			Ok(path)
+		};
+
+		let deb = fixture("xenomorph_0.1.0_amd64.deb", b"!<arch>\n")?;
+		assert_eq!(detect_format(&deb), Some(Format::Deb));
+		let rpm = fixture("xenomorph-0.1.0-1.x86_64.rpm", &[0xed, 0xab, 0xee, 0xdb])?;
+		assert_eq!(detect_format(&rpm), Some(Format::Rpm));
+		// Without a dependency on `lsb`, this is just an rpm with an unfortunate name.
+		let lsb = fixture(
+			"lsb-xenomorph-0.1.0-1.x86_64.rpm",
+			&[0xed, 0xab, 0xee, 0xdb],
+		)?;
+		assert_eq!(detect_format(&lsb), Some(Format::Rpm));
+		let apk = fixture("xenomorph-0.1.0-r1.apk", &[0x1f, 0x8b])?;
+		assert_eq!(detect_format(&apk), Some(Format::Apk));
+		let tgz = fixture("xenomorph-0.1.0.tar.gz", &[0x1f, 0x8b])?;
+		assert_eq!(detect_format(&tgz), Some(Format::Tgz));
+		let pkg = fixture("xenomorph-0.1.0.pkg", b"# PaCkAgE DaTaStReAm\n")?;
+		assert_eq!(detect_format(&pkg), Some(Format::Pkg));
+
+		// Files with unhelpful names are sniffed.
+		let sniffed = fixture("xenomorph", b"!<arch>\n")?;
+		assert_eq!(detect_format(&sniffed), Some(Format::Deb));
+
+		let dsc = fixture("xenomorph_0.1.0-1.dsc", b"Format: 3.0 (quilt)\n")?;
+		assert_eq!(detect_format(&dsc), None);
+		let unknown = fixture("xenomorph.txt", b"Shapeshift between package formats")?;
+		assert_eq!(detect_format(&unknown), None);
+		assert_eq!(detect_format(&dir.path().join("missing.bin")), None);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_format_from_str() {
+		for format in enumflags2::BitFlags::<Format>::all() {
+			assert_eq!(format.to_string().parse(), Ok(format));
+		}
+
+		assert_eq!("debian".parse(), Ok(Format::Deb));
+		assert_eq!("redhat".parse(), Ok(Format::Rpm));
+		assert_eq!("slackware".parse(), Ok(Format::Tgz));
+		assert_eq!("solaris".parse(), Ok(Format::Pkg));
+		assert!("Deb".parse::<Format>().is_err());
+		assert!("msi".parse::<Format>().is_err());
+	}
+
+	#[test]
+	fn test_package_file_name_renamed() -> eyre::Result<()> {
+		// As renamed by `--rename acme-foo`.
+		let info = PackageInfo {
+			name: "acme-foo".into(),
+			version: "1.0".into(),
+			release: "2".into(),
+			arch: "amd64".into(),
+			..Default::default()
+		};
+
+		let args = args().to_options().run_inner(&["acme-foo.deb"]).unwrap();
+		let unpacked = tempfile::tempdir()?;
+
+		assert_eq!(
+			Format::Deb.package_file_name(&info, &args)?,
+			"acme-foo_1.0-2_amd64.deb"
+		);
+
+		// The rpm targets rename the architecture, so check against the names
+		// they would actually build.
+		let lsb = LsbTarget::new(info.clone(), unpacked.path().to_path_buf(), &args)?;
+		let lsb_name = Format::Lsb.package_file_name(&info, &args)?;
+		assert_eq!(lsb_name, rpm::target::file_name(&lsb.rpm.info));
+		assert_eq!(lsb_name, "lsb-acme-foo-1.0-2.x86_64.rpm");
+		let rpm = RpmTarget::new(info.clone(), unpacked.path().to_path_buf(), &args)?;
+		let rpm_name = Format::Rpm.package_file_name(&info, &args)?;
+		assert_eq!(rpm_name, rpm::target::file_name(&rpm.info));
+		assert_eq!(rpm_name, "acme-foo-1.0-2.x86_64.rpm");
+		assert_eq!(
+			Format::Tgz.package_file_name(&info, &args)?,
+			"acme-foo-1.0.tgz"
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_generate_lsb_and_rpm() -> eyre::Result<()> {
+		let unpacked = tempfile::tempdir()?;
+		let info = PackageInfo {
+			name: "xenomorph".into(),
+			version: "1.0".into(),
+			release: "2".into(),
+			arch: "amd64".into(),
+			original_format: Format::Deb,
+			..Default::default()
+		};
+		let args = args()
+			.to_options()
+			.run_inner(&["--to-lsb", "--to-rpm", "--generate", "xenomorph.deb"])
+			.unwrap();
+
+		let generated = generate(
+			Path::new("xenomorph.deb"),
+			&info,
+			unpacked.path(),
+			Format::Lsb | Format::Rpm,
+			&args,
+		)?;
+		assert!(generated.is_empty());
+
+		let lsb = std::fs::read_to_string(unpacked.path().join("lsb-xenomorph-1.0-2.spec"))?;
+		assert!(lsb.contains("Name: lsb-xenomorph\n"));
+		assert!(lsb.contains("Requires: lsb"));
+		// The rpm is generated after the LSB package, from the same info.
+		let rpm = std::fs::read_to_string(unpacked.path().join("xenomorph-1.0-2.spec"))?;
+		assert!(rpm.contains("Name: xenomorph\n"));
+		assert!(!rpm.contains("lsb"));
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_generate_cleans_up_after_post_build() -> eyre::Result<()> {
+		let dir = tempfile::tempdir()?;
+		let unpacked = dir.path().join("xenomorph-1.0");
+		std::fs::create_dir_all(unpacked.join("usr/bin"))?;
+		std::fs::write(unpacked.join("usr/bin/xenomorph"), "#!/bin/sh\n")?;
+		let info = PackageInfo {
+			name: "xenomorph".into(),
+			version: "1.0".into(),
+			release: "2".into(),
+			arch: "amd64".into(),
+			original_format: Format::Deb,
+			files: vec![PathBuf::from("/usr/bin/xenomorph")],
+			..Default::default()
+		};
+
+		let output_dir = dir.path().to_string_lossy();
+		let cli = [
+			"--to-apk",
+			"--post-build",
+			"false",
+			"--output-dir",
+			&output_dir,
+			"x.deb",
+		];
+		let args = args().to_options().run_inner(&cli[..]).unwrap();
+
+		let err = generate(
+			Path::new("x.deb"),
+			&info,
+			&unpacked,
+			Format::Apk.into(),
+			&args,
+		)
+		.unwrap_err();
+		assert!(err.to_string().starts_with("Post-build command failed"));
+		// The package was built, but the control files it was built from are gone.
+		assert!(!unpacked.join(".PKGINFO").exists());
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_convert_dry_run() -> eyre::Result<()> {
+		use std::io::Write;
+
+		let dir = tempfile::tempdir()?;
+		let package = dir.path().join("xenomorph-1.0.tgz");
+		let mut tar = tar::Builder::new(vec![]);
+		let mut header = tar::Header::new_gnu();
+		header.set_mode(0o755);
+		header.set_size(9);
+		tar.append_data(&mut header, "usr/bin/xenomorph", &b"#!/bin/sh"[..])?;
+		let mut tgz = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+		tgz.write_all(&tar.into_inner()?)?;
+		std::fs::write(&package, tgz.finish()?)?;
+
+		let output_dir = dir.path().join("out");
+		let output_dir = output_dir.to_string_lossy();
+		let build_dir = dir.path().join("build");
+		let build_dir = build_dir.to_string_lossy();
+		let package_arg = package.to_string_lossy();
+		let cli = [
+			"--to-rpm",
+			"--dry-run",
+			"--install",
+			"--output-dir",
+			&output_dir,
+			"--build-dir",
+			&build_dir,
+			&package_arg,
+		];
+		let args = args().to_options().run_inner(&cli[..]).unwrap();
+
+		assert!(convert(&package, Format::Rpm.into(), &args)?.is_empty());
+		// Nothing is unpacked, built or installed.
+		assert!(!dir.path().join("out").exists());
+		assert!(!dir.path().join("build").exists());
+		assert!(package.exists());
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_convert_dsc_to_deb_rejected() -> eyre::Result<()> {
+		let dir = tempfile::tempdir()?;
+		let dsc = dir.path().join("xenomorph_0.1.0-2.dsc");
+		std::fs::write(
+			&dsc,
+			"Format: 3.0 (native)\nSource: xenomorph\nVersion: 0.1.0-2\nArchitecture: any\n",
+		)?;
+
+		let build_dir = dir.path().to_string_lossy();
+		let cli = ["--build-dir", &build_dir, "xenomorph_0.1.0-2.dsc"];
+		let args = args().to_options().run_inner(&cli[..]).unwrap();
+
+		let err = convert(&dsc, Format::Deb.into(), &args).unwrap_err();
+		assert!(err.to_string().contains("cannot be converted to deb"));
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_set_owner() -> eyre::Result<()> {
+		let unpacked = tempfile::tempdir()?;
+		let bin_dir = unpacked.path().join("usr/bin");
+		std::fs::create_dir_all(&bin_dir)?;
+		std::fs::write(bin_dir.join("xenomorph"), "")?;
+		std::os::unix::fs::symlink("xenomorph", bin_dir.join("alien"))?;
+
+		let mut info = PackageInfo {
+			files: ["/usr/", "/usr/bin/", "/usr/bin/xenomorph", "/usr/bin/alien"]
+				.map(PathBuf::from)
+				.into(),
+			..Default::default()
+		};
+		set_owner(&mut info, unpacked.path(), "root:root")?;
+
+		// Only the regular file is chowned.
+		let files: Vec<_> = info.file_info.keys().collect();
+		assert_eq!(files, [&PathBuf::from("/usr/bin/xenomorph")]);
+		assert_eq!(
+			info.file_info[&PathBuf::from("/usr/bin/xenomorph")].owner,
+			"root:root"
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_exclude() -> eyre::Result<()> {
+		let unpacked = tempfile::tempdir()?;
+		let doc_dir = unpacked.path().join("usr/share/doc/xenomorph");
+		std::fs::create_dir_all(&doc_dir)?;
+		std::fs::create_dir_all(unpacked.path().join("usr/bin"))?;
+		std::fs::write(doc_dir.join("README"), "")?;
+		std::fs::write(unpacked.path().join("usr/bin/xenomorph"), "")?;
+
+		let mut info = PackageInfo {
+			files: [
+				"/usr/bin/xenomorph",
+				"/usr/share/doc/xenomorph",
+				"/usr/share/doc/xenomorph/README",
+			]
+			.map(PathBuf::from)
+			.into(),
+			dirs: vec![PathBuf::from("/usr/share/doc/xenomorph")],
+			file_info: HashMap::from([(
+				PathBuf::from("/usr/share/doc/xenomorph/README"),
+				FileInfo::default(),
+			)]),
+			..Default::default()
+		};
+		let patterns = [glob::Pattern::new("/usr/share/doc/**")?];
+		exclude(&mut info, unpacked.path(), &patterns)?;
+
+		assert!(!doc_dir.exists());
+		assert!(unpacked.path().join("usr/bin/xenomorph").exists());
+		assert_eq!(info.files, [PathBuf::from("/usr/bin/xenomorph")]);
+		assert!(info.dirs.is_empty());
+		assert!(info.file_info.is_empty());
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_merge_usr() -> eyre::Result<()> {
+		let unpacked = tempfile::tempdir()?;
+		std::fs::create_dir_all(unpacked.path().join("bin"))?;
+		std::fs::create_dir_all(unpacked.path().join("etc"))?;
+		std::fs::write(unpacked.path().join("bin/foo"), "")?;
+		std::fs::write(unpacked.path().join("etc/foo.conf"), "")?;
+
+		let mut info = PackageInfo {
+			files: ["/bin", "/bin/foo", "/etc", "/etc/foo.conf"]
+				.map(PathBuf::from)
+				.into(),
+			dirs: ["/bin", "/etc"].map(PathBuf::from).into(),
+			conffiles: vec![PathBuf::from("/etc/foo.conf")],
+			file_info: HashMap::from([(PathBuf::from("/bin/foo"), FileInfo::default())]),
+			..Default::default()
+		};
+		merge_usr(&mut info, unpacked.path())?;
+
+		assert!(!unpacked.path().join("bin").exists());
+		assert!(unpacked.path().join("usr/bin/foo").is_file());
+		assert_eq!(
+			info.files,
+			["/usr", "/usr/bin", "/usr/bin/foo", "/etc", "/etc/foo.conf"].map(PathBuf::from)
+		);
+		assert_eq!(info.dirs, ["/usr", "/usr/bin", "/etc"].map(PathBuf::from));
+		assert_eq!(info.conffiles, [PathBuf::from("/etc/foo.conf")]);
+		assert!(info.file_info.contains_key(Path::new("/usr/bin/foo")));
+
+		// Files can't be merged over each other.
+		std::fs::create_dir(unpacked.path().join("bin"))?;
+		std::fs::write(unpacked.path().join("bin/foo"), "")?;
+		assert!(merge_usr(&mut info, unpacked.path()).is_err());
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_run_post_build() -> eyre::Result<()> {
+		let dir = tempfile::tempdir()?;
+		let package = dir.path().join("xenomorph 0.1.0.deb");
+		std::fs::write(&package, "")?;
+
+		run_post_build("true", &package, Verbosity::Normal, false)?;
+		run_post_build("cp {} {}.copy", &package, Verbosity::Normal, false)?;
+		assert!(dir.path().join("xenomorph 0.1.0.deb.copy").exists());
+		// Only printed for a dry run.
+		run_post_build("false", &package, Verbosity::Normal, true)?;
+
+		let err = run_post_build("false", &package, Verbosity::Normal, false).unwrap_err();
+		assert!(err.to_string().starts_with("Post-build command failed"));
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_display_summary() {
+		let info = PackageInfo {
+			name: "xenomorph".into(),
+			version: "0.1.0".into(),
+			release: "2".into(),
+			epoch: Some(1),
+			arch: "amd64".into(),
+			original_format: Format::Rpm,
+			maintainer: "Leah Amelia Chen <hi@pluie.me>".into(),
+			dependencies: vec!["libc6".into()],
+			files: ["/etc/xenomorph.conf", "/usr/bin/xenomorph"]
+				.map(PathBuf::from)
+				.into(),
+			conffiles: vec![PathBuf::from("/etc/xenomorph.conf")],
+			scripts: HashMap::from([
+				(Script::AfterInstall, "#!/bin/sh\n".into()),
+				(Script::BeforeInstall, "#!/bin/sh\n".into()),
+				(Script::AfterUninstall, "\n".into()),
+			]),
+			..Default::default()
+		};
+		assert_eq!(
+			info.display_summary(),
+			"\
+Name:         xenomorph
+Version:      1:0.1.0-2
+Architecture: amd64
+Format:       rpm
+Maintainer:   Leah Amelia Chen <hi@pluie.me>
+Dependencies: 1
+Files:        2
+Conffiles:    1
+Scripts:      preinst, postinst
+"
+		);
+	}
+
+	#[test]
+	fn test_bump_release() {
+		assert_eq!(bump_release("1", 1), "2");
+		assert_eq!(bump_release("9", 2), "11");
+		assert_eq!(bump_release("1.2ubuntu3", 1), "1.2ubuntu4");
+		assert_eq!(bump_release("2.1", 1), "2.2");
+		assert_eq!(bump_release("beta", 3), "3");
+		assert_eq!(bump_release("", 1), "1");
+	}
+}
@@ -2,7 +2,7 @@ use std::path::{Path, PathBuf};
 
 use eyre::Result;
 
-use crate::{rpm::source::RpmReader, Args};
+use crate::{rpm::source::RpmReader, util::Verbosity, Args};
 
 use super::{
 	rpm::{RpmSource, RpmTarget},
@@ -29,7 +29,8 @@ impl LsbSource {
 			return false;
 		}
 
-		let Ok(deps) = RpmReader::new(file).query("-R") else {
+		// Guessing the format of a package shouldn't make any noise.
+		let Ok(deps) = RpmReader::new(file, Verbosity::Normal).query("-R") else {
 			return false;
 		};
 
@@ -68,13 +69,20 @@ impl SourcePackage for LsbSource {
 
 #[derive(Debug)]
 pub struct LsbTarget {
-	rpm: RpmTarget,
+	pub(crate) rpm: RpmTarget,
 }
 impl LsbTarget {
 	/// Uses [`RpmTarget::new`] to generate the spec file.
 	/// First though, the package's name is munged to make it LSB compliant (sorta)
 	/// and `lsb` is added to its dependencies.
-	pub fn new(mut info: PackageInfo, unpacked_dir: PathBuf) -> Result<Self> {
+	pub fn new(mut info: PackageInfo, unpacked_dir: PathBuf, args: &Args) -> Result<Self> {
+		Self::sanitize_info(&mut info);
+		let rpm = RpmTarget::new(info, unpacked_dir, args)?;
+
+		Ok(Self { rpm })
+	}
+
+	pub(crate) fn sanitize_info(info: &mut PackageInfo) {
 		if !info.name.starts_with("lsb-") {
 			info.name.insert_str(0, "lsb-");
 		}
@@ -82,10 +90,6 @@ impl LsbTarget {
 
 		// Always include scripts when generating lsb package.
 		info.use_scripts = true;
-
-		let rpm = RpmTarget::new(info, unpacked_dir)?;
-
-		Ok(Self { rpm })
 	}
 }
 impl TargetPackage for LsbTarget {
@@ -94,11 +98,15 @@ impl TargetPackage for LsbTarget {
 	}
 
 	/// Uses [`RpmTarget::build`] to build the package, using `lsb-rpmbuild` if available.
-	fn build(&mut self) -> Result<PathBuf> {
+	fn build(&mut self, output_dir: &Path) -> Result<PathBuf> {
 		if let Ok(lsb_rpmbuild) = which::which("lsb-rpmbuild") {
-			self.rpm.build_with(&lsb_rpmbuild)
+			self.rpm.build_with(&lsb_rpmbuild, output_dir)
 		} else {
-			self.rpm.build()
+			self.rpm.build(output_dir)
 		}
 	}
+
+	fn test(&mut self, file_name: &Path) -> Result<Vec<String>> {
+		self.rpm.test(file_name)
+	}
 }
@@ -1,15 +1,25 @@
 #![forbid(unsafe_code)]
 #![warn(rust_2018_idioms, clippy::pedantic)]
 
-use std::{os::unix::prelude::PermissionsExt, path::Path};
+use std::{
+	fs::File,
+	io::Write,
+	os::unix::ffi::OsStrExt,
+	path::Path,
+	sync::{
+		atomic::{AtomicBool, AtomicUsize, Ordering},
+		Mutex, PoisonError,
+	},
+};
 
 use xenomorph::{
-	util::{args, Args, Verbosity},
-	AnySourcePackage, AnyTargetPackage, Format, PackageInfo, SourcePackage, TargetPackage,
+	util::{args, set_progress, Args},
+	Format, PackageInfo, Script, SourcePackage,
 };
 
 use bpaf::Parser;
-use eyre::{bail, Result};
+use eyre::{bail, Context, Result};
+use sha2::{Digest, Sha256};
 
 #[cfg(debug_assertions)]
 fn eyre() -> Result<()> {
@@ -24,6 +34,7 @@ fn main() -> Result<()> {
 	eyre()?;
 
 	let args = args()
+		.parse(|mut a| a.read_config().map(|()| a).map_err(|e| format!("{e:#}")))
 		.guard(
 			|a| !(a.install && (a.generate || a.deb_args.single)),
 			"You cannot use --generate or --single with --install.",
@@ -32,6 +43,10 @@ fn main() -> Result<()> {
 			|a| !(a.formats.exactly_one().is_none() && (a.generate || a.deb_args.single)),
 			"--generate and --single may only be used when converting to a single format.",
 		)
+		.guard(
+			|a| !a.null || a.list_files,
+			"--null may only be used with --list-files.",
+		)
 		.guard(
 			|a| !(a.deb_args.nopatch && a.deb_args.patch.is_some()),
 			"The options --nopatch and --patchfile cannot be used together.",
@@ -41,135 +56,256 @@ fn main() -> Result<()> {
 		.version(env!("CARGO_PKG_VERSION"))
 		.run();
 
-	Verbosity::set(args.verbosity);
+	if let Some(maintainer) = &args.maintainer {
+		if !maintainer.contains('@') {
+			eprintln!("Warning: maintainer `{maintainer}` does not contain an email address.");
+			eprintln!("Warning: Package building tools may reject it.");
+		}
+	}
 
 	// Check xenomorph's working environment.
-	// FIXME: We should let people decide the output directory.
-	if std::fs::write("test", "test").is_ok() {
-		std::fs::remove_file("test")?;
-	} else {
-		bail!("Cannot write to current directory. Try moving to /tmp and re-running `xenomorph`.");
+	// A dry run doesn't write anything, so there's no need to check in that case.
+	let output_dir = &args.output_dir;
+	if !args.dry_run
+		&& !args.show
+		&& !args.list_files
+		&& !args.list_scripts
+		&& args.extract_scripts.is_none()
+	{
+		if !output_dir.exists() {
+			std::fs::create_dir_all(output_dir).wrap_err_with(|| {
+				format!("Cannot create output directory {}", output_dir.display())
+			})?;
+		}
+		let test_file = output_dir.join("test");
+		if std::fs::write(&test_file, "test").is_ok() {
+			std::fs::remove_file(&test_file)?;
+		} else {
+			bail!(
+				"Cannot write to output directory {}. Try using --output-dir to choose another directory.",
+				output_dir.display()
+			);
+		}
+		if let Some(build_dir) = &args.build_dir {
+			std::fs::create_dir_all(build_dir).wrap_err_with(|| {
+				format!("Cannot create build directory {}", build_dir.display())
+			})?;
+		}
 	}
 
 	// Check if we're root.
 	if !nix::unistd::geteuid().is_root() {
-		if args.formats.contains(Format::Deb) && !args.generate && !args.deb_args.single {
+		if args.formats.contains(Format::Deb)
+			&& !args.generate
+			&& !args.deb_args.single
+			&& !args.dump_info
+			&& !args.show
+			&& !args.dry_run
+			&& !args.list_files
+			&& !args.list_scripts
+			&& args.extract_scripts.is_none()
+		{
 			bail!("Must run as root to convert to deb format (or you may use fakeroot).");
 		}
-		eprintln!("Warning: `xenomorph` is not running as root!");
-		eprintln!("Warning: Ownerships of files in the generated packages will probably be wrong.");
+		for warning in non_root_warnings(args.quiet, args.owner.is_some()) {
+			eprintln!("Warning: {warning}");
+		}
 	}
 
-	for file in &args.files {
-		if !file.try_exists()? {
-			bail!("File \"{}\" not found.", file.display());
+	let jobs = args.jobs.max(1);
+	// Progress from several packages at once would only garble the terminal.
+	set_progress(args.progress && jobs == 1);
+	if jobs == 1 {
+		for file in &args.files {
+			run(file, &args)?;
 		}
-		let mut pkg = AnySourcePackage::new(file.clone(), &args)?;
-
-		let scripts = &pkg.info().scripts;
-		if !pkg.info().use_scripts && !scripts.is_empty() {
-			if !args.scripts {
-				eprint!(
-					"Warning: Skipping conversion of scripts in package {}:",
-					pkg.info().name,
-				);
-				for (k, v) in scripts {
-					if !v.is_empty() {
-						eprint!(" {}", k.deb_name());
-					}
+		Ok(())
+	} else {
+		run_parallel(&args, jobs)
+	}
+}
+
+/// The warnings to print when `xenomorph` isn't running as root.
+fn non_root_warnings(quiet: bool, has_owner: bool) -> &'static [&'static str] {
+	if quiet {
+		&[]
+	} else if has_owner {
+		&["`xenomorph` is not running as root!"]
+	} else {
+		&[
+			"`xenomorph` is not running as root!",
+			"Ownerships of files in the generated packages will probably be wrong.",
+			"Use --owner to choose who should own them instead.",
+		]
+	}
+}
+
+fn run(file: &Path, args: &Args) -> Result<()> {
+	let res = if args.show {
+		xenomorph::read_package(file, args).map(|pkg| print!("{}", pkg.info().display_summary()))
+	} else if args.list_files {
+		xenomorph::read_package(file, args).and_then(|pkg| list_files(pkg.info(), args.null))
+	} else if args.list_scripts || args.extract_scripts.is_some() {
+		xenomorph::read_package(file, args).and_then(|pkg| {
+			if args.list_scripts {
+				list_scripts(pkg.info());
+			}
+			match &args.extract_scripts {
+				Some(dir) => extract_scripts(pkg.info(), dir),
+				None => Ok(()),
+			}
+		})
+	} else if args.dump_info {
+		xenomorph::read_package(file, args).and_then(|mut pkg| {
+			let unpacked = pkg.unpack()?;
+			let info = pkg.into_info();
+
+			let res = dump_info(&info);
+			xenomorph::cleanup(&unpacked)?;
+			res
+		})
+	} else {
+		xenomorph::convert(file, args.formats, args).and_then(|new_files| {
+			for new_file in new_files {
+				// Tell them where the package ended up.
+				println!("{} generated", new_file.display());
+				if args.checksums {
+					print_checksum(&new_file)?;
 				}
-				eprintln!(".");
-				eprintln!("Warning: Use the --scripts parameter to include the scripts.");
 			}
-			pkg.info_mut().use_scripts = args.scripts;
-		}
+			Ok(())
+		})
+	};
+	res.wrap_err_with(|| format!("Unable to convert {}", file.display()))
+}
+
+/// Converts the given files using up to `jobs` threads at once.
+///
+/// Once a conversion fails, no new ones are started. Every failure is
+/// reported, and the first one (in the order the files were given) is returned.
+fn run_parallel(args: &Args, jobs: usize) -> Result<()> {
+	let next = AtomicUsize::new(0);
+	let failed = AtomicBool::new(false);
+	let errors = Mutex::new(vec![]);
 
-		if !args.keep_version {
-			pkg.increment_release(args.bump);
+	std::thread::scope(|s| {
+		for _ in 0..jobs.min(args.files.len()) {
+			s.spawn(|| {
+				while !failed.load(Ordering::Relaxed) {
+					let i = next.fetch_add(1, Ordering::Relaxed);
+					let Some(file) = args.files.get(i) else {
+						break;
+					};
+					if let Err(e) = run(file, args) {
+						failed.store(true, Ordering::Relaxed);
+						errors
+							.lock()
+							.unwrap_or_else(PoisonError::into_inner)
+							.push((i, e));
+					}
+				}
+			});
 		}
+	});
 
-		let unpacked = pkg.unpack()?;
-		let info = pkg.into_info();
+	let mut errors = errors.into_inner().unwrap_or_else(PoisonError::into_inner);
+	errors.sort_by_key(|(i, _)| *i);
 
-		let res = generate(file, &info, &unpacked, &args);
-		cleanup(&unpacked)?;
-		res?;
+	let mut errors = errors.into_iter().map(|(_, e)| e);
+	let Some(first) = errors.next() else {
+		return Ok(());
+	};
+	for e in errors {
+		eprintln!("Error: {e:?}");
 	}
+	Err(first)
+}
 
+#[cfg(feature = "serde")]
+fn dump_info(info: &PackageInfo) -> Result<()> {
+	println!("{}", serde_json::to_string_pretty(info)?);
 	Ok(())
 }
+#[cfg(not(feature = "serde"))]
+fn dump_info(_info: &PackageInfo) -> Result<()> {
+	bail!("--dump-info requires xenomorph to be built with the `serde` feature.")
+}
 
-fn generate(file: &Path, info: &PackageInfo, unpacked: &Path, args: &Args) -> Result<()> {
-	for format in args.formats {
-		// Convert package
-		if args.generate || info.original_format != format {
-			let mut pkg =
-				AnyTargetPackage::new(format, info.clone(), unpacked.to_path_buf(), args)?;
-
-			if args.generate {
-				let tree = unpacked.display();
-				if format == Format::Deb && !args.deb_args.single {
-					println!("Directories {tree} and {tree}.orig prepared.");
-				} else {
-					println!("Directory {tree} prepared.");
-				}
-				// Make sure `package` does not wipe out the
-				// directory when it is destroyed.
-				// unpacked.clear();
-				continue;
-			}
-
-			let new_file = pkg.build()?;
+/// Prints the path, size and SHA-256 checksum of a generated package.
+fn print_checksum(file: &Path) -> Result<()> {
+	let mut hasher = Sha256::new();
+	let size = std::io::copy(&mut File::open(file)?, &mut hasher)
+		.wrap_err_with(|| format!("Unable to checksum {}", file.display()))?;
 
-			if args.deb_args.test {
-				let results = pkg.test(&new_file)?;
-				if !results.is_empty() {
-					println!("Test results:");
-					for result in results {
-						println!("\t{result}");
-					}
-				}
-			}
-			if args.install {
-				format.install(&new_file)?;
-				std::fs::remove_file(&new_file)?;
-			} else {
-				// Tell them where the package ended up.
-				println!("{} generated", new_file.display());
-			}
+	println!("{}  {size}  {:x}", file.display(), hasher.finalize());
+	Ok(())
+}
 
-			pkg.clean_tree()?;
-		} else if args.install {
-			// Don't convert the package, but do install it.
-			format.install(file)?;
-			// Note I don't remove it. I figure that might annoy
-			// people, since it was an input file.
+/// Prints the files in the package, one per line, with conffiles marked.
+///
+/// If `null` is set, each file is instead terminated by a NUL character
+/// and conffiles are not marked, so that the output can be safely passed
+/// to `xargs -0`.
+fn list_files(info: &PackageInfo, null: bool) -> Result<()> {
+	let mut stdout = std::io::stdout().lock();
+	for file in &info.files {
+		if null {
+			stdout.write_all(file.as_os_str().as_bytes())?;
+			stdout.write_all(b"\0")?;
+		} else if info.conffiles.contains(file) {
+			writeln!(stdout, "{} (conffile)", file.display())?;
+		} else {
+			writeln!(stdout, "{}", file.display())?;
 		}
 	}
 	Ok(())
 }
 
-fn cleanup(unpacked: &Path) -> Result<()> {
-	if !unpacked.as_os_str().is_empty() {
-		// This should never happen, but it pays to check.
-		if unpacked.as_os_str() == "/" {
-			bail!(
-				"xenomorph internal error: unpacked_tree is set to '/'. Please file a bug report!"
-			);
+/// Prints which maintainer scripts are in the package, one per line.
+fn list_scripts(info: &PackageInfo) {
+	for script in Script::ALL {
+		if info
+			.scripts
+			.get(&script)
+			.is_some_and(|s| !s.trim().is_empty())
+		{
+			println!("{}", script.deb_name());
 		}
-		if unpacked.is_dir() {
-			// Just in case some dir perms are too screwed up to remove
-			// and we're not running as root.
-			for path in glob::glob("*").unwrap() {
-				let path = path?;
-				if path.is_dir() {
-					let mut perms = std::fs::metadata(&path)?.permissions();
-					perms.set_mode(0o755);
-					std::fs::set_permissions(&path, perms)?;
-				}
-			}
-			std::fs::remove_dir_all(unpacked)?;
+	}
+}
+
+/// Writes each maintainer script in the package to `dir`, named after the
+/// package and the script.
+///
+/// Scripts are written whether or not they would be converted, since
+/// they're only being looked at.
+fn extract_scripts(info: &PackageInfo, dir: &Path) -> Result<()> {
+	std::fs::create_dir_all(dir)
+		.wrap_err_with(|| format!("Cannot create directory {}", dir.display()))?;
+	for (script, data) in &info.scripts {
+		if data.trim().is_empty() {
+			continue;
 		}
+		let path = dir.join(format!("{}.{}", info.name, script.deb_name()));
+		std::fs::write(&path, data)
+			.wrap_err_with(|| format!("Unable to write {}", path.display()))?;
+		println!("{} extracted", path.display());
 	}
 	Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+	use super::non_root_warnings;
+
+	#[test]
+	fn test_non_root_warnings() {
+		assert_eq!(non_root_warnings(false, false).len(), 3);
+		assert_eq!(
+			non_root_warnings(false, true),
+			["`xenomorph` is not running as root!"]
+		);
+		assert!(non_root_warnings(true, false).is_empty());
+		assert!(non_root_warnings(true, true).is_empty());
+	}
+}
@@ -0,0 +1,212 @@
+//! Detection of `AppStream` metadata and desktop entries, which software
+//! centers use to find graphical applications.
+
+use std::path::{Path, PathBuf};
+
+use eyre::Result;
+
+use crate::util::Verbosity;
+
+/// The `AppStream` metadata and desktop entries found in an unpacked package.
+#[derive(Debug, Default)]
+pub struct MetainfoReport {
+	/// `AppStream` metadata files, along with the problem that makes
+	/// each malformed, if any.
+	pub metainfo: Vec<(PathBuf, Option<String>)>,
+	/// Desktop entries.
+	pub desktop_files: Vec<PathBuf>,
+}
+impl MetainfoReport {
+	/// Looks for `AppStream` metadata and desktop entries in `dir`, and checks
+	/// that the metadata is well-formed XML.
+	pub fn scan(dir: &Path) -> Result<Self> {
+		let mut report = Self::default();
+
+		for pattern in ["**/*.appdata.xml", "**/*.metainfo.xml"] {
+			for file in glob::glob(&dir.join(pattern).to_string_lossy())? {
+				let file = file?;
+				let problem = match std::fs::read_to_string(&file) {
+					Ok(xml) => check_xml(&xml).err(),
+					Err(e) => Some(e.to_string()),
+				};
+				let path = Path::new("/").join(file.strip_prefix(dir)?);
+				report.metainfo.push((path, problem));
+			}
+		}
+		for file in glob::glob(&dir.join("**/*.desktop").to_string_lossy())? {
+			let file = file?;
+			report
+				.desktop_files
+				.push(Path::new("/").join(file.strip_prefix(dir)?));
+		}
+
+		report.metainfo.sort();
+		report.desktop_files.sort();
+		Ok(report)
+	}
+
+	/// Prints a summary of the report, listing every file if verbose.
+	///
+	/// Malformed metadata is always listed.
+	pub fn print(&self, verbosity: Verbosity) {
+		let malformed = self.metainfo.iter().filter(|(_, p)| p.is_some()).count();
+		eprintln!(
+			"Found {} AppStream metadata file(s), {malformed} of them malformed, and {} desktop file(s).",
+			self.metainfo.len(),
+			self.desktop_files.len()
+		);
+		if self.metainfo.is_empty() {
+			eprintln!("Warning: The package may not show up in software centers.");
+		}
+
+		for (file, problem) in &self.metainfo {
+			match problem {
+				Some(problem) => eprintln!("\t{}: {problem}", file.display()),
+				None if verbosity != Verbosity::Normal => eprintln!("\t{}", file.display()),
+				None => {}
+			}
+		}
+		if verbosity != Verbosity::Normal {
+			for file in &self.desktop_files {
+				eprintln!("\t{}", file.display());
+			}
+		}
+	}
+}
+
+/// Checks that `xml` is well-formed, returning what's wrong with it if not.
+///
+/// This only checks that elements are properly nested under a single root,
+/// which is enough to catch truncated or hand-mangled files. Attribute values
+/// and entities are not checked.
+fn check_xml(xml: &str) -> Result<(), String> {
+	let mut open = vec![];
+	let mut roots = 0;
+	let mut rest = xml;
+
+	while let Some(start) = rest.find('<') {
+		if open.is_empty() && !rest[..start].trim().is_empty() {
+			return Err("text outside of the root element".into());
+		}
+		rest = &rest[start..];
+
+		// Comments, CDATA sections, processing instructions and doctypes.
+		let skipped = [
+			("<!--", "-->"),
+			("<![CDATA[", "]]>"),
+			("<?", "?>"),
+			("<!", ">"),
+		]
+		.into_iter()
+		.find(|(start, _)| rest.starts_with(start));
+		if let Some((start, end)) = skipped {
+			let Some(len) = rest[start.len()..].find(end) else {
+				return Err(format!("unterminated `{start}`"));
+			};
+			rest = &rest[start.len() + len + end.len()..];
+			continue;
+		}
+
+		let Some(end) = rest.find('>') else {
+			return Err("unterminated tag".into());
+		};
+		let tag = &rest[1..end];
+		rest = &rest[end + 1..];
+
+		if let Some(name) = tag.strip_prefix('/') {
+			let name = name.trim();
+			match open.pop() {
+				Some(open) if open == name => {}
+				Some(open) => return Err(format!("</{name}> closes <{open}>")),
+				None => return Err(format!("</{name}> closes nothing")),
+			}
+			continue;
+		}
+
+		let self_closing = tag.ends_with('/');
+		let Some(name) = tag.trim_end_matches('/').split_whitespace().next() else {
+			return Err("tag without a name".into());
+		};
+		if open.is_empty() {
+			roots += 1;
+			if roots > 1 {
+				return Err(format!("<{name}> is a second root element"));
+			}
+		}
+		if !self_closing {
+			open.push(name);
+		}
+	}
+
+	if let Some(name) = open.last() {
+		return Err(format!("<{name}> is never closed"));
+	}
+	if !rest.trim().is_empty() {
+		return Err("text outside of the root element".into());
+	}
+	if roots == 0 {
+		return Err("no root element".into());
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use std::path::PathBuf;
+
+	use super::MetainfoReport;
+
+	#[test]
+	fn test_scan() -> eyre::Result<()> {
+		let dir = tempfile::tempdir()?;
+		let metainfo_dir = dir.path().join("usr/share/metainfo");
+		let applications_dir = dir.path().join("usr/share/applications");
+		std::fs::create_dir_all(&metainfo_dir)?;
+		std::fs::create_dir_all(&applications_dir)?;
+
+		std::fs::write(
+			metainfo_dir.join("me.pluie.Xenomorph.metainfo.xml"),
+			r#"<?xml version="1.0" encoding="UTF-8"?>
+<!-- Copyright 2024 Leah Amelia Chen -->
+<component type="desktop-application">
+  <id>me.pluie.Xenomorph</id>
+  <name>Xenomorph</name>
+  <launchable type="desktop-id">me.pluie.Xenomorph.desktop</launchable>
+  <description><p>Shapeshift between package formats</p></description>
+  <releases><release version="0.1.0"/></releases>
+</component>
+"#,
+		)?;
+		std::fs::write(
+			metainfo_dir.join("me.pluie.Alien.appdata.xml"),
+			"<component><id>me.pluie.Alien</id><name>Alien</component>",
+		)?;
+		std::fs::write(
+			applications_dir.join("me.pluie.Xenomorph.desktop"),
+			"[Desktop Entry]\nName=Xenomorph\n",
+		)?;
+
+		let report = MetainfoReport::scan(dir.path())?;
+		assert_eq!(
+			report.metainfo,
+			[
+				(
+					PathBuf::from("/usr/share/metainfo/me.pluie.Alien.appdata.xml"),
+					Some("</component> closes <name>".to_owned())
+				),
+				(
+					PathBuf::from("/usr/share/metainfo/me.pluie.Xenomorph.metainfo.xml"),
+					None
+				),
+			]
+		);
+		assert_eq!(
+			report.desktop_files,
+			[PathBuf::from(
+				"/usr/share/applications/me.pluie.Xenomorph.desktop"
+			)]
+		);
+
+		Ok(())
+	}
+}
@@ -1,7 +1,7 @@
 pub use source::PkgSource;
 pub use target::PkgTarget;
 
-use crate::util::{ExecExt, Verbosity};
+use crate::util::spawn_install;
 use eyre::{bail, Context, Result};
 use std::path::Path;
 use subprocess::Exec;
@@ -10,14 +10,10 @@ pub mod source;
 pub mod target;
 
 /// Install a pkg with pkgadd. Pass in the filename of the pkg to install.
-pub fn install(pkg: &Path) -> Result<()> {
+pub fn install(pkg: &Path, dry_run: bool) -> Result<()> {
 	if Path::new("/usr/sbin/pkgadd").exists() {
-		Exec::cmd("/usr/sbin/pkgadd")
-			.arg("-d")
-			.arg(".")
-			.arg(pkg)
-			.log_and_spawn(Verbosity::VeryVerbose)
-			.wrap_err("Unable to install")
+		let cmd = Exec::cmd("/usr/sbin/pkgadd").arg("-d").arg(".").arg(pkg);
+		spawn_install(cmd, dry_run).wrap_err("Unable to install")
 	} else {
 		bail!("Sorry, I cannot install the generated .pkg file because /usr/sbin/pkgadd is not present.")
 	}
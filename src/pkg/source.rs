@@ -7,11 +7,12 @@ use std::{
 
 use eyre::{bail, Context, Result};
 use fs_extra::dir::CopyOptions;
+use nix::{sys::stat, unistd::mkfifo};
 use subprocess::Exec;
 
 use crate::{
-	util::{make_unpack_work_dir, ExecExt},
-	Format, PackageInfo, Script, SourcePackage,
+	util::{make_unpack_work_dir, ExecExt, Verbosity},
+	Args, Format, PackageInfo, Script, SourcePackage,
 };
 
 #[derive(Debug)]
@@ -21,6 +22,11 @@ pub struct PkgSource {
 	pkg_dir: PathBuf,
 
 	pkgtrans: PathBuf,
+	/// Named pipes to create in the unpacked package, and their modes.
+	pipes: Vec<(PathBuf, u32)>,
+	verbosity: Verbosity,
+	/// Where to unpack the package, if not the current directory.
+	build_dir: Option<PathBuf>,
 }
 impl PkgSource {
 	#[must_use]
@@ -37,7 +43,7 @@ impl PkgSource {
 
 		line.contains("# PaCkAgE DaTaStReAm")
 	}
-	pub fn new(file: PathBuf) -> Result<Self> {
+	pub fn new(file: PathBuf, args: &Args) -> Result<Self> {
 		let pkginfo = which::which("pkginfo")
 			.wrap_err("`pkginfo` needs to be installed in order to convert from Solaris pkgs")?;
 		let pkgtrans = which::which("pkgtrans")
@@ -51,7 +57,7 @@ impl PkgSource {
 		};
 		let name = name.to_owned();
 
-		let mut reader = PkgReader::new(file, &pkginfo, &pkgtrans)?;
+		let mut reader = PkgReader::new(file, &pkginfo, &pkgtrans, args.verbosity)?;
 		let copyright = reader.read_copyright()?;
 
 		let mut info = PackageInfo {
@@ -66,7 +72,7 @@ impl PkgSource {
 		};
 
 		reader.read_pkg_info(&mut info)?;
-		reader.read_pkg_map(&mut info)?;
+		let pipes = reader.read_pkg_map(&mut info)?;
 
 		reader.cleanup()?;
 
@@ -83,6 +89,9 @@ impl PkgSource {
 			pkgname,
 			pkg_dir,
 			pkgtrans,
+			pipes,
+			verbosity: args.verbosity,
+			build_dir: args.build_dir.clone(),
 		})
 	}
 }
@@ -97,13 +106,13 @@ impl SourcePackage for PkgSource {
 		self.info
 	}
 	fn unpack(&mut self) -> Result<PathBuf> {
-		let work_dir = make_unpack_work_dir(&self.info)?;
+		let work_dir = make_unpack_work_dir(&self.info, self.build_dir.as_deref(), self.verbosity)?;
 
 		Exec::cmd(&self.pkgtrans)
 			.arg(&self.info.file)
 			.arg(&work_dir)
 			.arg(&self.pkgname)
-			.log_and_spawn(None)?;
+			.log_and_spawn(self.verbosity)?;
 
 		let mut work_dir_1 = work_dir.clone().into_os_string();
 		work_dir_1.push("_1");
@@ -112,6 +121,14 @@ impl SourcePackage for PkgSource {
 		std::fs::remove_dir(&work_dir)?;
 		fs_extra::dir::move_dir(&work_dir_1, &work_dir, &CopyOptions::default())?;
 
+		for (pipe, mode) in &self.pipes {
+			let path = work_dir.join(pipe);
+			if path.symlink_metadata().is_err() {
+				mkfifo(&path, stat::Mode::from_bits_truncate(*mode))
+					.wrap_err_with(|| format!("Unable to create pipe {}", path.display()))?;
+			}
+		}
+
 		Ok(work_dir)
 	}
 }
@@ -122,13 +139,18 @@ struct PkgReader {
 	pkgname: String,
 }
 impl PkgReader {
-	pub fn new(file: PathBuf, pkginfo: &Path, pkgtrans: &Path) -> Result<Self> {
+	pub fn new(
+		file: PathBuf,
+		pkginfo: &Path,
+		pkgtrans: &Path,
+		verbosity: Verbosity,
+	) -> Result<Self> {
 		let tdir = tempfile::tempdir()?.into_path();
 
 		let pkginfo = Exec::cmd(pkginfo)
 			.arg("-d")
 			.arg(&file)
-			.log_and_output(None)?
+			.log_and_output(verbosity)?
 			.stdout_str();
 		let Some(pkgname) = pkginfo.lines().next() else {
 			bail!("Received empty output from pkginfo");
@@ -144,7 +166,7 @@ impl PkgReader {
 			.arg(&file)
 			.arg(&tdir)
 			.arg(&pkgname)
-			.log_and_spawn(None)
+			.log_and_spawn(verbosity)
 			.wrap_err("Error running pkgtrans")?;
 
 		Ok(Self {
@@ -157,23 +179,12 @@ impl PkgReader {
 		let pkginfo = std::fs::read_to_string(&self.pkg_dir.join("pkginfo"))?;
 		parse_pkg_info(info, &pkginfo)
 	}
-	fn read_pkg_map(&mut self, info: &mut PackageInfo) -> Result<()> {
+	fn read_pkg_map(&mut self, info: &mut PackageInfo) -> Result<Vec<(PathBuf, u32)>> {
 		let pkgmap = std::fs::read_to_string(&self.pkg_dir.join("pkgmap"))?;
 		parse_pkg_map(info, &pkgmap, &self.file)
 	}
 	fn read_copyright(&mut self) -> Result<String> {
-		self.pkg_dir.push("copyright");
-
-		let copyright = if self.pkg_dir.is_file() {
-			let mut copyright = self.file.join("install");
-			copyright.push("copyright");
-			std::fs::read_to_string(&copyright)?
-		} else {
-			"unknown".into()
-		};
-
-		self.pkg_dir.pop();
-		Ok(copyright)
+		read_copyright(&self.pkg_dir)
 	}
 
 	fn cleanup(&mut self) -> Result<()> {
@@ -183,6 +194,18 @@ impl PkgReader {
 	}
 }
 
+fn read_copyright(pkg_dir: &Path) -> Result<String> {
+	// The copyright normally lives among the installation scripts.
+	for copyright in [pkg_dir.join("install/copyright"), pkg_dir.join("copyright")] {
+		if copyright.is_file() {
+			return std::fs::read_to_string(&copyright).wrap_err_with(|| {
+				format!("Unable to read copyright file {}", copyright.display())
+			});
+		}
+	}
+	Ok("unknown".into())
+}
+
 fn parse_pkg_info(info: &mut PackageInfo, content: &str) -> Result<()> {
 	// See https://docs.oracle.com/cd/E36784_01/html/E36882/pkginfo-4.html
 	let mut info_map: HashMap<&str, &str> = HashMap::new();
@@ -210,38 +233,65 @@ fn parse_pkg_info(info: &mut PackageInfo, content: &str) -> Result<()> {
 		.remove("DESC")
 		.map(|d| d.trim_matches('"').to_owned())
 		.unwrap_or_default();
+	info.base_dir = info_map
+		.remove("BASEDIR")
+		.map(|d| d.trim_matches('"'))
+		.filter(|d| !d.is_empty() && *d != "/")
+		.map(PathBuf::from);
 
 	Ok(())
 }
 
-fn parse_pkg_map(info: &mut PackageInfo, content: &str, file: &Path) -> Result<()> {
+/// Reads the files, conffiles and scripts of a package from its pkgmap.
+///
+/// Returns the named pipes in the package along with their modes, since
+/// `pkgadd` creates those at install time instead of unpacking them.
+fn parse_pkg_map(
+	info: &mut PackageInfo,
+	content: &str,
+	file: &Path,
+) -> Result<Vec<(PathBuf, u32)>> {
 	// See https://docs.oracle.com/cd/E36784_01/html/E36882/pkgmap-4.html
+	let mut pipes = vec![];
 
 	// Skip the preamble line
 	for f in content.lines().skip(1) {
-		let mut split = f.split(' ');
-
-		// TODO: allow other part numbers
-		let Some("1") = split.next() else {
-			continue;
-		};
-		let Some(ftype) = split.next() else {
+		let fields: Vec<_> = f.split(' ').collect();
+		let [part, ftype, class, path, rest @ ..] = &fields[..] else {
 			continue;
 		};
-		let Some(_) = split.next() else {
+		// Files can be spread across several parts (that is, volumes).
+		if part.parse::<u32>().is_err() {
 			continue;
-		};
-		let Some(path) = split.next() else {
-			continue;
-		};
+		}
+
+		if matches!(*ftype, "f" | "v" | "d" | "x" | "p") && *class != "none" {
+			info.file_classes
+				.insert(PathBuf::from(path), (*class).to_owned());
+		}
 
-		match ftype {
+		match *ftype {
 			"f" if path.starts_with("etc/") => {
 				let mut buf = PathBuf::from("/");
 				buf.push(path);
 				info.conffiles.push(buf);
 			}
-			"f" | "d" => info.files.push(PathBuf::from(path)),
+			// Volatile files are expected to change once installed,
+			// like logs, so they aren't conffiles.
+			"f" | "v" => info.files.push(PathBuf::from(path)),
+			// Exclusive directories are ones that only this package uses.
+			"d" | "x" => {
+				info.files.push(PathBuf::from(path));
+				info.dirs.push(PathBuf::from(path));
+			}
+			"p" => {
+				let mode = rest
+					.first()
+					.and_then(|m| u32::from_str_radix(m, 8).ok())
+					.unwrap_or(0o644);
+				info.files.push(PathBuf::from(path));
+				pipes.push((PathBuf::from(path), mode));
+			}
 			"i" => {
 				let Some(script) = Script::from_pkg_script_name(path) else {
 					continue;
@@ -253,12 +303,33 @@ fn parse_pkg_map(info: &mut PackageInfo, content: &str, file: &Path) -> Result<(
 		}
 	}
 
-	Ok(())
+	Ok(pipes)
 }
 
 #[cfg(test)]
 mod tests {
-	use std::path::Path;
+	use std::{
+		collections::HashMap,
+		path::{Path, PathBuf},
+	};
+
+	#[test]
+	fn test_read_copyright() -> eyre::Result<()> {
+		let pkg_dir = tempfile::tempdir()?;
+		assert_eq!(super::read_copyright(pkg_dir.path())?, "unknown");
+
+		std::fs::create_dir(pkg_dir.path().join("install"))?;
+		std::fs::write(
+			pkg_dir.path().join("install/copyright"),
+			"Copyright 1995 Sun Microsystems, Inc.\n",
+		)?;
+		assert_eq!(
+			super::read_copyright(pkg_dir.path())?,
+			"Copyright 1995 Sun Microsystems, Inc.\n"
+		);
+
+		Ok(())
+	}
 
 	#[test]
 	fn test_parse_pkg_info() -> eyre::Result<()> {
@@ -282,6 +353,7 @@ HOTLINE="Please contact your local service provider"
 EMAIL=""
 VSTOCK="0122c3f5566"
 CATEGORY="system"
+BASEDIR="/opt"
 ISTATES="S 2"
 RSTATES="S 2"
 			"#,
@@ -290,6 +362,7 @@ RSTATES="S 2"
 		assert_eq!(info.arch, "sparc");
 		assert_eq!(info.version, "11.5.1");
 		assert_eq!(info.description, "Have a nice Sun-day!");
+		assert_eq!(info.base_dir.as_deref(), Some(Path::new("/opt")));
 
 		Ok(())
 	}
@@ -298,7 +371,7 @@ RSTATES="S 2"
 	fn test_parse_pkg_map() -> eyre::Result<()> {
 		let mut info = crate::PackageInfo::default();
 
-		super::parse_pkg_map(
+		let pipes = super::parse_pkg_map(
 			&mut info,
 			r#"
 : 2 500
@@ -334,8 +407,27 @@ RSTATES="S 2"
 				Path::new("bin/cmda"),
 				Path::new("bin/cmdb"),
 				Path::new("bin/cmdc"),
+				Path::new("data"),
+				Path::new("data/apipe"),
+				Path::new("log"),
+				Path::new("log/logfile"),
+				Path::new("save"),
+				Path::new("spool"),
+				Path::new("tmp"),
+			]
+		);
+		assert_eq!(
+			info.dirs,
+			vec![
+				Path::new("bin"),
+				Path::new("data"),
+				Path::new("log"),
+				Path::new("save"),
+				Path::new("spool"),
+				Path::new("tmp"),
 			]
 		);
+		assert_eq!(pipes, [(PathBuf::from("data/apipe"), 0o755)]);
 		assert_eq!(
 			info.conffiles,
 			vec![
@@ -345,6 +437,16 @@ RSTATES="S 2"
 				Path::new("/etc/cmdg"),
 			]
 		);
+		assert_eq!(
+			info.file_classes,
+			HashMap::from([
+				(PathBuf::from("bin/cmdc"), "class1".to_owned()),
+				(PathBuf::from("etc/cmdd"), "class1".to_owned()),
+				(PathBuf::from("etc/cmdf"), "class2".to_owned()),
+				(PathBuf::from("data"), "class2".to_owned()),
+				(PathBuf::from("data/apipe"), "class1".to_owned()),
+			])
+		);
 
 		Ok(())
 	}
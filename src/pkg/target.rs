@@ -1,10 +1,15 @@
-use std::{fs::File, io::Write, path::PathBuf};
+use std::{
+	collections::{BTreeSet, HashMap, HashSet},
+	fs::{DirEntry, File},
+	io::Write,
+	path::{Path, PathBuf},
+};
 
 use eyre::{Context, Result};
-use subprocess::Exec;
+use subprocess::{Exec, Redirection};
 
 use crate::{
-	util::{chmod, mkdir, ExecExt},
+	util::{chmod, mkdir, move_file, Args, ExecExt, Verbosity},
 	PackageInfo, TargetPackage,
 };
 
@@ -13,32 +18,39 @@ pub struct PkgTarget {
 	info: PackageInfo,
 	unpacked_dir: PathBuf,
 	converted_name: String,
+	/// Files and directories added to the unpacked directory, in the order
+	/// they were created.
+	created: Vec<PathBuf>,
+	verbosity: Verbosity,
 }
 impl PkgTarget {
-	pub fn new(mut info: PackageInfo, mut unpacked_dir: PathBuf) -> Result<Self> {
-		let pwd = std::env::current_dir()?;
-		std::env::set_current_dir(&unpacked_dir)?;
+	pub fn new(mut info: PackageInfo, mut unpacked_dir: PathBuf, args: &Args) -> Result<Self> {
+		let (file_list, links) = Self::file_list(&unpacked_dir, &info.hard_links)?;
 
-		let mut file_list = String::new();
-		for file in glob::glob("**/*").unwrap() {
-			let file = file?;
-			let Some(name) = file.file_name() else {
-				continue;
-			};
-			let name = name.to_string_lossy();
-			if name != "prototype" {
-				file_list.push_str(&name);
-				file_list.push('\n');
+		let prototype = Exec::cmd("pkgproto")
+			.stdin(file_list.as_str())
+			.cwd(&unpacked_dir)
+			.log_and_output(args.verbosity)?
+			.stdout_str();
+
+		let mut created = vec![unpacked_dir.join("prototype")];
+		let mut pkgproto = File::create(&created[0])?;
+		pkgproto.write_all(Self::set_classes(&prototype, &info.file_classes).as_bytes())?;
+
+		// pkgproto is only fed regular files and directories, so that
+		// symlinks are recreated as links instead of copies of their target.
+		for (link, target) in links {
+			writeln!(pkgproto, "s none {}={}", link.display(), target.display())?;
+		}
+		// Likewise, hard links are recreated instead of duplicating their data.
+		for group in &info.hard_links {
+			if let Some((target, links)) = group.split_first() {
+				for link in links {
+					writeln!(pkgproto, "l none {}={}", link.display(), target.display())?;
+				}
 			}
 		}
 
-		let mut pkgproto = File::create("./prototype")?;
-		Exec::cmd("pkgproto")
-			.stdin(file_list.as_str())
-			.stdout(pkgproto.try_clone()?)
-			.log_and_spawn(None)?;
-		std::env::set_current_dir(pwd)?;
-
 		let PackageInfo {
 			name,
 			arch,
@@ -46,13 +58,32 @@ impl PkgTarget {
 			description,
 			copyright,
 			scripts,
+			base_dir,
+			file_classes,
+			vendor,
 			..
 		} = &mut info;
+		if vendor.is_empty() {
+			*vendor = "Xenomorph-converted package".into();
+		}
+		let base_dir = base_dir.as_deref().unwrap_or(Path::new("/")).display();
+		let classes: BTreeSet<_> = file_classes.values().map(String::as_str).collect();
+		let classes = std::iter::once("none")
+			.chain(classes)
+			.collect::<Vec<_>>()
+			.join(" ");
+
+		// pkginfo only has room for an email address.
+		let email = args.maintainer.as_deref().map_or("", |m| {
+			m.rsplit_once('<')
+				.map_or(m, |(_, email)| email.trim_end_matches('>'))
+		});
 		let mut converted_name = name.clone();
 		Self::convert_name(&mut converted_name);
 
 		unpacked_dir.push("pkginfo");
 		let mut pkginfo = File::create(&unpacked_dir)?;
+		created.push(unpacked_dir.clone());
 		#[rustfmt::skip]
 		writeln!(
 			pkginfo,
@@ -61,22 +92,27 @@ NAME="{name}"
 ARCH="{arch}"
 VERSION="{version}"
 CATEGORY="application"
-VENDOR="Xenomorph-converted package"
-EMAIL=
+VENDOR="{vendor}"
+EMAIL={email}
 PSTAMP=xenomorph
 MAXINST=1000
-BASEDIR="/"
-CLASSES="none"
+BASEDIR="{base_dir}"
+CLASSES="{classes}"
 DESC="{description}"
 "#)?;
 		unpacked_dir.pop();
 		writeln!(pkgproto, "i pkginfo=./pkginfo")?;
 
 		unpacked_dir.push("install");
-		mkdir(&unpacked_dir)?;
+		// The source package may already have an install directory.
+		if !unpacked_dir.is_dir() {
+			mkdir(&unpacked_dir, args.verbosity)?;
+			created.push(unpacked_dir.clone());
+		}
 
 		unpacked_dir.push("copyright");
 		std::fs::write(&unpacked_dir, copyright)?;
+		created.push(unpacked_dir.clone());
 		writeln!(pkgproto, "i copyright=./install/copyright")?;
 		unpacked_dir.pop();
 
@@ -85,7 +121,8 @@ DESC="{description}"
 			unpacked_dir.push(name);
 			if !data.trim().is_empty() {
 				std::fs::write(&unpacked_dir, data)?;
-				chmod(&unpacked_dir, 0o755)?;
+				chmod(&unpacked_dir, 0o755, args.verbosity)?;
+				created.push(unpacked_dir.clone());
 				writeln!(pkgproto, "i {name}={}", unpacked_dir.display())?;
 			}
 			unpacked_dir.pop();
@@ -96,9 +133,76 @@ DESC="{description}"
 			info,
 			unpacked_dir,
 			converted_name,
+			created,
+			verbosity: args.verbosity,
 		})
 	}
 
+	/// Lists the contents of `dir` as input for `pkgproto`, separating out
+	/// symlinks along with their targets.
+	///
+	/// Files that are hard links to the first file of one of `hard_links`
+	/// are left out. Symlinks to directories aren't followed, since the files
+	/// in them would otherwise be packaged twice.
+	fn file_list(
+		dir: &Path,
+		hard_links: &[Vec<PathBuf>],
+	) -> Result<(String, Vec<(PathBuf, PathBuf)>)> {
+		fn walk(
+			dir: &Path,
+			prefix: &Path,
+			hard_links: &HashSet<&PathBuf>,
+			file_list: &mut String,
+			links: &mut Vec<(PathBuf, PathBuf)>,
+		) -> Result<()> {
+			let mut entries = std::fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+			entries.sort_by_key(DirEntry::file_name);
+			for entry in entries {
+				let path = prefix.join(entry.file_name());
+				if path == Path::new("prototype") || hard_links.contains(&path) {
+					continue;
+				}
+				let file_type = entry.file_type()?;
+				if file_type.is_symlink() {
+					links.push((path, std::fs::read_link(entry.path())?));
+					continue;
+				}
+
+				file_list.push_str(&path.to_string_lossy());
+				file_list.push('\n');
+				if file_type.is_dir() {
+					walk(&entry.path(), &path, hard_links, file_list, links)?;
+				}
+			}
+			Ok(())
+		}
+
+		let mut file_list = String::new();
+		let mut links = vec![];
+		let hard_links: HashSet<_> = hard_links.iter().flat_map(|g| g.iter().skip(1)).collect();
+		walk(dir, Path::new(""), &hard_links, &mut file_list, &mut links)?;
+		Ok((file_list, links))
+	}
+
+	/// Puts the files in a prototype generated by `pkgproto` back into the
+	/// installation classes they were in, since `pkgproto` puts everything
+	/// into the `none` class.
+	fn set_classes(prototype: &str, classes: &HashMap<PathBuf, String>) -> String {
+		let mut out = String::new();
+		for line in prototype.lines() {
+			let mut fields: Vec<_> = line.split(' ').collect();
+			if let [_, class, path, ..] = &mut fields[..] {
+				let path = path.split_once('=').map_or(*path, |(path, _)| path);
+				if let Some(c) = classes.get(Path::new(path)) {
+					*class = c;
+				}
+			}
+			out.push_str(&fields.join(" "));
+			out.push('\n');
+		}
+		out
+	}
+
 	fn convert_name(name: &mut String) {
 		if name.starts_with("lib") {
 			name.replace_range(.."lib".len(), "l");
@@ -113,23 +217,165 @@ DESC="{description}"
 	}
 }
 impl TargetPackage for PkgTarget {
-	fn build(&mut self) -> Result<PathBuf> {
+	fn clean_tree(&mut self) -> Result<()> {
+		for path in self.created.drain(..).rev() {
+			if path.is_dir() {
+				std::fs::remove_dir_all(&path)?;
+			} else {
+				std::fs::remove_file(&path)?;
+			}
+		}
+		Ok(())
+	}
+	fn build(&mut self, output_dir: &Path) -> Result<PathBuf> {
 		Exec::cmd("pkgmk")
 			.args(&["-r", "/", "-d", "."])
 			.cwd(&self.unpacked_dir)
-			.log_and_spawn(None)
+			.log_and_spawn(self.verbosity)
 			.wrap_err("Error during pkgmk")?;
-		let name = format!("{}-{}.pkg", self.info.name, self.info.version);
+		// pkgmk spools the package into a directory named after it.
+		self.created
+			.push(self.unpacked_dir.join(&self.converted_name));
+		let name = file_name(&self.info);
 
 		Exec::cmd("pkgtrans")
 			.arg(&self.unpacked_dir)
 			.arg(&name)
 			.arg(&self.converted_name)
-			.log_and_spawn(None)
+			.log_and_spawn(self.verbosity)
 			.wrap_err("Error during pkgtrans")?;
 
-		std::fs::rename(self.unpacked_dir.join(&name), &name)?;
+		let dest = output_dir.join(&name);
+		move_file(&self.unpacked_dir.join(&name), &dest)?;
+
+		Ok(dest)
+	}
+	fn test(&mut self, file_name: &Path) -> Result<Vec<String>> {
+		pkgchk(which::which("pkgchk").ok(), file_name, self.verbosity)
+	}
+}
+
+/// The name of the file the package is built into.
+pub(crate) fn file_name(info: &PackageInfo) -> String {
+	format!("{}-{}.pkg", info.name, info.version)
+}
+
+/// Checks the datastream package `file_name` with `pkgchk`, if it's there.
+///
+/// It's hardly ever installed outside of Solaris, in which case there's
+/// nothing to check with.
+fn pkgchk(pkgchk: Option<PathBuf>, file_name: &Path, verbosity: Verbosity) -> Result<Vec<String>> {
+	let Some(pkgchk) = pkgchk else {
+		return Ok(vec!["pkgchk not available, so not testing".into()]);
+	};
+
+	// pkgchk fails if it finds any problems, and reports them on stderr.
+	let output = Exec::cmd(pkgchk)
+		.arg("-d")
+		.arg(file_name)
+		.stderr(Redirection::Merge)
+		.log_and_output_without_checking(verbosity)?
+		.stdout_str();
+
+	Ok(output
+		.lines()
+		.map(str::trim)
+		.filter(|s| !s.is_empty())
+		.map(str::to_owned)
+		.collect())
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{collections::HashMap, path::PathBuf};
+
+	use super::{pkgchk, PkgTarget};
+	use crate::{util::Verbosity, PackageInfo, TargetPackage};
+
+	#[test]
+	fn test_file_list() -> eyre::Result<()> {
+		let dir = tempfile::tempdir()?;
+		let lib_dir = dir.path().join("usr/lib");
+		std::fs::create_dir_all(&lib_dir)?;
+		std::fs::write(lib_dir.join("libfoo.so.1"), "ELF")?;
+		std::os::unix::fs::symlink("libfoo.so.1", lib_dir.join("libfoo.so"))?;
+		std::fs::hard_link(lib_dir.join("libfoo.so.1"), lib_dir.join("libfoo.so.1.0"))?;
+		std::fs::write(dir.path().join("prototype"), "")?;
+		// Only the link itself is packaged, not what's in the directory again.
+		std::os::unix::fs::symlink("usr/lib", dir.path().join("lib"))?;
+
+		let hard_links = [vec![
+			PathBuf::from("usr/lib/libfoo.so.1"),
+			PathBuf::from("usr/lib/libfoo.so.1.0"),
+		]];
+		let (file_list, links) = PkgTarget::file_list(dir.path(), &hard_links)?;
+		assert_eq!(file_list, "usr\nusr/lib\nusr/lib/libfoo.so.1\n");
+		assert_eq!(
+			links,
+			[
+				(PathBuf::from("lib"), PathBuf::from("usr/lib")),
+				(
+					PathBuf::from("usr/lib/libfoo.so"),
+					PathBuf::from("libfoo.so.1")
+				),
+			]
+		);
+
+		Ok(())
+	}
+	#[test]
+	fn test_clean_tree() -> eyre::Result<()> {
+		let unpacked_dir = tempfile::tempdir()?;
+		let dir = unpacked_dir.path();
+		std::fs::create_dir(dir.join("install"))?;
+		std::fs::write(dir.join("install/original"), "")?;
+		std::fs::write(dir.join("prototype"), "")?;
+		std::fs::write(dir.join("install/copyright"), "")?;
+
+		let mut target = PkgTarget {
+			info: PackageInfo::default(),
+			unpacked_dir: dir.to_owned(),
+			converted_name: "xenomorph".into(),
+			created: vec![dir.join("prototype"), dir.join("install/copyright")],
+			verbosity: Verbosity::Normal,
+		};
+		target.clean_tree()?;
+
+		// Only the original install directory is left.
+		assert!(!dir.join("prototype").exists());
+		assert!(!dir.join("install/copyright").exists());
+		assert!(dir.join("install/original").exists());
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_pkgchk_missing() -> eyre::Result<()> {
+		assert_eq!(
+			pkgchk(None, "xenomorph-1.0.pkg".as_ref(), Verbosity::Normal)?,
+			["pkgchk not available, so not testing"]
+		);
+		Ok(())
+	}
 
-		Ok(PathBuf::from(name))
+	#[test]
+	fn test_set_classes() {
+		let classes = HashMap::from([
+			(PathBuf::from("bin/cmdc"), "class1".to_owned()),
+			(PathBuf::from("etc/cmdd"), "class2".to_owned()),
+		]);
+		let prototype = "\
+d none bin 0755 leah staff
+f none bin/cmdc 0755 leah staff
+f none etc/cmdd 0644 leah staff
+";
+		assert_eq!(
+			PkgTarget::set_classes(prototype, &classes),
+			"\
+d none bin 0755 leah staff
+f class1 bin/cmdc 0755 leah staff
+f class2 etc/cmdd 0644 leah staff
+"
+		);
 	}
 }
@@ -0,0 +1,654 @@
+//! A minimal reader for the binary RPM header, for when `rpm` itself isn't available.
+//!
+//! See <https://rpm-software-management.github.io/rpm/manual/format_v4.html>
+//! for a description of the format.
+
+use std::{
+	collections::HashMap,
+	fmt::Write,
+	fs::File,
+	io::{BufReader, Read},
+	path::{Path, PathBuf},
+};
+
+use eyre::{bail, eyre, Context, Result};
+
+use super::source::{RpmFileAttrs, RpmQuery};
+
+const LEAD_MAGIC: [u8; 4] = [0xed, 0xab, 0xee, 0xdb];
+const LEAD_SIZE: usize = 96;
+const HEADER_MAGIC: [u8; 3] = [0x8e, 0xad, 0xe8];
+/// The most index entries `rpm` allows in a header (`HEADER_TAGS_MAX`).
+const HEADER_TAGS_MAX: usize = 0xffff;
+/// The largest data store `rpm` allows in a header (`HEADER_DATA_MAX`).
+const HEADER_DATA_MAX: usize = 0x0fff_ffff;
+
+/// The file is marked as `%config` in the spec.
+const RPMFILE_CONFIG: u64 = 1 << 0;
+
+/// Header tags, as defined in `rpmtag.h`.
+mod tag {
+	pub const I18NTABLE: u32 = 100;
+	pub const NAME: u32 = 1000;
+	pub const VERSION: u32 = 1001;
+	pub const RELEASE: u32 = 1002;
+	pub const EPOCH: u32 = 1003;
+	pub const SUMMARY: u32 = 1004;
+	pub const DESCRIPTION: u32 = 1005;
+	pub const BUILDTIME: u32 = 1006;
+	pub const BUILDHOST: u32 = 1007;
+	pub const SIZE: u32 = 1009;
+	pub const DISTRIBUTION: u32 = 1010;
+	pub const VENDOR: u32 = 1011;
+	pub const LICENSE: u32 = 1014;
+	pub const PACKAGER: u32 = 1015;
+	pub const GROUP: u32 = 1016;
+	pub const URL: u32 = 1020;
+	pub const OS: u32 = 1021;
+	pub const ARCH: u32 = 1022;
+	pub const PREIN: u32 = 1023;
+	pub const POSTIN: u32 = 1024;
+	pub const PREUN: u32 = 1025;
+	pub const POSTUN: u32 = 1026;
+	pub const OLDFILENAMES: u32 = 1027;
+	pub const FILEMODES: u32 = 1030;
+	pub const FILEFLAGS: u32 = 1037;
+	pub const FILEUSERNAME: u32 = 1039;
+	pub const FILEGROUPNAME: u32 = 1040;
+	pub const SOURCERPM: u32 = 1044;
+	pub const PROVIDENAME: u32 = 1047;
+	pub const REQUIREFLAGS: u32 = 1048;
+	pub const REQUIRENAME: u32 = 1049;
+	pub const REQUIREVERSION: u32 = 1050;
+	pub const CONFLICTFLAGS: u32 = 1053;
+	pub const CONFLICTNAME: u32 = 1054;
+	pub const CONFLICTVERSION: u32 = 1055;
+	pub const CHANGELOGTIME: u32 = 1080;
+	pub const CHANGELOGNAME: u32 = 1081;
+	pub const CHANGELOGTEXT: u32 = 1082;
+	pub const PREFIXES: u32 = 1098;
+	pub const PROVIDEFLAGS: u32 = 1112;
+	pub const PROVIDEVERSION: u32 = 1113;
+	pub const DIRINDEXES: u32 = 1116;
+	pub const BASENAMES: u32 = 1117;
+	pub const DIRNAMES: u32 = 1118;
+	pub const PAYLOADCOMPRESSOR: u32 = 1125;
+	pub const FILECAPS: u32 = 5010;
+
+	/// Maps a tag name, as used in query formats, to its numeric value.
+	pub fn from_name(name: &str) -> Option<u32> {
+		Some(match name {
+			"NAME" => NAME,
+			"VERSION" => VERSION,
+			"RELEASE" => RELEASE,
+			"EPOCH" => EPOCH,
+			"SUMMARY" => SUMMARY,
+			"DESCRIPTION" => DESCRIPTION,
+			"BUILDTIME" => BUILDTIME,
+			"BUILDHOST" => BUILDHOST,
+			"SIZE" => SIZE,
+			"DISTRIBUTION" => DISTRIBUTION,
+			"VENDOR" => VENDOR,
+			// `COPYRIGHT` is the old name of `LICENSE`.
+			"LICENSE" | "COPYRIGHT" => LICENSE,
+			"PACKAGER" => PACKAGER,
+			"GROUP" => GROUP,
+			"URL" => URL,
+			"OS" => OS,
+			"ARCH" => ARCH,
+			"PREIN" => PREIN,
+			"POSTIN" => POSTIN,
+			"PREUN" => PREUN,
+			"POSTUN" => POSTUN,
+			"SOURCERPM" => SOURCERPM,
+			"PROVIDENAME" => PROVIDENAME,
+			"REQUIREFLAGS" => REQUIREFLAGS,
+			"REQUIRENAME" => REQUIRENAME,
+			"REQUIREVERSION" => REQUIREVERSION,
+			"CONFLICTFLAGS" => CONFLICTFLAGS,
+			"CONFLICTNAME" => CONFLICTNAME,
+			"CONFLICTVERSION" => CONFLICTVERSION,
+			"CHANGELOGTIME" => CHANGELOGTIME,
+			"CHANGELOGNAME" => CHANGELOGNAME,
+			"CHANGELOGTEXT" => CHANGELOGTEXT,
+			"PREFIXES" => PREFIXES,
+			"PROVIDEFLAGS" => PROVIDEFLAGS,
+			"PROVIDEVERSION" => PROVIDEVERSION,
+			"PAYLOADCOMPRESSOR" => PAYLOADCOMPRESSOR,
+			"FILEFLAGS" => FILEFLAGS,
+			"FILECAPS" => FILECAPS,
+			_ => return None,
+		})
+	}
+}
+
+#[derive(Debug, Clone)]
+enum Value {
+	Int(Vec<u64>),
+	String(Vec<String>),
+	Binary,
+}
+
+/// The main header of an RPM package, parsed without the help of `rpm`.
+#[derive(Debug, Default)]
+pub struct RpmHeader {
+	entries: HashMap<u32, Value>,
+}
+impl RpmHeader {
+	pub fn read(file: &Path) -> Result<Self> {
+		let read = || {
+			let f = File::open(file)?;
+			let len = f.metadata()?.len();
+			Self::from_reader(BufReader::new(f), len)
+		};
+		read().wrap_err_with(|| format!("Error reading rpm header of {}", file.display()))
+	}
+
+	/// Reads the header from the start of an rpm file, `len` bytes long.
+	pub fn from_reader(mut reader: impl Read, len: u64) -> Result<Self> {
+		let mut lead = [0u8; LEAD_SIZE];
+		reader.read_exact(&mut lead)?;
+		if lead[..4] != LEAD_MAGIC {
+			bail!("Not an rpm file - invalid lead magic");
+		}
+
+		// The signature header comes first, and is padded to a multiple of 8 bytes.
+		let remaining = len.saturating_sub(LEAD_SIZE as u64);
+		let (_, sig_len) = Self::read_section(&mut reader, remaining)?;
+		let padding = (8 - sig_len % 8) % 8;
+		std::io::copy(
+			&mut reader.by_ref().take(padding as u64),
+			&mut std::io::sink(),
+		)?;
+
+		let remaining = remaining.saturating_sub((sig_len + padding) as u64);
+		let (header, _) = Self::read_section(&mut reader, remaining)?;
+		Ok(header)
+	}
+
+	/// Reads a header section, which must fit in the `remaining` bytes of the file.
+	fn read_section(reader: &mut impl Read, remaining: u64) -> Result<(Self, usize)> {
+		let mut intro = [0u8; 16];
+		reader.read_exact(&mut intro)?;
+		if intro[..3] != HEADER_MAGIC {
+			bail!("Malformed rpm header - invalid header magic");
+		}
+		let count = be_u32(&intro[8..12]) as usize;
+		let size = be_u32(&intro[12..16]) as usize;
+
+		// Both come from the file, so check them before allocating anything.
+		if count > HEADER_TAGS_MAX || size > HEADER_DATA_MAX {
+			bail!("Malformed rpm header - too large ({count} tags, {size} bytes of data)");
+		}
+		let len = 16 + count * 16 + size;
+		if len as u64 > remaining {
+			bail!("Malformed rpm header - {len} bytes long, but only {remaining} bytes are left");
+		}
+
+		let mut index = vec![0u8; count * 16];
+		reader.read_exact(&mut index)?;
+		let mut store = vec![0u8; size];
+		reader.read_exact(&mut store)?;
+
+		let mut entries = HashMap::new();
+		for entry in index.chunks_exact(16) {
+			let tag = be_u32(&entry[0..4]);
+			let ty = be_u32(&entry[4..8]);
+			let offset = be_u32(&entry[8..12]) as usize;
+			let count = be_u32(&entry[12..16]) as usize;
+
+			let data = store
+				.get(offset..)
+				.ok_or_else(|| eyre!("Malformed rpm header - tag {tag} out of bounds"))?;
+			if let Some(value) = Self::read_value(data, ty, count) {
+				entries.insert(tag, value);
+			} else {
+				bail!("Malformed rpm header - tag {tag} is truncated");
+			}
+		}
+
+		Ok((Self { entries }, len))
+	}
+
+	fn read_value(data: &[u8], ty: u32, count: usize) -> Option<Value> {
+		let ints = |width: usize| {
+			let data = data.get(..width * count)?;
+			let ints = data
+				.chunks_exact(width)
+				.map(|c| c.iter().fold(0u64, |acc, &b| acc << 8 | u64::from(b)))
+				.collect();
+			Some(Value::Int(ints))
+		};
+
+		match ty {
+			// NULL
+			0 => Some(Value::Binary),
+			// CHAR, INT8, INT16, INT32, INT64
+			1 | 2 => ints(1),
+			3 => ints(2),
+			4 => ints(4),
+			5 => ints(8),
+			// STRING, STRING_ARRAY, I18NSTRING
+			6 | 8 | 9 => {
+				let count = if ty == 6 { 1 } else { count };
+				let strings = data
+					.split(|&b| b == 0)
+					.take(count)
+					.map(|s| String::from_utf8_lossy(s).into_owned())
+					.collect::<Vec<_>>();
+				(strings.len() == count).then_some(Value::String(strings))
+			}
+			// BIN
+			7 => data.get(..count).map(|_| Value::Binary),
+			_ => None,
+		}
+	}
+
+	fn strings(&self, tag: u32) -> &[String] {
+		match self.entries.get(&tag) {
+			Some(Value::String(s)) => s,
+			_ => &[],
+		}
+	}
+	/// Picks the translation of `tag` for the first locale in `lang`, a
+	/// colon-separated list as in `$LANGUAGE`, that it's translated to,
+	/// the way `rpm` does. Falls back to the untranslated value.
+	fn localized(&self, tag: u32, lang: &str) -> Option<&str> {
+		let values = self.strings(tag);
+		let locales = self.strings(tag::I18NTABLE);
+
+		// `de_DE.UTF-8@euro` is matched by translations for itself, `de_DE` and `de`.
+		let matches = |locale: &str, wanted: &str| {
+			let country = wanted.split(['.', '@']).next().unwrap_or(wanted);
+			let language = country.split('_').next().unwrap_or(country);
+			locale == wanted || locale == country || locale == language
+		};
+		lang.split(':')
+			.filter(|wanted| !wanted.is_empty())
+			.find_map(|wanted| {
+				locales
+					.iter()
+					.zip(values)
+					.find(|(locale, value)| matches(locale, wanted) && !value.is_empty())
+			})
+			.map(|(_, value)| value)
+			.or(values.first())
+			.map(String::as_str)
+	}
+	fn ints(&self, tag: u32) -> &[u64] {
+		match self.entries.get(&tag) {
+			Some(Value::Int(i)) => i,
+			_ => &[],
+		}
+	}
+
+	/// Returns the full paths of all files in the package.
+	fn file_names(&self) -> Vec<PathBuf> {
+		let base_names = self.strings(tag::BASENAMES);
+		if base_names.is_empty() {
+			// Older packages store the full paths directly.
+			return self
+				.strings(tag::OLDFILENAMES)
+				.iter()
+				.map(PathBuf::from)
+				.collect();
+		}
+
+		let dir_names = self.strings(tag::DIRNAMES);
+		let dir_indexes = self.ints(tag::DIRINDEXES);
+
+		base_names
+			.iter()
+			.zip(dir_indexes)
+			.filter_map(|(base, &dir)| {
+				let dir = dir_names.get(usize::try_from(dir).ok()?)?;
+				Some(PathBuf::from(format!("{dir}{base}")))
+			})
+			.collect()
+	}
+}
+impl RpmQuery for RpmHeader {
+	fn query_field(&self, name: &str) -> Result<Option<String>> {
+		Ok(self.query_array(name)?.into_iter().next())
+	}
+
+	fn query_array(&self, name: &str) -> Result<Vec<String>> {
+		let tag = tag_of(name)?;
+		Ok(match self.entries.get(&tag) {
+			Some(Value::String(s)) => s.clone(),
+			Some(Value::Int(i)) => i.iter().map(|i| i.to_string()).collect(),
+			Some(Value::Binary) | None => vec![],
+		})
+	}
+
+	fn query_localized(&self, name: &str, lang: Option<&str>) -> Result<Option<String>> {
+		let tag = tag_of(name)?;
+		Ok(self
+			.localized(tag, lang.unwrap_or_default())
+			.map(str::to_owned))
+	}
+
+	fn query_files(&self) -> Result<Vec<PathBuf>> {
+		Ok(self.file_names())
+	}
+
+	fn query_conffiles(&self) -> Result<Vec<PathBuf>> {
+		let flags = self.ints(tag::FILEFLAGS);
+		Ok(self
+			.file_names()
+			.into_iter()
+			.zip(flags)
+			.filter(|(_, &flags)| flags & RPMFILE_CONFIG != 0)
+			.map(|(f, _)| f)
+			.collect())
+	}
+
+	fn query_info(&self) -> Result<String> {
+		let mut info = String::new();
+		for (key, name) in [
+			("Name", "NAME"),
+			("Version", "VERSION"),
+			("Release", "RELEASE"),
+			("Architecture", "ARCH"),
+			("Group", "GROUP"),
+			("License", "LICENSE"),
+			("Packager", "PACKAGER"),
+			("URL", "URL"),
+			("Summary", "SUMMARY"),
+			("Description", "DESCRIPTION"),
+		] {
+			if let Some(value) = self.query_field(name)? {
+				writeln!(info, "{key:<12}: {value}")?;
+			}
+		}
+		Ok(info)
+	}
+
+	fn query_file_attrs(&self) -> Result<Vec<RpmFileAttrs>> {
+		let modes = self.ints(tag::FILEMODES);
+		let owners = self.strings(tag::FILEUSERNAME);
+		let groups = self.strings(tag::FILEGROUPNAME);
+
+		Ok(self
+			.file_names()
+			.into_iter()
+			.zip(modes)
+			.zip(owners.iter().zip(groups))
+			.map(|((path, &mode), (owner, group))| RpmFileAttrs {
+				// FILEMODES is an array of 16-bit integers.
+				mode: u32::try_from(mode).unwrap_or_default(),
+				owner: owner.clone(),
+				group: group.clone(),
+				path,
+			})
+			.collect())
+	}
+}
+
+/// Looks up the tag for a field name in `rpm`'s query format, e.g. `%{NAME}`.
+fn tag_of(name: &str) -> Result<u32> {
+	let name = name
+		.strip_prefix("%{")
+		.and_then(|n| n.strip_suffix('}'))
+		.unwrap_or(name);
+	let Some(tag) = tag::from_name(name) else {
+		bail!("Cannot read rpm tag {name} without `rpm` installed");
+	};
+	Ok(tag)
+}
+
+fn be_u32(bytes: &[u8]) -> u32 {
+	u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+#[cfg(test)]
+mod tests {
+	use std::path::{Path, PathBuf};
+
+	use super::{tag, RpmHeader, RpmQuery};
+	use crate::rpm::source::{ensure_binary, ensure_linux};
+
+	/// A value stored in a synthetic header.
+	pub enum TestValue<'a> {
+		String(&'a str),
+		StringArray(&'a [&'a str]),
+		Int16(&'a [u16]),
+		Int32(&'a [u32]),
+	}
+
+	/// Builds a header section from a list of tags and values.
+	pub fn build_section(tags: &[(u32, TestValue<'_>)]) -> Vec<u8> {
+		let mut index = vec![];
+		let mut store = vec![];
+		for (tag, value) in tags {
+			let (ty, count, align) = match value {
+				TestValue::String(_) => (6u32, 1, 1),
+				TestValue::StringArray(s) => (8, s.len(), 1),
+				TestValue::Int16(i) => (3, i.len(), 2),
+				TestValue::Int32(i) => (4, i.len(), 4),
+			};
+			while store.len() % align != 0 {
+				store.push(0);
+			}
+			index.extend(tag.to_be_bytes());
+			index.extend(ty.to_be_bytes());
+			index.extend(u32::try_from(store.len()).unwrap().to_be_bytes());
+			index.extend(u32::try_from(count).unwrap().to_be_bytes());
+			match value {
+				TestValue::String(s) => {
+					store.extend(s.as_bytes());
+					store.push(0);
+				}
+				TestValue::StringArray(ss) => {
+					for s in *ss {
+						store.extend(s.as_bytes());
+						store.push(0);
+					}
+				}
+				TestValue::Int16(is) => is.iter().for_each(|i| store.extend(i.to_be_bytes())),
+				TestValue::Int32(is) => is.iter().for_each(|i| store.extend(i.to_be_bytes())),
+			}
+		}
+
+		let mut section = vec![0x8e, 0xad, 0xe8, 0x01, 0, 0, 0, 0];
+		section.extend(u32::try_from(tags.len()).unwrap().to_be_bytes());
+		section.extend(u32::try_from(store.len()).unwrap().to_be_bytes());
+		section.extend(index);
+		section.extend(store);
+		section
+	}
+
+	/// Builds the metadata part of an rpm file (i.e. without the payload).
+	pub fn build_rpm(tags: &[(u32, TestValue<'_>)]) -> Vec<u8> {
+		let mut rpm = vec![0u8; 96];
+		rpm[..4].copy_from_slice(&[0xed, 0xab, 0xee, 0xdb]);
+
+		let signature = build_section(&[(1000, TestValue::Int32(&[1234]))]);
+		let padding = (8 - signature.len() % 8) % 8;
+		rpm.extend(signature);
+		rpm.resize(rpm.len() + padding, 0);
+		rpm.extend(build_section(tags));
+		rpm
+	}
+
+	fn read(rpm: &[u8]) -> eyre::Result<RpmHeader> {
+		RpmHeader::from_reader(rpm, rpm.len() as u64)
+	}
+
+	#[test]
+	fn test_localized_summary() -> eyre::Result<()> {
+		let rpm = build_rpm(&[
+			(
+				tag::I18NTABLE,
+				TestValue::StringArray(&["C", "de", "fr_FR"]),
+			),
+			(tag::NAME, TestValue::String("xenomorph")),
+			(
+				tag::SUMMARY,
+				TestValue::StringArray(&[
+					"Shapeshift between package formats",
+					"Wechselt zwischen Paketformaten",
+					"",
+				]),
+			),
+		]);
+		let header = read(&rpm)?;
+		let summary = |lang| header.query_localized("%{SUMMARY}", lang);
+
+		let english = Some("Shapeshift between package formats".to_owned());
+		let german = Some("Wechselt zwischen Paketformaten".to_owned());
+		assert_eq!(summary(None)?, english);
+		assert_eq!(summary(Some("de_AT.UTF-8"))?, german);
+		// There's an entry for French, but it's empty.
+		assert_eq!(summary(Some("fr_FR:de"))?, german);
+		assert_eq!(summary(Some("ja_JP"))?, english);
+		assert_eq!(
+			header.query_localized("%{NAME}", Some("de"))?.as_deref(),
+			Some("xenomorph")
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_read_header() -> eyre::Result<()> {
+		let rpm = build_rpm(&[
+			(tag::NAME, TestValue::String("xenomorph")),
+			(tag::VERSION, TestValue::String("0.1.0")),
+			(tag::RELEASE, TestValue::String("2")),
+			(tag::ARCH, TestValue::String("x86_64")),
+			(
+				tag::SOURCERPM,
+				TestValue::String("xenomorph-0.1.0-2.src.rpm"),
+			),
+			(tag::VENDOR, TestValue::String("Xenomorph Project")),
+			(
+				tag::URL,
+				TestValue::String("https://github.com/pluiedev/xenomorph"),
+			),
+			(tag::FILEMODES, TestValue::Int16(&[0o100_755, 0o100_644])),
+			(tag::FILEFLAGS, TestValue::Int32(&[0, 1])),
+			(tag::FILEUSERNAME, TestValue::StringArray(&["root", "leah"])),
+			(
+				tag::FILEGROUPNAME,
+				TestValue::StringArray(&["root", "users"]),
+			),
+			(tag::DIRINDEXES, TestValue::Int32(&[0, 1])),
+			(
+				tag::BASENAMES,
+				TestValue::StringArray(&["xenomorph", "xenomorph.conf"]),
+			),
+			(
+				tag::DIRNAMES,
+				TestValue::StringArray(&["/usr/bin/", "/etc/"]),
+			),
+			(
+				tag::CHANGELOGTIME,
+				TestValue::Int32(&[1_700_000_000, 1_600_000_000]),
+			),
+		]);
+		let header = read(&rpm)?;
+
+		assert_eq!(header.query_field("%{NAME}")?.as_deref(), Some("xenomorph"));
+		assert_eq!(header.query_field("%{VERSION}")?.as_deref(), Some("0.1.0"));
+		assert_eq!(header.query_field("%{RELEASE}")?.as_deref(), Some("2"));
+		assert_eq!(header.query_field("%{SUMMARY}")?, None);
+		assert_eq!(header.query_arch(None)?, "amd64");
+		assert!(!header.is_source()?);
+		assert_eq!(
+			header.query_field("%{VENDOR}")?.as_deref(),
+			Some("Xenomorph Project")
+		);
+		assert_eq!(
+			header.query_field("%{URL}")?.as_deref(),
+			Some("https://github.com/pluiedev/xenomorph")
+		);
+
+		assert_eq!(
+			header.query_files()?,
+			vec![
+				PathBuf::from("/usr/bin/xenomorph"),
+				PathBuf::from("/etc/xenomorph.conf")
+			]
+		);
+		assert_eq!(
+			header.query_conffiles()?,
+			vec![PathBuf::from("/etc/xenomorph.conf")]
+		);
+
+		assert_eq!(
+			header.query_array("%{CHANGELOGTIME}")?,
+			vec!["1700000000", "1600000000"]
+		);
+
+		let attrs = header.query_file_attrs()?;
+		assert_eq!(attrs[1].mode, 0o100_644);
+		assert_eq!(attrs[1].owner, "leah");
+		assert_eq!(attrs[1].group, "users");
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_source_rpm_rejected() -> eyre::Result<()> {
+		let srpm = build_rpm(&[
+			(tag::NAME, TestValue::String("xenomorph")),
+			(tag::ARCH, TestValue::String("x86_64")),
+		]);
+		let header = read(&srpm)?;
+		assert!(header.is_source()?);
+		assert!(ensure_binary(&header, Path::new("xenomorph-0.1.0-2.src.rpm")).is_err());
+
+		let nosrc = build_rpm(&[
+			(tag::ARCH, TestValue::String("nosrc")),
+			(tag::SOURCERPM, TestValue::String("(none)")),
+		]);
+		let header = read(&nosrc)?;
+		assert!(header.is_source()?);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_foreign_os_rejected() -> eyre::Result<()> {
+		let file = Path::new("xenomorph-0.1.0-2.x86_64.rpm");
+		let rpm = build_rpm(&[
+			(tag::OS, TestValue::String("irix")),
+			(
+				tag::SOURCERPM,
+				TestValue::String("xenomorph-0.1.0-2.src.rpm"),
+			),
+		]);
+		let header = read(&rpm)?;
+		assert!(ensure_linux(&header, file, false).is_err());
+		assert_eq!(ensure_linux(&header, file, true)?.as_deref(), Some("irix"));
+
+		let rpm = build_rpm(&[(tag::OS, TestValue::String("linux"))]);
+		let header = read(&rpm)?;
+		assert_eq!(
+			ensure_linux(&header, file, false)?.as_deref(),
+			Some("linux")
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_oversized_header_rejected() {
+		let mut rpm = build_rpm(&[(tag::NAME, TestValue::String("xenomorph"))]);
+		// After the lead and the signature, which is padded to 40 bytes.
+		let header = 96 + 40;
+		assert!(read(&rpm).is_ok());
+		// Cut off the end of the data store.
+		assert!(read(&rpm[..rpm.len() - 1]).is_err());
+
+		// More tags than `rpm` allows.
+		rpm[header + 8..header + 12].copy_from_slice(&0x10000u32.to_be_bytes());
+		assert!(read(&rpm).is_err());
+
+		// A data store bigger than `rpm` allows, which isn't allocated.
+		rpm[header + 8..header + 12].copy_from_slice(&1u32.to_be_bytes());
+		rpm[header + 12..header + 16].copy_from_slice(&u32::MAX.to_be_bytes());
+		assert!(read(&rpm).is_err());
+	}
+}
@@ -1,15 +1,16 @@
 pub use source::RpmSource;
 pub use target::RpmTarget;
 
-use crate::util::{ExecExt, Verbosity};
+use crate::util::spawn_install;
 use eyre::Result;
 use std::path::Path;
 use subprocess::Exec;
 
+mod header;
 pub mod source;
 pub mod target;
 
-pub fn install(rpm: &Path) -> Result<()> {
+pub fn install(rpm: &Path, dry_run: bool) -> Result<()> {
 	let mut cmd = Exec::cmd("rpm").arg("-ivh");
 
 	if let Ok(args) = std::env::var("RPMINSTALLOPT") {
@@ -18,5 +19,224 @@ pub fn install(rpm: &Path) -> Result<()> {
 		}
 	}
 
-	cmd.arg(rpm).log_and_spawn(Verbosity::VeryVerbose)
+	spawn_install(cmd.arg(rpm), dry_run)
+}
+
+/// Maps an rpm architecture name (or number) to its Debian equivalent.
+/// Unknown architectures are passed through as-is.
+#[must_use]
+pub fn rpm_arch_to_deb(arch: &str) -> &str {
+	match arch.as_bytes() {
+		// NOTE(pluie): do NOT ask me where these numbers came from.
+		// I have NO clue.
+		b"1" => "i386",
+		b"2" => "alpha",
+		b"3" => "sparc",
+		b"6" => "m68k",
+		b"noarch" => "all",
+		b"ppc" => "powerpc",
+		b"x86_64" | b"em64t" => "amd64",
+		b"aarch64" => "arm64",
+		b"armv4l" => "arm",
+		b"armv7l" => "armel",
+		b"armv7hl" => "armhf",
+		b"parisc" => "hppa",
+		b"ppc64le" => "ppc64el",
+		b"loongarch64" => "loong64",
+
+		// Treat 486, 586, etc, and Pentium, as 386.
+		o if o.eq_ignore_ascii_case(b"pentium") => "i386",
+		&[b'i' | b'I', b'0'..=b'9', b'8', b'6'] => "i386",
+
+		// s390x, riscv64, mips64el and others are named the same.
+		_ => arch,
+	}
+}
+
+/// Maps a Debian architecture name to its rpm equivalent.
+/// Unknown architectures are passed through as-is.
+#[must_use]
+pub fn deb_arch_to_rpm(arch: &str) -> &str {
+	match arch {
+		"all" => "noarch",
+		"i386" => "i686",
+		"amd64" => "x86_64",
+		"arm64" => "aarch64",
+		"arm" => "armv4l",
+		"armel" => "armv7l",
+		"armhf" => "armv7hl",
+		"powerpc" => "ppc", // XXX is this the canonical name for powerpc on rpm systems?
+		"hppa" => "parisc",
+		"ppc64el" => "ppc64le",
+		"loong64" => "loongarch64",
+		_ => arch,
+	}
+}
+
+/// Standard rpm groups for common Debian sections.
+pub const SECTION_GROUPS: &[(&str, &str)] = &[
+	("admin", "System Environment/Base"),
+	("comm", "Applications/Communications"),
+	("database", "Applications/Databases"),
+	("debug", "Development/Debuggers"),
+	("devel", "Development/Tools"),
+	("doc", "Documentation"),
+	("editors", "Applications/Editors"),
+	("electronics", "Applications/Engineering"),
+	("fonts", "User Interface/X"),
+	("games", "Amusements/Games"),
+	("gnome", "User Interface/Desktops"),
+	("graphics", "Applications/Multimedia"),
+	("hamradio", "Applications/Communications"),
+	("interpreters", "Development/Languages"),
+	("kde", "User Interface/Desktops"),
+	("kernel", "System Environment/Kernel"),
+	("libdevel", "Development/Libraries"),
+	("libs", "System Environment/Libraries"),
+	("mail", "Applications/Internet"),
+	("math", "Applications/Engineering"),
+	("net", "Applications/Internet"),
+	("news", "Applications/Internet"),
+	("oldlibs", "System Environment/Libraries"),
+	("perl", "Development/Languages"),
+	("python", "Development/Languages"),
+	("science", "Applications/Engineering"),
+	("shells", "System Environment/Shells"),
+	("sound", "Applications/Multimedia"),
+	("text", "Applications/Text"),
+	("utils", "Applications/System"),
+	("video", "Applications/Multimedia"),
+	("web", "Applications/Internet"),
+	("x11", "User Interface/X"),
+];
+
+/// Maps a Debian section to a standard rpm group.
+/// Unknown sections end up in `Converted/{section}`.
+#[must_use]
+pub fn deb_section_to_rpm_group(section: &str) -> String {
+	// Sections outside of main are prefixed with their archive area.
+	let (_, name) = section.rsplit_once('/').unwrap_or(("", section));
+	match SECTION_GROUPS.iter().find(|(s, _)| *s == name) {
+		Some((_, group)) => (*group).to_owned(),
+		None => format!("Converted/{section}"),
+	}
+}
+
+/// Debian sections for standard rpm groups.
+pub const GROUP_SECTIONS: &[(&str, &str)] = &[
+	("Amusements/Games", "games"),
+	("Amusements/Graphics", "graphics"),
+	("Applications/Archiving", "utils"),
+	("Applications/Communications", "comm"),
+	("Applications/Databases", "database"),
+	("Applications/Editors", "editors"),
+	("Applications/Emulators", "otherosfs"),
+	("Applications/Engineering", "science"),
+	("Applications/File", "utils"),
+	("Applications/Internet", "net"),
+	("Applications/Multimedia", "video"),
+	("Applications/Publishing", "text"),
+	("Applications/System", "admin"),
+	("Applications/Text", "text"),
+	("Development/Debuggers", "debug"),
+	("Development/Languages", "interpreters"),
+	("Development/Libraries", "libdevel"),
+	("Development/System", "devel"),
+	("Development/Tools", "devel"),
+	("Documentation", "doc"),
+	("System Environment/Base", "admin"),
+	("System Environment/Daemons", "admin"),
+	("System Environment/Kernel", "kernel"),
+	("System Environment/Libraries", "libs"),
+	("System Environment/Shells", "shells"),
+	("User Interface/Desktops", "x11"),
+	("User Interface/X", "x11"),
+	("User Interface/X Hardware Support", "x11"),
+];
+
+/// Maps an rpm group to a Debian section, if it's a standard group or one
+/// made by [`deb_section_to_rpm_group`].
+#[must_use]
+pub fn rpm_group_to_deb_section(group: &str) -> Option<String> {
+	if let Some(section) = group.strip_prefix("Converted/") {
+		return Some(section.to_owned());
+	}
+	GROUP_SECTIONS
+		.iter()
+		.find(|(g, _)| *g == group)
+		.map(|(_, section)| (*section).to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{
+		deb_arch_to_rpm, deb_section_to_rpm_group, rpm_arch_to_deb, rpm_group_to_deb_section,
+	};
+
+	#[test]
+	fn test_arch_round_trip() {
+		let cases = [
+			("noarch", "all"),
+			("i686", "i386"),
+			("x86_64", "amd64"),
+			("aarch64", "arm64"),
+			("armv4l", "arm"),
+			("armv7l", "armel"),
+			("armv7hl", "armhf"),
+			("ppc", "powerpc"),
+			("ppc64le", "ppc64el"),
+			("parisc", "hppa"),
+			("s390x", "s390x"),
+			("riscv64", "riscv64"),
+			("mips64el", "mips64el"),
+			("loongarch64", "loong64"),
+		];
+		for (rpm, deb) in cases {
+			assert_eq!(rpm_arch_to_deb(rpm), deb, "rpm arch {rpm}");
+			assert_eq!(deb_arch_to_rpm(deb), rpm, "deb arch {deb}");
+		}
+
+		// Some names are only ever mapped one way.
+		assert_eq!(rpm_arch_to_deb("i586"), "i386");
+		assert_eq!(rpm_arch_to_deb("em64t"), "amd64");
+		assert_eq!(rpm_arch_to_deb("1"), "i386");
+	}
+
+	#[test]
+	fn test_section_to_group() {
+		assert_eq!(deb_section_to_rpm_group("utils"), "Applications/System");
+		assert_eq!(
+			deb_section_to_rpm_group("libs"),
+			"System Environment/Libraries"
+		);
+		assert_eq!(deb_section_to_rpm_group("devel"), "Development/Tools");
+		assert_eq!(
+			deb_section_to_rpm_group("contrib/net"),
+			"Applications/Internet"
+		);
+
+		assert_eq!(deb_section_to_rpm_group("unknown"), "Converted/unknown");
+		assert_eq!(
+			deb_section_to_rpm_group("non-free/xenomorphs"),
+			"Converted/non-free/xenomorphs"
+		);
+	}
+
+	#[test]
+	fn test_group_to_section() {
+		assert_eq!(
+			rpm_group_to_deb_section("Applications/Internet").as_deref(),
+			Some("net")
+		);
+		assert_eq!(
+			rpm_group_to_deb_section("User Interface/X").as_deref(),
+			Some("x11")
+		);
+		// Sections that had no standard group survive a round trip.
+		assert_eq!(
+			rpm_group_to_deb_section(&deb_section_to_rpm_group("non-free/xenomorphs")).as_deref(),
+			Some("non-free/xenomorphs")
+		);
+		assert_eq!(rpm_group_to_deb_section("Unspecified"), None);
+	}
 }
@@ -1,22 +1,36 @@
 use std::{
 	collections::{HashMap, HashSet},
-	path::{Component, Path, PathBuf},
+	path::{Path, PathBuf},
 };
 
+use enumflags2::BitFlags;
 use eyre::{bail, Context, Result};
 use fs_extra::dir::CopyOptions;
 use nix::unistd::{chown, geteuid, Gid, Group, Uid, User};
-use subprocess::{Exec, NullFile};
+use subprocess::{Exec, NullFile, Redirection};
+use time::OffsetDateTime;
 
+use super::{header::RpmHeader, rpm_arch_to_deb};
 use crate::{
-	util::{chmod, make_unpack_work_dir, mkdir, ExecExt},
-	Args, {FileInfo, Format, PackageInfo, Script, SourcePackage},
+	caps,
+	util::{chmod, make_unpack_work_dir, mkdir, spawn_with_progress, ExecExt, Progress, Verbosity},
+	Args, ChangelogEntry, FileFlag, FileInfo, Format, PackageInfo, Relation, Script, SourcePackage,
+	VersionOp,
 };
 
 #[derive(Debug)]
 pub struct RpmSource {
 	info: PackageInfo,
 	prefixes: Option<PathBuf>,
+	/// Prefixes to move files from, and where to move them to.
+	relocations: Vec<(PathBuf, PathBuf)>,
+	/// The parsed package header, used in place of `rpm` when it isn't installed.
+	header: Option<RpmHeader>,
+	/// What the payload is compressed with, e.g. `zstd`.
+	payload_compressor: Option<String>,
+	verbosity: Verbosity,
+	/// Where to unpack the package, if not the current directory.
+	build_dir: Option<PathBuf>,
 }
 impl RpmSource {
 	#[must_use]
@@ -25,19 +39,34 @@ impl RpmSource {
 			.map_or(false, |o| o.eq_ignore_ascii_case("rpm"))
 	}
 	pub fn new(file: PathBuf, args: &Args) -> Result<Self> {
-		let rpm = RpmReader::new(&file);
+		// Prefer asking `rpm` itself, but fall back to reading the header
+		// ourselves on systems that don't have it installed.
+		let header = if which::which("rpm").is_err() {
+			Some(RpmHeader::read(&file)?)
+		} else {
+			None
+		};
+		let reader = RpmReader::new(&file, args.verbosity);
+		let rpm: &dyn RpmQuery = match &header {
+			Some(header) => header,
+			None => &reader,
+		};
+
+		ensure_binary(rpm, &file)?;
+		let os = ensure_linux(rpm, &file, args.allow_foreign_os)?;
 
 		let prefixes = rpm.query_field("%{PREFIXES}")?.map(PathBuf::from);
 
-		let conffiles = rpm.query_file_list("-c")?;
-		let files = rpm.query_file_list("-l")?;
-		let binary_info = rpm.query("-i")?;
+		let conffiles = rpm.query_conffiles()?;
+		let files = rpm.query_files()?;
+		let binary_info = rpm.query_info()?;
 
 		// Sanity check and sanitize fields.
 
-		let description = rpm.query_field("%{DESCRIPTION}")?;
+		let lang = args.lang.as_deref();
+		let description = rpm.query_localized("%{DESCRIPTION}", lang)?;
 
-		let summary = if let Some(summary) = rpm.query_field("%{SUMMARY}")? {
+		let summary = if let Some(summary) = rpm.query_localized("%{SUMMARY}", lang)? {
 			summary
 		} else {
 			// Older rpms will have no summary, but will have a description.
@@ -71,19 +100,45 @@ impl RpmSource {
 		let Some(release) = rpm.query_field("%{RELEASE}")? else {
 			bail!("Error querying rpm file: release not found!")
 		};
+		let epoch = match rpm.query_field("%{EPOCH}")? {
+			Some(epoch) => Some(
+				epoch
+					.parse()
+					.wrap_err_with(|| format!("Error querying rpm file: invalid epoch {epoch}"))?,
+			),
+			None => None,
+		};
+
+		let vendor = rpm.query_field("%{VENDOR}")?.unwrap_or_default();
+		let payload_compressor = rpm.query_field("%{PAYLOADCOMPRESSOR}")?;
+		// Fall back to the vendor if there's no packager.
+		let maintainer = rpm
+			.query_field("%{PACKAGER}")?
+			.unwrap_or_else(|| vendor.clone());
 
+		// Scripts should see the prefix the files end up in.
+		let install_prefix = prefixes.as_deref().map(|p| relocated(p, &args.relocate));
 		let mut scripts = HashMap::new();
 		for script in Script::ALL {
 			let field = rpm.query_field(script.rpm_query_key())?;
-			scripts.insert(script, sanitize_script(&prefixes, field));
+			scripts.insert(script, sanitize_script(&install_prefix, field));
 		}
 
 		let info = PackageInfo {
 			name,
 			version,
+			epoch,
 			release,
 			arch: rpm.query_arch(args.target.as_deref())?,
+			os,
+			maintainer,
+			vendor,
+			url: rpm.query_field("%{URL}")?.unwrap_or_default(),
 			changelog: rpm.query_field("%{CHANGELOGTEXT}")?.unwrap_or_default(),
+			changelog_entries: read_changelog(rpm)?,
+			requires: read_relations(rpm, "REQUIRE")?,
+			provides: read_relations(rpm, "PROVIDE")?,
+			conflicts: read_relations(rpm, "CONFLICT")?,
 			summary,
 			description,
 			scripts,
@@ -99,7 +154,15 @@ impl RpmSource {
 			..Default::default()
 		};
 
-		Ok(Self { info, prefixes })
+		Ok(Self {
+			info,
+			prefixes,
+			relocations: args.relocate.clone(),
+			header,
+			payload_compressor,
+			verbosity: args.verbosity,
+			build_dir: args.build_dir.clone(),
+		})
 	}
 }
 impl SourcePackage for RpmSource {
@@ -114,62 +177,74 @@ impl SourcePackage for RpmSource {
 	}
 
 	fn unpack(&mut self) -> Result<PathBuf> {
-		let work_dir = make_unpack_work_dir(&self.info)?;
+		let work_dir = make_unpack_work_dir(&self.info, self.build_dir.as_deref(), self.verbosity)?;
 
 		let rpm2cpio = || Exec::cmd("rpm2cpio").arg(&self.info.file);
 
-		// Check if we need to use lzma to uncompress the cpio archive
-		let lzma = if let Ok(lzma) = which::which("lzma") {
-			Exec::cmd(lzma)
-		} else {
-			// Some distros don't have `lzma` (such as mine, Fedora)!
-			// `xz --format=lzma` would do just fine however
-			Exec::cmd("xz").arg("--format=lzma")
-		};
-		let cmd = (rpm2cpio() | lzma.arg("-tq")).stdout(NullFile);
-
-		let decomp = if cmd.log_and_output_without_checking(None)?.success() {
-			|| Exec::cmd("lzma").arg("-dq")
-		} else {
-			|| Exec::cmd("cat")
+		// Newer versions of `rpm2cpio` decompress the payload themselves, but
+		// older ones leave it compressed. Check which we're dealing with by
+		// testing the output against the package's compressor.
+		// Packages that don't say are compressed with gzip.
+		let compressor = self.payload_compressor.as_deref().unwrap_or("gzip");
+		let decompressor = decompressor_for(compressor).filter(|&(program, args)| {
+			let test = Exec::cmd(program)
+				.args(args)
+				.args(&["-t", "-q"])
+				.stderr(NullFile);
+			(rpm2cpio() | test)
+				.stdout(NullFile)
+				.log_and_output_without_checking(self.verbosity)
+				.is_ok_and(|c| c.success())
+		});
+		let decomp = || match decompressor {
+			Some((program, args)) => Exec::cmd(program).args(args).args(&["-d", "-q"]),
+			None => Exec::cmd("cat"),
 		};
 
-		let cpio = Exec::cmd("cpio").cwd(&work_dir).args(&[
-			"--extract",
-			"--make-directories",
-			"--no-absolute-filenames",
-			"--preserve-modification-time",
-		]);
-
-		(rpm2cpio() | decomp() | cpio)
-			.log_and_spawn(None)
-			.wrap_err_with(|| format!("Unpacking of {} failed", self.info.file.display()))?;
-
 		// `cpio` does not necessarily store all parent directories in an archive,
 		// and so some directories, if it has to make them and has no permission info,
 		// will come out with some random permissions.
-		// Find those directories and make them mode 755, which is more reasonable.
+		// Remember which files are in the archive to find those directories later.
+		// This also tells us how many files there are to unpack.
 
 		let cpio = Exec::cmd("cpio").args(&["-it", "--quiet"]);
 		let seen_files: HashSet<_> = (rpm2cpio() | decomp() | cpio)
-			.log_and_output(None)
+			.log_and_output(self.verbosity)
 			.wrap_err_with(|| format!("File list of {} failed", self.info.file.display()))?
 			.stdout_str()
 			.lines()
 			.map(PathBuf::from)
 			.collect();
 
-		let cur_dir = std::env::current_dir()?;
-		std::env::set_current_dir(&work_dir)?;
-		// glob doesn't allow you to specify a cwd... annoying, but ok
-		for file in glob::glob("**/*").unwrap() {
-			let file = file?;
-			let new_file = work_dir.join(&file);
-			if !seen_files.contains(&file) && new_file.exists() && !new_file.is_symlink() {
-				chmod(&new_file, 0o755)?;
+		let cpio = Exec::cmd("cpio").cwd(&work_dir).args(&[
+			"--extract",
+			"--make-directories",
+			"--no-absolute-filenames",
+			"--preserve-modification-time",
+		]);
+
+		if Progress::is_enabled() {
+			// `cpio` lists each file it unpacks on stderr.
+			let cpio = cpio.arg("--verbose").stderr(Redirection::Merge);
+			let progress = Progress::new("Unpacking", Some(seen_files.len()));
+			spawn_with_progress(rpm2cpio() | decomp() | cpio, progress, self.verbosity)
+		} else {
+			(rpm2cpio() | decomp() | cpio).log_and_spawn(self.verbosity)
+		}
+		.wrap_err_with(|| format!("Unpacking of {} failed", self.info.file.display()))?;
+
+		// Make the directories `cpio` had to make up mode 755, which is more reasonable.
+		// glob doesn't allow you to specify a cwd, so strip the work dir
+		// off of the results instead of changing into it.
+		for new_file in glob::glob(&work_dir.join("**/*").to_string_lossy())? {
+			let new_file = new_file?;
+			let Ok(file) = new_file.strip_prefix(&work_dir) else {
+				continue;
+			};
+			if !seen_files.contains(file) && new_file.exists() && !new_file.is_symlink() {
+				chmod(&new_file, 0o755, self.verbosity)?;
 			}
 		}
-		std::env::set_current_dir(cur_dir)?;
 
 		// If the package is relocatable, we'd like to move it to be under the `self.prefixes` directory.
 		// However, it's possible that that directory is in the package - it seems some rpm's are marked
@@ -179,24 +254,23 @@ impl SourcePackage for RpmSource {
 		// so we'll just muddle through.
 
 		if let Some(prefixes) = &self.prefixes {
-			let w_prefixes = work_dir.join(prefixes);
+			// The prefix is absolute, but has to be made relative to
+			// the work dir, or else joining would replace it.
+			let rel_prefixes = prefixes.strip_prefix("/").unwrap_or(prefixes);
+			let w_prefixes = work_dir.join(rel_prefixes);
 			if !w_prefixes.exists() {
 				let mut relocate = true;
 
 				// Get the files to move.
 				let pattern = work_dir.join("*");
-				let file_list: Vec<_> = glob::glob(&pattern.to_string_lossy())
-					.unwrap()
+				let file_list: Vec<_> = glob::glob(&pattern.to_string_lossy())?
 					.filter_map(|p| p.ok())
 					.collect();
 
 				// Now, make the destination directory.
-				let mut dest = PathBuf::new();
+				let mut dest = work_dir.clone();
 
-				for comp in prefixes.components() {
-					if comp == Component::CurDir {
-						dest.push("/");
-					}
+				for comp in rel_prefixes.components() {
 					dest.push(comp);
 
 					if dest.is_dir() {
@@ -206,7 +280,7 @@ impl SourcePackage for RpmSource {
 						relocate = false;
 						break;
 					}
-					mkdir(&dest)?;
+					mkdir(&dest, self.verbosity)?;
 				}
 
 				if relocate {
@@ -225,49 +299,80 @@ impl SourcePackage for RpmSource {
 			}
 		}
 
+		// Then move anything the user asked to be relocated.
+		for (old, new) in &self.relocations {
+			if !self.prefixes.as_deref().is_some_and(|p| old.starts_with(p)) {
+				eprintln!(
+					"Warning: {} is not a relocatable prefix of {}; relocating it anyway.",
+					old.display(),
+					self.info.file.display()
+				);
+			}
+			relocate_tree(&work_dir, old, new)?;
+		}
+		if !self.relocations.is_empty() {
+			for file in self.info.files.iter_mut().chain(&mut self.info.conffiles) {
+				*file = relocated(file, &self.relocations);
+			}
+		}
+
 		// `rpm` files have two sets of permissions; the set in the cpio archive,
 		// and the set in the control data, which override the set in the archive.
 		// The set in the control data are more correct, so let's use those.
 		// Some permissions setting may have to be postponed until the postinst.
 
-		let out = Exec::cmd("rpm")
-			.args(&[
-				"--queryformat",
-				r#"[%{FILEMODES} %{FILEUSERNAME} %{FILEGROUPNAME} %{FILENAMES}\n]"#,
-				"-qp",
-			])
-			.arg(&self.info.file)
-			.log_and_output(None)?
-			.stdout_str();
+		let reader = RpmReader::new(&self.info.file, self.verbosity);
+		let rpm: &dyn RpmQuery = match &self.header {
+			Some(header) => header,
+			None => &reader,
+		};
+		let file_attrs = rpm.query_file_attrs()?;
+
+		// Capabilities are kept in the header instead of the payload.
+		// Older versions of `rpm` don't know about them at all.
+		let file_caps = rpm.query_array("%{FILECAPS}").unwrap_or_default();
+		for (attrs, caps) in file_attrs.iter().zip(file_caps) {
+			if let Some(caps) = caps::from_text(&caps) {
+				let file = relocated(&attrs.path, &self.relocations);
+				self.info.capabilities.insert(file, caps);
+			}
+		}
+		let file_flags = rpm.query_array("%{FILEFLAGS}")?;
+		for (attrs, flags) in file_attrs.iter().zip(file_flags) {
+			let flags = rpm_file_flags(flags.parse()?);
+			if !flags.is_empty() {
+				let file = relocated(&attrs.path, &self.relocations);
+				self.info.file_flags.insert(file, flags);
+			}
+		}
 
 		let mut owninfo: HashMap<PathBuf, FileInfo> = HashMap::new();
 
-		for line in out.lines() {
-			let mut line = line.split(' ');
-			let Some(mode) = line.next() else { continue; };
-			let Some(owner) = line.next() else { continue; };
-			let Some(group) = line.next() else { continue; };
-			let Some(file) = line.next() else { continue; };
-
-			let mut mode: u32 = mode.parse()?;
-			mode &= 0o7777; // remove filetype
+		for RpmFileAttrs {
+			mode,
+			owner,
+			group,
+			path: file,
+		} in file_attrs
+		{
+			let mode = mode & 0o7777; // remove filetype
+			let file = relocated(&file, &self.relocations);
 
-			let file = PathBuf::from(file);
 			let file_info = owninfo.entry(file.clone()).or_default();
 
 			// TODO: this is not gonna work on windows, is it
-			let user_id = match User::from_name(owner)? {
+			let user_id = match User::from_name(&owner)? {
 				Some(User { uid, .. }) if uid.is_root() => uid,
 				_ => {
-					file_info.owner = owner.to_owned();
+					file_info.owner = owner;
 					Uid::from_raw(0)
 				}
 			};
-			let group_id = match Group::from_name(group)? {
+			let group_id = match Group::from_name(&group)? {
 				Some(Group { gid, .. }) if gid.as_raw() == 0 => gid,
 				_ => {
 					file_info.owner.push(':');
-					file_info.owner.push_str(group);
+					file_info.owner.push_str(&group);
 					Gid::from_raw(0)
 				}
 			};
@@ -286,7 +391,7 @@ impl SourcePackage for RpmSource {
 						format!("failed chowning {} to {user_id}:{group_id}", file.display())
 					})?;
 				}
-				chmod(&file, mode)
+				chmod(&file, mode, self.verbosity)
 					.wrap_err_with(|| format!("failed chowning {} to {mode}", file.display()))?;
 			}
 		}
@@ -296,6 +401,44 @@ impl SourcePackage for RpmSource {
 }
 
 //= Utilities
+
+/// Maps `path` to where it should be installed, following the first of
+/// `relocations` whose old prefix it is under.
+fn relocated(path: &Path, relocations: &[(PathBuf, PathBuf)]) -> PathBuf {
+	relocations
+		.iter()
+		.find_map(|(old, new)| path.strip_prefix(old).ok().map(|rest| new.join(rest)))
+		.unwrap_or_else(|| path.to_owned())
+}
+
+/// Moves the files under `old` in the unpacked directory to `new`.
+fn relocate_tree(work_dir: &Path, old: &Path, new: &Path) -> Result<()> {
+	// Both prefixes are absolute, and have to be made relative to the work dir.
+	let from = work_dir.join(old.strip_prefix("/").unwrap_or(old));
+	let to = work_dir.join(new.strip_prefix("/").unwrap_or(new));
+	if !from.exists() {
+		eprintln!(
+			"Warning: the package has no files under {}, so nothing was relocated.",
+			old.display()
+		);
+		return Ok(());
+	}
+
+	if to.exists() {
+		let contents = std::fs::read_dir(&from)?
+			.map(|e| e.map(|e| e.path()))
+			.collect::<std::io::Result<Vec<_>>>()?;
+		fs_extra::move_items(&contents, &to, &CopyOptions::new())?;
+		std::fs::remove_dir(&from)?;
+	} else {
+		if let Some(parent) = to.parent() {
+			std::fs::create_dir_all(parent)?;
+		}
+		std::fs::rename(&from, &to)?;
+	}
+	Ok(())
+}
+
 pub trait QueryModifier {
 	fn modify_query(self, exec: Exec) -> Exec;
 }
@@ -315,12 +458,130 @@ where
 	}
 }
 
+/// The ownership and permissions `rpm` records for a file in its control data.
+#[derive(Debug, Clone)]
+pub(crate) struct RpmFileAttrs {
+	pub mode: u32,
+	pub owner: String,
+	pub group: String,
+	pub path: PathBuf,
+}
+
+/// Fails if `rpm` is a source rpm, since converting one would produce
+/// a package without any of the files that would be built from it.
+pub(crate) fn ensure_binary(rpm: &dyn RpmQuery, file: &Path) -> Result<()> {
+	if rpm.is_source()? {
+		bail!(
+			"{} is a source rpm, and source rpms are not supported. Build it with `rpmbuild --rebuild` first.",
+			file.display()
+		);
+	}
+	Ok(())
+}
+
+/// Checks that the package was built for Linux, returning the operating
+/// system it was built for.
+///
+/// Packages for other operating systems are only let through, with a
+/// warning, if `allow_foreign_os` is set.
+pub(crate) fn ensure_linux(
+	rpm: &dyn RpmQuery,
+	file: &Path,
+	allow_foreign_os: bool,
+) -> Result<Option<String>> {
+	let os = rpm.query_field("%{OS}")?;
+	if let Some(os) = os.as_deref().filter(|os| !os.eq_ignore_ascii_case("linux")) {
+		if !allow_foreign_os {
+			bail!(
+				"{} is built for {os}, not Linux, and would not work once converted. Use --allow-foreign-os to convert it anyway.",
+				file.display()
+			);
+		}
+		eprintln!(
+			"Warning: {} is built for {os}, not Linux. The converted package will probably not work.",
+			file.display()
+		);
+	}
+	Ok(os)
+}
+
+/// Finds the program and arguments that decompress a payload compressed with
+/// `compressor`, as named in the `PAYLOADCOMPRESSOR` header tag.
+///
+/// Returns [`None`] for uncompressed payloads and compressors we don't know.
+fn decompressor_for(compressor: &str) -> Option<(&'static str, &'static [&'static str])> {
+	match compressor {
+		"gzip" => Some(("gzip", &[])),
+		"bzip2" => Some(("bzip2", &[])),
+		"xz" => Some(("xz", &[])),
+		// Some distros don't have `lzma` (such as mine, Fedora)!
+		// `xz --format=lzma` would do just fine however
+		"lzma" => Some(("xz", &["--format=lzma"])),
+		"zstd" => Some(("zstd", &[])),
+		_ => None,
+	}
+}
+
+/// Picks the flags `xenomorph` keeps out of the `RPMFILE_*` bits of
+/// an rpm's `FILEFLAGS` tag, as defined in `rpmfiles.h`.
+fn rpm_file_flags(bits: u32) -> BitFlags<FileFlag> {
+	[
+		(1 << 1, FileFlag::Doc),
+		(1 << 4, FileFlag::NoReplace),
+		(1 << 6, FileFlag::Ghost),
+		(1 << 7, FileFlag::License),
+	]
+	.into_iter()
+	.filter(|&(bit, _)| bits & bit != 0)
+	.map(|(_, flag)| flag)
+	.collect()
+}
+
+/// The metadata queries needed to convert an rpm package, answered either
+/// by the `rpm` tool or by reading the package header directly.
+pub(crate) trait RpmQuery {
+	/// Queries a single header field in `rpm`'s query format, e.g. `%{NAME}`.
+	fn query_field(&self, name: &str) -> Result<Option<String>>;
+	/// Queries all values of an array header field, e.g. `%{CHANGELOGTEXT}`.
+	fn query_array(&self, name: &str) -> Result<Vec<String>>;
+	/// Queries a translatable header field, e.g. `%{SUMMARY}`, in the first
+	/// locale in `lang` (a colon-separated list, as in `$LANGUAGE`) that
+	/// it's translated to. Untranslated if there's no such locale, or no `lang`.
+	fn query_localized(&self, name: &str, lang: Option<&str>) -> Result<Option<String>>;
+	fn query_files(&self) -> Result<Vec<PathBuf>>;
+	fn query_conffiles(&self) -> Result<Vec<PathBuf>>;
+	/// Human-readable information about the package, as in `rpm -qi`.
+	fn query_info(&self) -> Result<String>;
+	fn query_file_attrs(&self) -> Result<Vec<RpmFileAttrs>>;
+
+	/// Whether this is a source rpm, which holds sources and a spec file
+	/// instead of files to install.
+	fn is_source(&self) -> Result<bool> {
+		// Binary rpms record which source rpm they were built from.
+		if self.query_field("%{SOURCERPM}")?.is_none() {
+			return Ok(true);
+		}
+		let arch = self.query_field("%{ARCH}")?;
+		Ok(matches!(arch.as_deref(), Some("src" | "nosrc")))
+	}
+
+	fn query_arch(&self, target: Option<&str>) -> Result<String> {
+		if let Some(arch) = target {
+			Ok(rpm_arch_to_deb(arch).to_owned())
+		} else {
+			let arch = self.query_field("%{ARCH}")?.unwrap_or_default();
+			Ok(rpm_arch_to_deb(&arch).to_owned())
+		}
+	}
+}
+
 pub(crate) struct RpmReader<'r> {
 	file: &'r Path,
+	verbosity: Verbosity,
 }
 impl<'r> RpmReader<'r> {
-	pub fn new(file: &'r Path) -> Self {
-		Self { file }
+	pub fn new(file: &'r Path, verbosity: Verbosity) -> Self {
+		Self { file, verbosity }
 	}
 	pub fn query(&self, flag: &str) -> Result<String> {
 		self.query_with(|e| e.arg(flag))
@@ -329,7 +590,10 @@ impl<'r> RpmReader<'r> {
 		let exec = Exec::cmd("rpm").env("LANG", "C").arg("-qp");
 		let exec = modifier(exec);
 
-		Ok(exec.arg(self.file).log_and_output(None)?.stdout_str())
+		Ok(exec
+			.arg(self.file)
+			.log_and_output(self.verbosity)?
+			.stdout_str())
 	}
 	pub fn query_file_list(&self, flag: &str) -> Result<Vec<PathBuf>> {
 		let mut files: Vec<_> = self
@@ -344,41 +608,135 @@ impl<'r> RpmReader<'r> {
 		}
 		Ok(files)
 	}
-	pub fn query_field(&self, name: &str) -> Result<Option<String>> {
+}
+impl RpmQuery for RpmReader<'_> {
+	fn query_field(&self, name: &str) -> Result<Option<String>> {
 		let res = self.query_with(|e| e.arg("--queryformat").arg(name))?;
 
 		Ok(if res == "(none)" { None } else { Some(res) })
 	}
-	pub fn query_arch(&self, target: Option<&str>) -> Result<String> {
-		if let Some(arch) = target {
-			Ok(Self::map_arch(arch).to_owned())
-		} else {
-			let arch = self.query_field("%{ARCH}")?.unwrap_or_default();
-			Ok(Self::map_arch(&arch).to_owned())
+	fn query_array(&self, name: &str) -> Result<Vec<String>> {
+		// Values may span multiple lines, so separate them with the ASCII record separator instead.
+		let res = self.query_with(|e| e.arg("--queryformat").arg(format!("[{name}\x1e]")))?;
+
+		Ok(res
+			.split_terminator('\x1e')
+			.filter(|s| *s != "(none)")
+			.map(str::to_owned)
+			.collect())
+	}
+	fn query_localized(&self, name: &str, lang: Option<&str>) -> Result<Option<String>> {
+		let Some(lang) = lang else {
+			return self.query_field(name);
+		};
+		// `rpm` picks translations by `$LANGUAGE`, like gettext does.
+		let res = self.query_with(|e| e.env("LANGUAGE", lang).arg("--queryformat").arg(name))?;
+
+		Ok(if res == "(none)" { None } else { Some(res) })
+	}
+	fn query_files(&self) -> Result<Vec<PathBuf>> {
+		self.query_file_list("-l")
+	}
+	fn query_conffiles(&self) -> Result<Vec<PathBuf>> {
+		self.query_file_list("-c")
+	}
+	fn query_info(&self) -> Result<String> {
+		self.query("-i")
+	}
+	fn query_file_attrs(&self) -> Result<Vec<RpmFileAttrs>> {
+		let out = self.query_with(|e| {
+			e.arg("--queryformat")
+				.arg(r#"[%{FILEMODES} %{FILEUSERNAME} %{FILEGROUPNAME} %{FILENAMES}\n]"#)
+		})?;
+
+		let mut attrs = vec![];
+		for line in out.lines() {
+			// File names may contain spaces, so only split off the first three fields.
+			let mut line = line.splitn(4, ' ');
+			let (Some(mode), Some(owner), Some(group), Some(path)) =
+				(line.next(), line.next(), line.next(), line.next())
+			else {
+				continue;
+			};
+			attrs.push(RpmFileAttrs {
+				mode: mode.parse()?,
+				owner: owner.to_owned(),
+				group: group.to_owned(),
+				path: PathBuf::from(path),
+			});
 		}
+		Ok(attrs)
 	}
-	pub fn map_arch(arch: &str) -> &str {
-		match arch.as_bytes() {
-			// NOTE(pluie): do NOT ask me where these numbers came from.
-			// I have NO clue.
-			b"1" => "i386",
-			b"2" => "alpha",
-			b"3" => "sparc",
-			b"6" => "m68k",
-			b"noarch" => "all",
-			b"ppc" => "powerpc",
-			b"x86_64" | b"em64t" => "amd64",
-			b"armv4l" => "arm",
-			b"armv7l" => "armel",
-			b"parisc" => "hppa",
-			b"ppc64le" => "ppc64el",
-
-			// Treat 486, 586, etc, and Pentium, as 386.
-			o if o.eq_ignore_ascii_case(b"pentium") => "i386",
-			&[b'i' | b'I', b'0'..=b'9', b'8', b'6'] => "i386",
-
-			_ => arch,
+}
+
+/// Reads the changelog entries of an rpm, which are stored as three
+/// parallel arrays of authors, times and texts.
+fn read_changelog(rpm: &dyn RpmQuery) -> Result<Vec<ChangelogEntry>> {
+	let authors = rpm.query_array("%{CHANGELOGNAME}")?;
+	let times = rpm.query_array("%{CHANGELOGTIME}")?;
+	let texts = rpm.query_array("%{CHANGELOGTEXT}")?;
+
+	let mut entries = vec![];
+	for ((author, time), text) in authors.into_iter().zip(times).zip(texts) {
+		let time = time
+			.parse()
+			.wrap_err_with(|| format!("Error querying rpm file: invalid changelog time {time}"))?;
+		entries.push(ChangelogEntry {
+			author,
+			date: OffsetDateTime::from_unix_timestamp(time)?,
+			text,
+		});
+	}
+	Ok(entries)
+}
+
+/// The comparison bits of rpm's dependency flags, from `rpmds.h`.
+const RPMSENSE_LESS: u64 = 1 << 1;
+const RPMSENSE_GREATER: u64 = 1 << 2;
+const RPMSENSE_EQUAL: u64 = 1 << 3;
+
+/// Reads one kind of relation (`REQUIRE`, `PROVIDE` or `CONFLICT`) of an rpm,
+/// which are stored as three parallel arrays of names, flags and versions.
+fn read_relations(rpm: &dyn RpmQuery, kind: &str) -> Result<Vec<Relation>> {
+	let names = rpm.query_array(&format!("%{{{kind}NAME}}"))?;
+	let flags = rpm.query_array(&format!("%{{{kind}FLAGS}}"))?;
+	let versions = rpm.query_array(&format!("%{{{kind}VERSION}}"))?;
+	relations(names, &flags, versions)
+}
+
+/// Pairs up the names, flags and versions of relations, leaving out the
+/// `rpmlib(...)` requirements that only mean something to `rpm` itself.
+fn relations(names: Vec<String>, flags: &[String], versions: Vec<String>) -> Result<Vec<Relation>> {
+	let mut relations = vec![];
+	for ((name, flags), version) in names.into_iter().zip(flags).zip(versions) {
+		if name.starts_with("rpmlib(") {
+			continue;
 		}
+		let flags: u64 = flags
+			.parse()
+			.wrap_err_with(|| format!("Error querying rpm file: invalid relation flags {flags}"))?;
+		let op = rpm_sense_op(flags);
+		relations.push(Relation {
+			name,
+			version: if op.is_some() { version } else { String::new() },
+			op,
+		});
+	}
+	Ok(relations)
+}
+
+/// Translates the comparison in rpm's dependency flags.
+fn rpm_sense_op(flags: u64) -> Option<VersionOp> {
+	let less = flags & RPMSENSE_LESS != 0;
+	let greater = flags & RPMSENSE_GREATER != 0;
+	let equal = flags & RPMSENSE_EQUAL != 0;
+	match (less, equal, greater) {
+		(true, false, false) => Some(VersionOp::Less),
+		(true, true, false) => Some(VersionOp::LessEqual),
+		(false, true, false) => Some(VersionOp::Equal),
+		(false, true, true) => Some(VersionOp::GreaterEqual),
+		(false, false, true) => Some(VersionOp::Greater),
+		_ => None,
 	}
 }
 
@@ -420,3 +778,96 @@ fn sanitize_script(prefixes: &Option<PathBuf>, s: Option<String>) -> String {
 	}
 	format!("#!/bin/bash\n{prefix_code}{}", s.unwrap_or_default())
 }
+
+#[cfg(test)]
+mod tests {
+	use std::path::{Path, PathBuf};
+
+	use super::{decompressor_for, relations, relocate_tree, relocated, rpm_sense_op};
+	use crate::{Relation, VersionOp};
+
+	#[test]
+	fn test_decompressor_for() {
+		assert_eq!(decompressor_for("gzip"), Some(("gzip", &[][..])));
+		assert_eq!(decompressor_for("bzip2"), Some(("bzip2", &[][..])));
+		assert_eq!(decompressor_for("xz"), Some(("xz", &[][..])));
+		assert_eq!(
+			decompressor_for("lzma"),
+			Some(("xz", &["--format=lzma"][..]))
+		);
+		assert_eq!(decompressor_for("zstd"), Some(("zstd", &[][..])));
+		assert_eq!(decompressor_for("identity"), None);
+	}
+
+	#[test]
+	fn test_relocate() -> eyre::Result<()> {
+		let work_dir = tempfile::tempdir()?;
+		let lib_dir = work_dir.path().join("usr/lib/xenomorph");
+		std::fs::create_dir_all(&lib_dir)?;
+		std::fs::write(lib_dir.join("xenomorph.conf"), "")?;
+
+		let relocations = [(
+			PathBuf::from("/usr/lib/xenomorph"),
+			PathBuf::from("/opt/xenomorph"),
+		)];
+		relocate_tree(work_dir.path(), &relocations[0].0, &relocations[0].1)?;
+
+		assert!(!lib_dir.exists());
+		assert!(work_dir
+			.path()
+			.join("opt/xenomorph/xenomorph.conf")
+			.is_file());
+
+		assert_eq!(
+			relocated(Path::new("/usr/lib/xenomorph/xenomorph.conf"), &relocations),
+			Path::new("/opt/xenomorph/xenomorph.conf")
+		);
+		assert_eq!(
+			relocated(Path::new("/usr/lib/xenomorph2"), &relocations),
+			Path::new("/usr/lib/xenomorph2")
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_rpm_sense_op() {
+		assert_eq!(rpm_sense_op(0), None);
+		assert_eq!(rpm_sense_op(0x02), Some(VersionOp::Less));
+		assert_eq!(rpm_sense_op(0x0a), Some(VersionOp::LessEqual));
+		assert_eq!(rpm_sense_op(0x08), Some(VersionOp::Equal));
+		assert_eq!(rpm_sense_op(0x0c), Some(VersionOp::GreaterEqual));
+		assert_eq!(rpm_sense_op(0x04), Some(VersionOp::Greater));
+		// Flags for when the requirement applies don't affect the comparison.
+		assert_eq!(rpm_sense_op(0x0100_000c), Some(VersionOp::GreaterEqual));
+	}
+
+	#[test]
+	fn test_relations_skip_rpmlib() -> eyre::Result<()> {
+		let names = vec![
+			"rpmlib(CompressedFileNames)".to_owned(),
+			"glibc".to_owned(),
+			"/bin/sh".to_owned(),
+		];
+		let flags = ["16777226".to_owned(), "12".to_owned(), "0".to_owned()];
+		let versions = vec!["3.0.4-1".to_owned(), "2.34".to_owned(), String::new()];
+
+		assert_eq!(
+			relations(names, &flags, versions)?,
+			[
+				Relation {
+					name: "glibc".into(),
+					op: Some(VersionOp::GreaterEqual),
+					version: "2.34".into(),
+				},
+				Relation {
+					name: "/bin/sh".into(),
+					op: None,
+					version: String::new(),
+				},
+			]
+		);
+
+		Ok(())
+	}
+}
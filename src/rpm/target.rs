@@ -2,59 +2,52 @@ use std::{
 	fmt::Write as _,
 	fs::File,
 	io::Write,
+	os::unix::ffi::OsStrExt,
 	path::{Path, PathBuf},
 };
 
 use base64::Engine;
 use eyre::{bail, Result};
 use subprocess::{Exec, Redirection};
-
-use crate::{util::ExecExt, PackageInfo, Script, TargetPackage};
+use time::OffsetDateTime;
+
+use super::{deb_arch_to_rpm, deb_section_to_rpm_group};
+use crate::{
+	caps,
+	deb::split_relation,
+	util::{check_arch_independent, fetch_build_date, fetch_email_address, ExecExt, Verbosity},
+	Args, FileFlag, FileInfo, Format, PackageInfo, Script, TargetPackage,
+};
 
 #[derive(Debug)]
 pub struct RpmTarget {
 	pub(crate) info: PackageInfo,
 	unpacked_dir: PathBuf,
 	spec: PathBuf,
+	verbosity: Verbosity,
 }
 impl RpmTarget {
-	pub fn new(mut info: PackageInfo, unpacked_dir: PathBuf) -> Result<Self> {
+	pub fn new(mut info: PackageInfo, unpacked_dir: PathBuf, args: &Args) -> Result<Self> {
 		Self::sanitize_info(&mut info);
-
-		let mut file_list = String::new();
-		for filename in &info.files {
-			// DIFFERENCE WITH THE PERL VERSION:
-			// `snailquote` doesn't escape the same characters as Perl, but that difference
-			// is negligible at best - feel free to implement Perl-style escaping if you want to.
-			// The list of escape sequences is in `perlop`.
-
-			// Unquote any escaped characters in filenames - needed for non ascii characters.
-			// (eg. iso_8859-1 latin set)
-			let unquoted = snailquote::unescape(&filename.to_string_lossy())?;
-
-			if unquoted.ends_with('/') {
-				file_list.push_str("%dir ");
-			} else if info
-				.conffiles
-				.iter()
-				.any(|f| f.as_os_str() == unquoted.as_str())
-			{
-				// it's a conffile
-				file_list.push_str("%config ");
-			}
-			// Note all filenames are quoted in case they contain spaces.
-			writeln!(file_list, r#""{unquoted}""#)?;
+		if args.check_arch {
+			check_arch_independent(&info, &unpacked_dir, args.verbosity)?;
 		}
 
+		let file_list = Self::file_list(&info, &unpacked_dir, args.strict_files, args.verbosity)?;
+		let group = rpm_group(&info, args);
+		let distribution = args.distribution.as_ref().unwrap_or(&info.distribution);
+		let provenance = provenance(distribution, args.build_host.as_deref(), args.reproducible);
+
 		let PackageInfo {
 			name,
 			version,
+			epoch,
 			release,
 			dependencies: depends,
 			summary,
 			copyright,
-			distribution,
-			group,
+			vendor,
+			url,
 			use_scripts,
 			scripts,
 			description,
@@ -70,22 +63,39 @@ impl RpmTarget {
 
 		let mut build_root = std::env::current_dir()?;
 		build_root.push(&unpacked_dir);
+		let rpm_version = rpm_version(args.verbosity);
+
+		// rpmbuild runs inside the unpacked directory, so the output directory
+		// has to be absolute.
+		let mut rpm_dir = std::env::current_dir()?;
+		rpm_dir.push(&args.output_dir);
 
 		#[rustfmt::skip]
 		write!(
 			spec_file,
-r#"Buildroot: {build_root}
-Name: {name}
+r#"{preamble}Name: {name}
 Version: {version}
 Release: {release}
 "#,
-			build_root = build_root.display(),
+			preamble = preamble(&build_root, rpm_version),
 		)?;
+		if let Some(epoch) = epoch {
+			writeln!(spec_file, "Epoch: {epoch}")?;
+		}
+		if let Some(maintainer) = &args.maintainer {
+			writeln!(spec_file, "Packager: {maintainer}")?;
+		}
+		if !vendor.is_empty() {
+			writeln!(spec_file, "Vendor: {vendor}")?;
+		}
+		if !url.is_empty() {
+			writeln!(spec_file, "URL: {url}")?;
+		}
 
 		if let [first, rest @ ..] = &depends[..] {
-			write!(spec_file, "Requires: {first}",)?;
+			write!(spec_file, "Requires: {}", rpm_dependency(first))?;
 			for dep in rest {
-				write!(spec_file, ", {dep}")?;
+				write!(spec_file, ", {}", rpm_dependency(dep))?;
 			}
 			writeln!(spec_file)?;
 		}
@@ -95,14 +105,14 @@ Release: {release}
 			spec_file,
 r#"Summary: {summary}
 License: {copyright}
-Distribution: {distribution}
-Group: Converted/{group}
+{provenance}Group: {group}
 
-%define _rpmdir ../
+%define _rpmdir {rpm_dir}
 %define _rpmfilename %%{{NAME}}-%%{{VERSION}}-%%{{RELEASE}}.%%{{ARCH}}.rpm
 %define _unpackaged_files_terminate_build 0
 
 "#,
+			rpm_dir = rpm_dir.display(),
 		)?;
 
 		if *use_scripts {
@@ -114,30 +124,30 @@ Group: Converted/{group}
 				write!(spec_file, "{name}\n{script}\n\n")?;
 			}
 		}
-		#[rustfmt::skip]
-		write!(
-			spec_file,
-r#"%description
-{description}
+		writeln!(spec_file, "%description\n{description}\n")?;
+		if !args.no_conversion_notice {
+			writeln!(
+				spec_file,
+				"(Converted from a {original_format} package by `xenomorph` version {}.)\n",
+				env!("CARGO_PKG_VERSION")
+			)?;
+		}
+		write!(spec_file, "%files\n{file_list}")?;
 
-(Converted from a {original_format} package by `xenomorph` version {xenomorph_version}.)
-
-%files
-{file_list}"#,
-			xenomorph_version = env!("CARGO_PKG_VERSION")
-		)?;
+		write_changelog(&mut spec_file, &info, args)?;
 
 		Ok(Self {
 			info,
 			unpacked_dir,
 			spec,
+			verbosity: args.verbosity,
 		})
 	}
 
-	pub(crate) fn build_with(&mut self, cmd: &Path) -> Result<PathBuf> {
+	pub(crate) fn build_with(&mut self, cmd: &Path, output_dir: &Path) -> Result<PathBuf> {
 		let rpmdir = Exec::cmd("rpm")
 			.arg("--showrc")
-			.log_and_output(None)?
+			.log_and_output(self.verbosity)?
 			.stdout_str()
 			.lines()
 			.find_map(|l| {
@@ -157,7 +167,7 @@ r#"%description
 			..
 		} = &self.info;
 
-		let rpm = format!("{name}-{version}-{release}.{arch}.rpm");
+		let rpm = file_name(&self.info);
 
 		let (rpm, arch_flag) = if let Some(rpmdir) = rpmdir {
 			// Old versions of rpm toss it off in te middle of nowhere.
@@ -169,12 +179,12 @@ r#"%description
 			// output rpmdir in any format I'd care to try to parse.
 			// Instead, rpm is now of a late enough version to notice the
 			// %define's in the spec file, which will make the file end up
-			// in the directory we started in.
+			// in the output directory.
 			// Anyway, let's assume this is version 3 or above.
 
 			// This is the new command line argument to set the arch rpms.
 			// It appeared in rpm version 3.
-			(PathBuf::from(rpm), "--target")
+			(output_dir.join(rpm), "--target")
 		};
 
 		let mut build_root = std::env::current_dir()?;
@@ -197,7 +207,7 @@ r#"%description
 		cmd = cmd.arg(format!("{name}-{version}-{release}.spec"));
 
 		let cmdline = cmd.to_cmdline_lossy();
-		let out = cmd.log_and_output_without_checking(None)?;
+		let out = cmd.log_and_output_without_checking(self.verbosity)?;
 
 		if !out.success() {
 			bail!(
@@ -209,7 +219,70 @@ r#"%description
 		Ok(rpm)
 	}
 
-	fn sanitize_info(info: &mut PackageInfo) {
+	/// Builds the `%files` section of the spec file.
+	pub(crate) fn file_list(
+		info: &PackageInfo,
+		unpacked_dir: &Path,
+		strict_files: bool,
+		verbosity: Verbosity,
+	) -> Result<String> {
+		let mut file_list = String::new();
+		let mut missing_files = vec![];
+		for filename in &info.files {
+			// The file list comes from the source package, and may list files that
+			// were never unpacked (such as ghost files), which rpmbuild chokes on.
+			// Ghost files are never in a package to begin with, which is fine.
+			let flags = info.file_flags.get(filename).copied().unwrap_or_default();
+			let relative = filename.strip_prefix("/").unwrap_or(filename);
+			if std::fs::symlink_metadata(unpacked_dir.join(relative)).is_err()
+				&& !flags.contains(FileFlag::Ghost)
+			{
+				missing_files.push(filename.display().to_string());
+				continue;
+			}
+
+			if let Some(file_info) = info.file_info.get(filename) {
+				// Restore ownership and modes that couldn't be represented on disk.
+				write!(file_list, "{} ", attr(file_info))?;
+			}
+			if let Some(caps) = info
+				.capabilities
+				.get(filename)
+				.and_then(|c| caps::to_text(c))
+			{
+				write!(file_list, "%caps({caps}) ")?;
+			}
+
+			for (flag, directive) in [
+				(FileFlag::Doc, "%doc "),
+				(FileFlag::License, "%license "),
+				(FileFlag::Ghost, "%ghost "),
+			] {
+				if flags.contains(flag) {
+					file_list.push_str(directive);
+				}
+			}
+
+			if filename.as_os_str().as_bytes().ends_with(b"/") || info.dirs.contains(filename) {
+				file_list.push_str("%dir ");
+			} else if info.conffiles.contains(filename) {
+				// it's a conffile
+				if flags.contains(FileFlag::NoReplace) {
+					file_list.push_str("%config(noreplace) ");
+				} else {
+					file_list.push_str("%config ");
+				}
+			}
+			writeln!(file_list, "{}", spec_file_name(filename))?;
+		}
+		if !missing_files.is_empty() {
+			report_missing_files(&missing_files, strict_files, verbosity)?;
+		}
+
+		Ok(file_list)
+	}
+
+	pub(crate) fn sanitize_info(info: &mut PackageInfo) {
 		// When retrieving scripts for building, we have to do some truly sick mangling.
 		// Since debian/slackware scripts can be anything -- perl programs or binary files --
 		// and rpm is limited to only shell scripts, we need to encode the files and add a
@@ -247,19 +320,18 @@ rmdir /tmp/xenomorph.$$
 			*script = patched;
 		}
 
-		info.version = info.version.replace('-', "_");
-
-		let arch = match info.arch.as_str() {
-			"amd64" => Some("x86_64"),
-			"powerpc" => Some("ppc"), // XXX is this the canonical name for powerpc on rpm systems?
-			"hppa" => Some("parisc"),
-			"all" => Some("noarch"),
-			"ppc64el" => Some("ppc64le"),
-			_ => None,
+		// rpm doesn't allow dashes in versions, but versions coming from rpm
+		// are kept as they were, so converting back and forth loses nothing.
+		let version = if matches!(info.original_format, Format::Rpm | Format::Lsb)
+			&& !info.original_version.is_empty()
+		{
+			&info.original_version
+		} else {
+			&info.version
 		};
-		if let Some(arch) = arch {
-			info.arch = arch.to_owned();
-		}
+		info.version = version.replace('-', "_");
+
+		info.arch = deb_arch_to_rpm(&info.arch).to_owned();
 	}
 }
 
@@ -268,7 +340,585 @@ impl TargetPackage for RpmTarget {
 		let _ignore = std::fs::remove_file(&self.spec);
 		Ok(())
 	}
-	fn build(&mut self) -> Result<PathBuf> {
-		self.build_with(Path::new("rpmbuild"))
+	fn build(&mut self, output_dir: &Path) -> Result<PathBuf> {
+		self.build_with(Path::new("rpmbuild"), output_dir)
+	}
+	fn test(&mut self, file_name: &Path) -> Result<Vec<String>> {
+		let Ok(rpmlint) = which::which("rpmlint") else {
+			return Ok(vec!["rpmlint not available, so not testing".into()]);
+		};
+
+		// rpmlint fails if it finds any errors, which is what we're asking for.
+		let output = Exec::cmd(rpmlint)
+			.arg(file_name)
+			.log_and_output_without_checking(self.verbosity)?
+			.stdout_str();
+
+		Ok(filter_rpmlint_output(&output))
+	}
+}
+
+/// The name of the file the package is built into.
+pub(crate) fn file_name(info: &PackageInfo) -> String {
+	let PackageInfo {
+		name,
+		version,
+		release,
+		arch,
+		..
+	} = info;
+	format!("{name}-{version}-{release}.{arch}.rpm")
+}
+
+/// Picks the results out of `rpmlint`'s output, leaving out complaints about
+/// things every converted package does.
+fn filter_rpmlint_output(output: &str) -> Vec<String> {
+	// Converted packages don't have their documentation split out, and keep
+	// whatever license name the original package used.
+	const NOISE: &[&str] = &["no-documentation", "invalid-license"];
+
+	output
+		.lines()
+		.map(str::trim)
+		.filter(|s| !s.is_empty())
+		.filter(|s| !NOISE.iter().any(|noise| s.contains(noise)))
+		.map(str::to_owned)
+		.collect()
+}
+
+/// Picks the group of the generated package, mapping Debian sections to
+/// standard rpm groups where possible.
+fn rpm_group(info: &PackageInfo, args: &Args) -> String {
+	match &args.group {
+		Some(group) => group.clone(),
+		None if info.original_format == Format::Deb => deb_section_to_rpm_group(&info.group),
+		None => format!("Converted/{}", info.group),
+	}
+}
+
+/// The first version of rpm to ignore `BuildRoot`, and warn about it.
+const BUILDROOT_IGNORED_SINCE: (u32, u32) = (4, 6);
+
+/// Gives the lines at the top of the spec file, which set up the build.
+///
+/// The build root is only given to versions of rpm older than
+/// [`BUILDROOT_IGNORED_SINCE`], or if the version isn't known.
+fn preamble(build_root: &Path, rpm_version: Option<(u32, u32)>) -> String {
+	// Converted binaries are often stripped already, and rpmbuild fails
+	// trying to find debug symbols in them.
+	let mut lines = "%global debug_package %{nil}\n".to_owned();
+	if !matches!(rpm_version, Some(v) if v >= BUILDROOT_IGNORED_SINCE) {
+		_ = writeln!(lines, "Buildroot: {}", build_root.display());
+	}
+	lines
+}
+
+/// Asks the installed rpm for its major and minor version.
+fn rpm_version(verbosity: Verbosity) -> Option<(u32, u32)> {
+	let output = Exec::cmd("rpm")
+		.arg("--version")
+		.log_and_output(verbosity)
+		.ok()?;
+	parse_rpm_version(&output.stdout_str())
+}
+
+/// Picks the major and minor version out of the output of `rpm --version`,
+/// which looks like `RPM version 4.19.1.1`.
+fn parse_rpm_version(output: &str) -> Option<(u32, u32)> {
+	let version = output.split_whitespace().last()?;
+	let mut parts = version.split('.');
+	let major = parts.next()?.parse().ok()?;
+	let minor = parts.next().and_then(|p| p.parse().ok()).unwrap_or(0);
+	Some((major, minor))
+}
+
+/// The build host recorded in reproducible builds, so that the real one
+/// doesn't end up in the package.
+const REPRODUCIBLE_BUILD_HOST: &str = "localhost";
+
+/// Gives the lines of the spec file that record where the package comes from,
+/// and the host it was built on if given or if the build is `reproducible`.
+fn provenance(distribution: &str, build_host: Option<&str>, reproducible: bool) -> String {
+	let mut lines = format!("Distribution: {distribution}\n");
+	if let Some(build_host) = build_host.or(reproducible.then_some(REPRODUCIBLE_BUILD_HOST)) {
+		// `BuildHost` isn't a valid tag in the spec file itself.
+		_ = writeln!(lines, "%define _buildhost {build_host}");
+	}
+	lines
+}
+
+/// Writes the `%changelog` section of the spec file, if there's a
+/// changelog entry to put in it.
+fn write_changelog(spec_file: &mut impl Write, info: &PackageInfo, args: &Args) -> Result<()> {
+	let Some(entry) = &args.changelog_entry else {
+		return Ok(());
+	};
+	let packager = match &args.maintainer {
+		Some(maintainer) => maintainer.clone(),
+		None => format!("{} <{}>", whoami::realname(), fetch_email_address()?),
+	};
+	let date = fetch_build_date()?;
+
+	let section = changelog_section(entry, &packager, date, info)?;
+	write!(spec_file, "\n%changelog\n{section}")?;
+	Ok(())
+}
+
+/// Formats a `%changelog` entry for the package with the given text,
+/// which may span lines.
+fn changelog_section(
+	text: &str,
+	packager: &str,
+	date: OffsetDateTime,
+	info: &PackageInfo,
+) -> Result<String> {
+	let PackageInfo {
+		version,
+		epoch,
+		release,
+		..
+	} = info;
+	let evr = match epoch {
+		Some(epoch) => format!("{epoch}:{version}-{release}"),
+		None => format!("{version}-{release}"),
+	};
+	// rpm wants dates like `Thu Feb 29 2024`.
+	let weekday = date.weekday().to_string();
+	let month = date.month().to_string();
+	let mut section = format!(
+		"* {} {} {:02} {} {packager} - {evr}\n",
+		&weekday[..3],
+		&month[..3],
+		date.day(),
+		date.year()
+	);
+
+	// Blank lines would end the entry, and `%` starts a macro.
+	let mut lines = text
+		.lines()
+		.map(|l| l.trim().replace('%', "%%"))
+		.filter(|l| !l.is_empty());
+	if let Some(first) = lines.next() {
+		writeln!(section, "- {first}")?;
+	}
+	for line in lines {
+		writeln!(section, "  {line}")?;
+	}
+	Ok(section)
+}
+
+/// Quotes a file name for the `%files` section of the spec file, so that
+/// spaces, quotes and characters special to rpm are taken literally.
+///
+/// Characters that can't be written in a spec file, such as newlines and
+/// bytes that aren't valid UTF-8, are replaced with `?` wildcards instead.
+fn spec_file_name(path: &Path) -> String {
+	let mut quoted = String::from('"');
+	for chunk in path.as_os_str().as_bytes().utf8_chunks() {
+		for c in chunk.valid().chars() {
+			match c {
+				'"' | '\\' | '*' | '?' | '[' | ']' | '{' | '}' => {
+					quoted.push('\\');
+					quoted.push(c);
+				}
+				// Macros are expanded even in file names.
+				'%' => quoted.push_str("%%"),
+				c if c.is_control() => quoted.push('?'),
+				c => quoted.push(c),
+			}
+		}
+		for _ in chunk.invalid() {
+			quoted.push('?');
+		}
+	}
+	quoted.push('"');
+	quoted
+}
+
+/// Rewrites a dependency in Debian's syntax, like `lsb (>= 3.0)`, the way
+/// rpm spells it, like `lsb >= 3.0`.
+///
+/// Alternatives become a boolean dependency, which needs rpm 4.13 or newer.
+/// Dependencies that are already in rpm's syntax are left alone.
+fn rpm_dependency(dep: &str) -> String {
+	if dep.starts_with('(') {
+		return dep.to_owned();
+	}
+	let alternatives: Vec<_> = dep.split('|').map(rpm_relation).collect();
+	match &alternatives[..] {
+		[relation] => relation.clone(),
+		_ => format!("({})", alternatives.join(" or ")),
+	}
+}
+
+/// Rewrites a single relation of a Debian dependency, like `libc6 (>> 2.31)`.
+fn rpm_relation(relation: &str) -> String {
+	match split_relation(relation) {
+		(name, "", _) => name.to_owned(),
+		(name, op, version) => format!("{name} {op} {version}"),
+	}
+}
+
+/// Formats the `%attr` directive for a file, leaving out anything unknown.
+fn attr(file_info: &FileInfo) -> String {
+	fn or_default(s: &str) -> &str {
+		if s.is_empty() {
+			"-"
+		} else {
+			s
+		}
+	}
+	let (owner, group) = file_info
+		.owner
+		.split_once(':')
+		.unwrap_or((&file_info.owner, ""));
+	let mode = file_info.mode.map_or("-".into(), |m| format!("{m:o}"));
+
+	format!(
+		"%attr({mode}, {}, {})",
+		or_default(owner),
+		or_default(group)
+	)
+}
+
+/// Reports files in the file list that don't exist in the unpacked directory,
+/// failing if `strict` is set.
+fn report_missing_files(missing: &[String], strict: bool, verbosity: Verbosity) -> Result<()> {
+	if strict {
+		bail!(
+			"The following files in the package's file list do not exist:\n\t{}",
+			missing.join("\n\t")
+		);
+	}
+
+	eprintln!(
+		"Warning: Dropping {} files that do not exist from the package's file list.",
+		missing.len()
+	);
+	if verbosity == Verbosity::Normal {
+		eprintln!("Warning: Use --verbose to see which files were dropped.");
+	} else {
+		for file in missing {
+			eprintln!("\t{file}");
+		}
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{
+		collections::HashMap,
+		ffi::OsStr,
+		os::unix::ffi::OsStrExt,
+		path::{Path, PathBuf},
+	};
+
+	use bpaf::Parser;
+	use time::OffsetDateTime;
+
+	use super::{
+		changelog_section, file_name, filter_rpmlint_output, parse_rpm_version, preamble,
+		provenance, rpm_dependency, RpmTarget,
+	};
+	use crate::{
+		override_version,
+		util::{args, Verbosity},
+		FileFlag, FileInfo, Format, PackageInfo,
+	};
+
+	#[test]
+	fn test_file_list() -> eyre::Result<()> {
+		let unpacked_dir = tempfile::tempdir()?;
+		std::fs::create_dir(unpacked_dir.path().join("etc"))?;
+		std::fs::write(unpacked_dir.path().join("etc/xenomorph.conf"), "")?;
+
+		let mut info = PackageInfo {
+			files: vec![PathBuf::from("/etc/"), PathBuf::from("/etc/xenomorph.conf")],
+			conffiles: vec![PathBuf::from("/etc/xenomorph.conf")],
+			..Default::default()
+		};
+		assert_eq!(
+			RpmTarget::file_list(&info, unpacked_dir.path(), true, Verbosity::Normal)?,
+			"%dir \"/etc/\"\n%config \"/etc/xenomorph.conf\"\n"
+		);
+
+		info.files.push(PathBuf::from("/usr/bin/xenomorph"));
+		assert!(RpmTarget::file_list(&info, unpacked_dir.path(), true, Verbosity::Normal).is_err());
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_requires() -> eyre::Result<()> {
+		let unpacked_dir = tempfile::tempdir()?;
+		let info = PackageInfo {
+			name: "xenomorph".into(),
+			version: "1.0".into(),
+			release: "1".into(),
+			arch: "amd64".into(),
+			// As kept from a deb by `--keep-depends`.
+			dependencies: vec!["lsb (>= 3.0)".into(), "libc6:any (>> 2.31) | musl".into()],
+			..Default::default()
+		};
+		let args = args()
+			.to_options()
+			.run_inner(&["--to-rpm", "--keep-depends", "xenomorph.deb"])
+			.unwrap();
+		RpmTarget::new(info, unpacked_dir.path().to_path_buf(), &args)?;
+
+		let spec = std::fs::read_to_string(unpacked_dir.path().join("xenomorph-1.0-1.spec"))?;
+		assert!(spec.contains("\nRequires: lsb >= 3.0, (libc6 > 2.31 or musl)\n"));
+
+		assert_eq!(rpm_dependency("lsb"), "lsb");
+		assert_eq!(rpm_dependency("libc6 (= 1:2.31-1)"), "libc6 = 1:2.31-1");
+		assert_eq!(rpm_dependency("(libc6 or musl)"), "(libc6 or musl)");
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_version_round_trip() {
+		// An rpm that was converted to a deb, and is being converted back.
+		let mut info = PackageInfo {
+			original_format: Format::Deb,
+			..Default::default()
+		};
+		crate::deb::set_version_and_release(&mut info, "1.2.3-1");
+		RpmTarget::sanitize_info(&mut info);
+		assert_eq!(info.version, "1.2.3");
+
+		// Debian upstream versions may have dashes, which rpm can't take.
+		crate::deb::set_version_and_release(&mut info, "1.2-beta-1");
+		info.original_version.clone_from(&info.version);
+		RpmTarget::sanitize_info(&mut info);
+		assert_eq!(info.version, "1.2_beta");
+		assert_eq!(info.original_version, "1.2-beta");
+
+		// Versions that came from an rpm are left alone.
+		let mut info = PackageInfo {
+			version: "1.2.3".into(),
+			original_version: "1.2.3".into(),
+			original_format: Format::Rpm,
+			..Default::default()
+		};
+		RpmTarget::sanitize_info(&mut info);
+		assert_eq!(info.version, "1.2.3");
+	}
+
+	#[test]
+	fn test_override_version() {
+		let mut info = PackageInfo {
+			name: "xenomorph".into(),
+			version: "0.1.0".into(),
+			original_version: "0.1.0".into(),
+			release: "2".into(),
+			arch: "all".into(),
+			original_format: Format::Rpm,
+			..Default::default()
+		};
+		override_version(&mut info, Some("2024.1-beta"), Some("7"));
+		RpmTarget::sanitize_info(&mut info);
+		assert_eq!(file_name(&info), "xenomorph-2024.1_beta-7.noarch.rpm");
+	}
+
+	#[test]
+	fn test_preamble() {
+		let build_root = Path::new("/tmp/xenomorph-0.1.0");
+		assert_eq!(
+			preamble(build_root, Some((4, 19))),
+			"%global debug_package %{nil}\n"
+		);
+		assert_eq!(
+			preamble(build_root, Some((4, 4))),
+			"%global debug_package %{nil}\nBuildroot: /tmp/xenomorph-0.1.0\n"
+		);
+		assert!(preamble(build_root, None).contains("Buildroot: "));
+
+		assert_eq!(parse_rpm_version("RPM version 4.19.1.1\n"), Some((4, 19)));
+		assert_eq!(parse_rpm_version("RPM version 4\n"), Some((4, 0)));
+		assert_eq!(parse_rpm_version(""), None);
+	}
+
+	#[test]
+	fn test_provenance() {
+		assert_eq!(provenance("Debian", None, false), "Distribution: Debian\n");
+		assert_eq!(
+			provenance("Xenomorph Linux", None, true),
+			"Distribution: Xenomorph Linux\n%define _buildhost localhost\n"
+		);
+		assert_eq!(
+			provenance("Debian", Some("builder.example.org"), true),
+			"Distribution: Debian\n%define _buildhost builder.example.org\n"
+		);
+	}
+
+	#[test]
+	fn test_file_list_special_names() -> eyre::Result<()> {
+		let unpacked_dir = tempfile::tempdir()?;
+		let doc_dir = unpacked_dir.path().join("usr/share/doc");
+		std::fs::create_dir_all(&doc_dir)?;
+
+		let names: [&OsStr; 4] = [
+			OsStr::new("read me.txt"),
+			OsStr::new("\"quoted\".txt"),
+			OsStr::new("café.txt"),
+			OsStr::from_bytes(b"caf\xe9.txt"),
+		];
+		let mut info = PackageInfo::default();
+		for name in names {
+			std::fs::write(doc_dir.join(name), "")?;
+			info.files.push(Path::new("/usr/share/doc").join(name));
+		}
+
+		assert_eq!(
+			RpmTarget::file_list(&info, unpacked_dir.path(), true, Verbosity::Normal)?,
+			r#""/usr/share/doc/read me.txt"
+"/usr/share/doc/\"quoted\".txt"
+"/usr/share/doc/café.txt"
+"/usr/share/doc/caf?.txt"
+"#
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_changelog_section() -> eyre::Result<()> {
+		assert_eq!(
+			changelog_section(
+				"Rebuilt against the new libfoo.\n\nNow 100% less broken.",
+				"Leah <hi@pluie.me>",
+				OffsetDateTime::from_unix_timestamp(1_709_208_000)?,
+				&PackageInfo {
+					version: "0.1.0".into(),
+					release: "2".into(),
+					..Default::default()
+				}
+			)?,
+			"\
+* Thu Feb 29 2024 Leah <hi@pluie.me> - 0.1.0-2
+- Rebuilt against the new libfoo.
+  Now 100%% less broken.
+"
+		);
+		Ok(())
+	}
+
+	#[test]
+	fn test_filter_rpmlint_output() {
+		let output = "\
+xenomorph.x86_64: W: no-documentation
+xenomorph.x86_64: W: invalid-license unknown
+xenomorph.x86_64: E: zero-length /etc/xenomorph.conf
+
+1 packages and 0 specfiles checked; 1 errors, 2 warnings.
+";
+		assert_eq!(
+			filter_rpmlint_output(output),
+			[
+				"xenomorph.x86_64: E: zero-length /etc/xenomorph.conf",
+				"1 packages and 0 specfiles checked; 1 errors, 2 warnings."
+			]
+		);
+	}
+
+	#[test]
+	fn test_file_list_attrs() -> eyre::Result<()> {
+		let unpacked_dir = tempfile::tempdir()?;
+		std::fs::create_dir_all(unpacked_dir.path().join("usr/bin"))?;
+		std::fs::write(unpacked_dir.path().join("usr/bin/sudo"), "")?;
+		std::fs::write(unpacked_dir.path().join("usr/bin/games"), "")?;
+
+		let info = PackageInfo {
+			files: vec![
+				PathBuf::from("/usr/bin/"),
+				PathBuf::from("/usr/bin/games"),
+				PathBuf::from("/usr/bin/sudo"),
+			],
+			file_info: HashMap::from([
+				(
+					PathBuf::from("/usr/bin/sudo"),
+					FileInfo {
+						owner: "wheel:wheel".into(),
+						mode: Some(0o4755),
+					},
+				),
+				(
+					PathBuf::from("/usr/bin/games"),
+					FileInfo {
+						owner: ":games".into(),
+						mode: None,
+					},
+				),
+			]),
+			..Default::default()
+		};
+		assert_eq!(
+			RpmTarget::file_list(&info, unpacked_dir.path(), true, Verbosity::Normal)?,
+			"\
+%dir \"/usr/bin/\"
+%attr(-, -, games) \"/usr/bin/games\"
+%attr(4755, wheel, wheel) \"/usr/bin/sudo\"
+"
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_file_list_flags() -> eyre::Result<()> {
+		let unpacked_dir = tempfile::tempdir()?;
+		std::fs::create_dir_all(unpacked_dir.path().join("usr/share/doc/xenomorph"))?;
+		std::fs::create_dir(unpacked_dir.path().join("etc"))?;
+		std::fs::write(
+			unpacked_dir.path().join("usr/share/doc/xenomorph/README"),
+			"",
+		)?;
+		std::fs::write(
+			unpacked_dir.path().join("usr/share/doc/xenomorph/COPYING"),
+			"",
+		)?;
+		std::fs::write(unpacked_dir.path().join("etc/xenomorph.conf"), "")?;
+
+		let info = PackageInfo {
+			files: vec![
+				PathBuf::from("/etc/xenomorph.conf"),
+				PathBuf::from("/usr/share/doc/xenomorph/COPYING"),
+				PathBuf::from("/usr/share/doc/xenomorph/README"),
+				PathBuf::from("/var/log/xenomorph.log"),
+			],
+			conffiles: vec![PathBuf::from("/etc/xenomorph.conf")],
+			file_flags: HashMap::from([
+				(
+					PathBuf::from("/etc/xenomorph.conf"),
+					FileFlag::NoReplace.into(),
+				),
+				(
+					PathBuf::from("/usr/share/doc/xenomorph/COPYING"),
+					FileFlag::License.into(),
+				),
+				(
+					PathBuf::from("/usr/share/doc/xenomorph/README"),
+					FileFlag::Doc.into(),
+				),
+				(
+					PathBuf::from("/var/log/xenomorph.log"),
+					FileFlag::Ghost.into(),
+				),
+			]),
+			..Default::default()
+		};
+		assert_eq!(
+			RpmTarget::file_list(&info, unpacked_dir.path(), true, Verbosity::Normal)?,
+			"\
+%config(noreplace) \"/etc/xenomorph.conf\"
+%license \"/usr/share/doc/xenomorph/COPYING\"
+%doc \"/usr/share/doc/xenomorph/README\"
+%ghost \"/var/log/xenomorph.log\"
+"
+		);
+
+		Ok(())
 	}
 }
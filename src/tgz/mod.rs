@@ -1,9 +1,9 @@
 pub use source::TgzSource;
 pub use target::TgzTarget;
 
-use crate::util::{ExecExt, Verbosity};
+use crate::util::spawn_install;
 use eyre::{bail, Context, Result};
-use std::path::Path;
+use std::{path::Path, str::FromStr};
 use subprocess::Exec;
 
 pub mod source;
@@ -13,13 +13,55 @@ pub mod target;
 ///
 /// installpkg (a slackware program) is used because I'm not sanguine about
 /// just untarring a tgz file — it might trash a system.
-pub fn install(tgz: &Path) -> Result<()> {
+pub fn install(tgz: &Path, dry_run: bool) -> Result<()> {
 	if Path::new("/sbin/installpkg").exists() {
-		Exec::cmd("/sbin/installpkg")
-			.arg(tgz)
-			.log_and_spawn(Verbosity::VeryVerbose)
-			.wrap_err("Unable to install")
+		let cmd = Exec::cmd("/sbin/installpkg").arg(tgz);
+		spawn_install(cmd, dry_run).wrap_err("Unable to install")
 	} else {
 		bail!("Sorry, I cannot install the generated .tgz file because /sbin/installpkg is not present. You can use tar to install it yourself.")
 	}
 }
+
+/// Compression algorithms generated tgz packages can use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TgzCompression {
+	#[default]
+	Gzip,
+	Xz,
+	Zstd,
+}
+impl FromStr for TgzCompression {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"gzip" => Ok(Self::Gzip),
+			"xz" => Ok(Self::Xz),
+			"zstd" => Ok(Self::Zstd),
+			_ => Err(format!(
+				"Unknown compression algorithm `{s}`. Expected one of gzip, xz or zstd."
+			)),
+		}
+	}
+}
+impl TgzCompression {
+	/// The extension Slackware uses for packages compressed with this algorithm.
+	#[must_use]
+	pub fn extension(self) -> &'static str {
+		match self {
+			Self::Gzip => "tgz",
+			Self::Xz => "txz",
+			Self::Zstd => "tzst",
+		}
+	}
+
+	/// The magic number at the start of files compressed with this algorithm.
+	#[must_use]
+	pub fn magic(self) -> &'static [u8] {
+		match self {
+			Self::Gzip => &[0x1f, 0x8b],
+			Self::Xz => &[0xfd, b'7', b'z', b'X', b'Z', 0],
+			Self::Zstd => &[0x28, 0xb5, 0x2f, 0xfd],
+		}
+	}
+}
@@ -2,34 +2,46 @@ use std::{
 	collections::HashMap,
 	fmt::Debug,
 	fs::File,
-	io::{Read, Seek},
+	io::{BufReader, Read},
 	path::{Path, PathBuf},
 };
 
+use bzip2::read::BzDecoder;
 use eyre::Result;
+use flate2::read::GzDecoder;
+use liblzma::{read::XzDecoder, stream::Stream};
 use subprocess::Exec;
+use zstd::stream::read::Decoder as ZstdDecoder;
 
+use super::TgzCompression;
 use crate::{
-	util::{make_unpack_work_dir, ExecExt},
-	Format, PackageInfo, Script, SourcePackage,
+	caps,
+	util::{make_unpack_work_dir, normalize_archive_path, unpack_archive, ExecExt, Verbosity},
+	Args, Format, PackageInfo, Script, SourcePackage,
 };
 
 pub struct TgzSource {
 	info: PackageInfo,
-	tar: tar::Archive<File>,
+	verbosity: Verbosity,
+	/// Where to unpack the package, if not the current directory.
+	build_dir: Option<PathBuf>,
 }
 impl TgzSource {
 	#[must_use]
 	pub fn check_file(file: &Path) -> bool {
-		let Some(f) = file.file_name() else { return false; };
+		let Some(f) = file.file_name() else {
+			return false;
+		};
 		let f = f.to_string_lossy();
 
-		let Some((rest, ext)) = f.rsplit_once('.') else { return false; };
+		let Some((rest, ext)) = f.rsplit_once('.') else {
+			return false;
+		};
 		let ext = ext.to_ascii_lowercase();
 
 		match ext.as_str() {
-			"tgz" | "taz" => true,
-			"gz" | "z" | "bz" | "bz2" => {
+			"tgz" | "taz" | "txz" | "tlz" | "tbz" | "tzst" => true,
+			"gz" | "z" | "bz" | "bz2" | "xz" | "lzma" | "zst" => {
 				if let Some((_, ext2)) = rest.rsplit_once('.') {
 					ext2.eq_ignore_ascii_case("tar")
 				} else {
@@ -39,7 +51,7 @@ impl TgzSource {
 			_ => false,
 		}
 	}
-	pub fn new(file: PathBuf) -> Result<Self> {
+	pub fn new(file: PathBuf, args: &Args) -> Result<Self> {
 		let mut basename = if let Some(file_name) = file.file_name() {
 			PathBuf::from(file_name)
 		} else {
@@ -54,40 +66,17 @@ impl TgzSource {
 		let binary_info = Exec::cmd("ls")
 			.arg("-l")
 			.arg(&file)
-			.log_and_output(None)?
+			.log_and_output(args.verbosity)?
 			.stdout_str();
 
-		let mut conffiles = vec![];
-		let mut files = vec![];
-		let mut scripts = HashMap::new();
-
-		let mut tar = tar::Archive::new(File::open(&file)?);
-		for entry in tar.entries()? {
-			let mut entry = entry?;
-			let header = entry.header();
-			let mut path = PathBuf::from("/");
-			path.push(header.path()?);
-
-			// Assume any regular file (non-directory) in /etc/ is a conffile.
-			if path.starts_with("/etc/") && header.mode()? & 0o1000 == 0 {
-				// If entry is just a regular file and not a directory
-
-				conffiles.push(path.clone());
-			} else if path.starts_with("/install/") {
-				// It might be a script!
-
-				let Some(name) = path.file_name() else { continue; };
-				let name = name.to_string_lossy();
-				let Some(script) = Script::from_tgz_script_name(&name) else { continue; };
-
-				let mut content = String::new();
-				entry.read_to_string(&mut content)?;
-				scripts.insert(script, content);
-			} else {
-				// Regular old file
-				files.push(path);
-			}
-		}
+		let mut tar = open_tarball(&file)?;
+		let Entries {
+			conffiles,
+			files,
+			dirs,
+			scripts,
+			capabilities,
+		} = read_entries(&mut tar)?;
 
 		let info = PackageInfo {
 			file,
@@ -104,18 +93,99 @@ impl TgzSource {
 			binary_info,
 			conffiles,
 			files,
+			dirs,
 			scripts,
+			capabilities,
 			..Default::default()
 		};
 
-		// Rewind tar to
-		let mut tar = tar.into_inner();
-		tar.rewind()?;
-		let tar = tar::Archive::new(tar);
+		Ok(Self {
+			info,
+			verbosity: args.verbosity,
+			build_dir: args.build_dir.clone(),
+		})
+	}
+}
+/// Opens a tarball, decompressing it if it is compressed with gzip, bzip2,
+/// xz, lzma or zstd.
+pub(super) fn open_tarball(file: &Path) -> Result<tar::Archive<Box<dyn Read>>> {
+	let mut magic = Vec::with_capacity(6);
+	File::open(file)?.take(6).read_to_end(&mut magic)?;
+
+	let reader = BufReader::new(File::open(file)?);
+	let reader: Box<dyn Read> = if magic.starts_with(TgzCompression::Gzip.magic()) {
+		Box::new(GzDecoder::new(reader))
+	} else if magic.starts_with(b"BZh") {
+		Box::new(BzDecoder::new(reader))
+	} else if magic.starts_with(TgzCompression::Xz.magic()) {
+		Box::new(XzDecoder::new(reader))
+	} else if magic.starts_with(TgzCompression::Zstd.magic()) {
+		Box::new(ZstdDecoder::new(reader)?)
+	} else if is_lzma(file) {
+		// Raw lzma streams have no magic number to speak of, so go by the extension.
+		let stream = Stream::new_lzma_decoder(u64::MAX)?;
+		Box::new(XzDecoder::new_stream(reader, stream))
+	} else {
+		// Hopefully it's just a plain tarball.
+		Box::new(reader)
+	};
+	Ok(tar::Archive::new(reader))
+}
+
+/// Whether `file` is named like a tarball compressed with lzma.
+fn is_lzma(file: &Path) -> bool {
+	file.extension()
+		.is_some_and(|e| e.eq_ignore_ascii_case("tlz") || e.eq_ignore_ascii_case("lzma"))
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+	conffiles: Vec<PathBuf>,
+	files: Vec<PathBuf>,
+	dirs: Vec<PathBuf>,
+	scripts: HashMap<Script, String>,
+	capabilities: HashMap<PathBuf, Vec<u8>>,
+}
+
+fn read_entries(tar: &mut tar::Archive<impl Read>) -> Result<Entries> {
+	let mut entries = Entries::default();
 
-		Ok(Self { info, tar })
+	for entry in tar.entries()? {
+		let mut entry = entry?;
+		let path = normalize_archive_path(&entry.path()?);
+		if let Some(caps) = caps::from_tar_entry(&mut entry)? {
+			entries.capabilities.insert(path.clone(), caps);
+		}
+		let header = entry.header();
+
+		// Assume any regular file in /etc/ is a conffile.
+		if path.starts_with("/etc/") && header.entry_type().is_file() {
+			entries.conffiles.push(path);
+		} else if path.starts_with("/install/") {
+			// It might be a script!
+
+			let Some(name) = path.file_name() else {
+				continue;
+			};
+			let name = name.to_string_lossy();
+			let Some(script) = Script::from_tgz_script_name(&name) else {
+				continue;
+			};
+
+			let mut content = String::new();
+			entry.read_to_string(&mut content)?;
+			entries.scripts.insert(script, content);
+		} else {
+			// Regular old file
+			if header.entry_type().is_dir() && path != Path::new("/") {
+				entries.dirs.push(path.clone());
+			}
+			entries.files.push(path);
+		}
 	}
+	Ok(entries)
 }
+
 impl SourcePackage for TgzSource {
 	fn info(&self) -> &PackageInfo {
 		&self.info
@@ -127,12 +197,17 @@ impl SourcePackage for TgzSource {
 		self.info
 	}
 	fn unpack(&mut self) -> Result<PathBuf> {
-		let work_dir = make_unpack_work_dir(&self.info)?;
+		let work_dir = make_unpack_work_dir(&self.info, self.build_dir.as_deref(), self.verbosity)?;
 
-		self.tar.unpack(&work_dir)?;
+		// The archive has to be read from the start again.
+		unpack_archive(&mut open_tarball(&self.info.file)?, &work_dir)?;
 
 		// Delete the install directory that has slackware info in it.
-		std::fs::remove_dir_all(work_dir.join("install"))?;
+		// Plain tarballs, and packages without any scripts, don't have one.
+		match std::fs::remove_dir_all(work_dir.join("install")) {
+			Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+			_ => {}
+		}
 
 		Ok(work_dir)
 	}
@@ -141,6 +216,156 @@ impl Debug for TgzSource {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		f.debug_struct("TgzSource")
 			.field("info", &self.info)
+			.field("verbosity", &self.verbosity)
+			.field("build_dir", &self.build_dir)
 			.finish()
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use std::{
+		io::{Cursor, Write},
+		path::{Path, PathBuf},
+	};
+
+	use super::{open_tarball, read_entries, TgzSource};
+
+	fn test_tarball() -> eyre::Result<Vec<u8>> {
+		let mut builder = tar::Builder::new(vec![]);
+		let mut header = tar::Header::new_gnu();
+		header.set_mode(0o755);
+		header.set_size(9);
+		builder.append_data(&mut header, "usr/bin/xenomorph", &b"#!/bin/sh"[..])?;
+		Ok(builder.into_inner()?)
+	}
+
+	fn test_open_tarball_with(
+		suffix: &str,
+		compress: impl FnOnce(&[u8]) -> eyre::Result<Vec<u8>>,
+	) -> eyre::Result<()> {
+		let mut file = tempfile::Builder::new().suffix(suffix).tempfile()?;
+		file.write_all(&compress(&test_tarball()?)?)?;
+
+		let entries = read_entries(&mut open_tarball(file.path())?)?;
+		assert_eq!(entries.files, [PathBuf::from("/usr/bin/xenomorph")]);
+		Ok(())
+	}
+
+	#[test]
+	fn test_open_tarball_gzip() -> eyre::Result<()> {
+		test_open_tarball_with(".tgz", |tar| {
+			let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+			encoder.write_all(tar)?;
+			Ok(encoder.finish()?)
+		})
+	}
+
+	#[test]
+	fn test_open_tarball_bzip2() -> eyre::Result<()> {
+		test_open_tarball_with(".tbz", |tar| {
+			let mut encoder = bzip2::write::BzEncoder::new(vec![], bzip2::Compression::default());
+			encoder.write_all(tar)?;
+			Ok(encoder.finish()?)
+		})
+	}
+
+	#[test]
+	fn test_open_tarball_xz() -> eyre::Result<()> {
+		test_open_tarball_with(".txz", |tar| {
+			let mut encoder = liblzma::write::XzEncoder::new(vec![], 6);
+			encoder.write_all(tar)?;
+			Ok(encoder.finish()?)
+		})
+	}
+
+	#[test]
+	fn test_open_tarball_lzma() -> eyre::Result<()> {
+		test_open_tarball_with(".tlz", |tar| {
+			let options = liblzma::stream::LzmaOptions::new_preset(6)?;
+			let stream = liblzma::stream::Stream::new_lzma_encoder(&options)?;
+			let mut encoder = liblzma::write::XzEncoder::new_stream(vec![], stream);
+			encoder.write_all(tar)?;
+			Ok(encoder.finish()?)
+		})
+	}
+
+	#[test]
+	fn test_open_tarball_zstd() -> eyre::Result<()> {
+		test_open_tarball_with(".tzst", |tar| Ok(zstd::encode_all(tar, 0)?))
+	}
+
+	#[test]
+	fn test_check_file() {
+		for file in [
+			"xenomorph-0.1.0-x86_64-1.tgz",
+			"xenomorph-0.1.0-x86_64-1.txz",
+			"xenomorph-0.1.0-x86_64-1.tlz",
+			"xenomorph-0.1.0-x86_64-1.tbz",
+			"xenomorph-0.1.0-x86_64-1.tzst",
+			"xenomorph-0.1.0.tar.xz",
+			"xenomorph-0.1.0.tar.zst",
+		] {
+			assert!(TgzSource::check_file(Path::new(file)), "{file}");
+		}
+		assert!(!TgzSource::check_file(Path::new("xenomorph-0.1.0.xz")));
+	}
+
+	#[test]
+	fn test_read_entries_conffiles() -> eyre::Result<()> {
+		let mut builder = tar::Builder::new(vec![]);
+
+		let mut header = tar::Header::new_gnu();
+		header.set_entry_type(tar::EntryType::Regular);
+		header.set_mode(0o644);
+		header.set_size(4);
+		builder.append_data(&mut header, "etc/foo.conf", &b"foo\n"[..])?;
+
+		let mut header = tar::Header::new_gnu();
+		header.set_entry_type(tar::EntryType::Directory);
+		header.set_mode(0o755);
+		header.set_size(0);
+		builder.append_data(&mut header, "etc/dir/", std::io::empty())?;
+
+		let mut header = tar::Header::new_gnu();
+		header.set_entry_type(tar::EntryType::Symlink);
+		header.set_mode(0o777);
+		header.set_size(0);
+		builder.append_link(&mut header, "etc/link", "foo.conf")?;
+
+		let mut tar = tar::Archive::new(Cursor::new(builder.into_inner()?));
+		let entries = read_entries(&mut tar)?;
+
+		assert_eq!(entries.conffiles, [PathBuf::from("/etc/foo.conf")]);
+		assert_eq!(
+			entries.files,
+			[PathBuf::from("/etc/dir/"), PathBuf::from("/etc/link")]
+		);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_unpack_without_install_dir() -> eyre::Result<()> {
+		use bpaf::Parser;
+
+		use crate::SourcePackage;
+
+		let dir = tempfile::tempdir()?;
+		let file = dir.path().join("xenomorph-0.1.0.tar");
+		std::fs::write(&file, test_tarball()?)?;
+
+		let build_dir = dir.path().to_string_lossy();
+		let cli = ["--build-dir", &build_dir, "xenomorph-0.1.0.tar"];
+		let args = crate::util::args()
+			.to_options()
+			.run_inner(&cli[..])
+			.unwrap();
+
+		let unpacked = TgzSource::new(file, &args)?.unpack()?;
+		assert!(unpacked.join("usr/bin/xenomorph").is_file());
+		assert!(!unpacked.join("install").exists());
+
+		Ok(())
+	}
+}
@@ -1,9 +1,19 @@
-use std::{fs::File, path::PathBuf};
+use std::{
+	collections::{HashMap, HashSet},
+	fs::{DirEntry, File},
+	io::{Read, Write},
+	os::unix::fs::MetadataExt,
+	path::{Component, Path, PathBuf},
+};
 
 use eyre::Result;
+use flate2::write::GzEncoder;
+use liblzma::write::XzEncoder;
+use zstd::stream::write::Encoder as ZstdEncoder;
 
+use super::{source::open_tarball, TgzCompression};
 use crate::{
-	util::{chmod, mkdir},
+	util::{chmod, mkdir, Args},
 	PackageInfo, TargetPackage,
 };
 
@@ -11,9 +21,11 @@ use crate::{
 pub struct TgzTarget {
 	info: PackageInfo,
 	unpacked_dir: PathBuf,
+	compression: TgzCompression,
+	clamp_mtime: Option<u64>,
 }
 impl TgzTarget {
-	pub fn new(info: PackageInfo, unpacked_dir: PathBuf) -> Result<Self> {
+	pub fn new(info: PackageInfo, unpacked_dir: PathBuf, args: &Args) -> Result<Self> {
 		if info.use_scripts {
 			let mut out = unpacked_dir.join("install");
 			let mut created_install_folder = false;
@@ -24,28 +36,404 @@ impl TgzTarget {
 				}
 
 				if !created_install_folder {
-					mkdir(&out)?;
-					chmod(&out, 0o755)?;
+					mkdir(&out, args.verbosity)?;
+					chmod(&out, 0o755, args.verbosity)?;
 					created_install_folder = true;
 				}
 				out.push(script.tgz_script_name());
 
 				std::fs::write(&out, data)?;
-				chmod(&out, 0o755)?;
+				chmod(&out, 0o755, args.verbosity)?;
+				out.pop();
 			}
 		}
 
-		Ok(Self { info, unpacked_dir })
+		Ok(Self {
+			info,
+			unpacked_dir,
+			compression: args.tgz_args.tgz_compression.unwrap_or_default(),
+			clamp_mtime: args.clamp_mtime,
+		})
+	}
+
+	/// Writes the unpacked directory as a tarball to `writer`, returning it
+	/// once done.
+	fn write_tarball<W: Write>(&self, writer: W) -> Result<W> {
+		let mut tgz = tar::Builder::new(writer);
+		append_entry(
+			&mut tgz,
+			&self.unpacked_dir,
+			Path::new("."),
+			self.clamp_mtime,
+			&mut HashMap::new(),
+		)?;
+		Ok(tgz.into_inner()?)
 	}
 }
 impl TargetPackage for TgzTarget {
-	fn build(&mut self) -> Result<PathBuf> {
-		let path = format!("{}-{}.tgz", self.info.name, self.info.version);
-		let path = PathBuf::from(path);
+	fn build(&mut self, output_dir: &Path) -> Result<PathBuf> {
+		let path = output_dir.join(file_name(&self.info, self.compression));
 
-		let mut tgz = tar::Builder::new(File::create(&path)?);
-		tgz.append_dir_all(".", &self.unpacked_dir)?;
+		let file = File::create(&path)?;
+		match self.compression {
+			TgzCompression::Gzip => {
+				let encoder = GzEncoder::new(file, flate2::Compression::default());
+				self.write_tarball(encoder)?.finish()?;
+			}
+			TgzCompression::Xz => {
+				self.write_tarball(XzEncoder::new(file, 6))?.finish()?;
+			}
+			TgzCompression::Zstd => {
+				self.write_tarball(ZstdEncoder::new(file, 0)?)?.finish()?;
+			}
+		}
 
 		Ok(path)
 	}
+
+	/// Checks that the package can be read by `installpkg`, has the scripts it
+	/// should have, and doesn't put anything outside of the root directory.
+	fn test(&mut self, package: &Path) -> Result<Vec<String>> {
+		let mut results = vec![];
+
+		let expected = self.compression.magic();
+		let mut magic = Vec::with_capacity(expected.len());
+		File::open(package)?
+			.take(expected.len() as u64)
+			.read_to_end(&mut magic)?;
+		if magic != expected {
+			results.push(format!(
+				"package is not compressed as its .{} extension says",
+				self.compression.extension()
+			));
+		}
+
+		let mut names = HashSet::new();
+		let mut archive = open_tarball(package)?;
+		let entries = archive.entries().and_then(|entries| {
+			entries
+				.map(|e| e.and_then(|e| Ok(e.path()?.into_owned())))
+				.collect::<std::io::Result<Vec<_>>>()
+		});
+		match entries {
+			Ok(entries) => names.extend(entries),
+			Err(e) => results.push(format!("package is not a valid tar archive: {e}")),
+		}
+
+		for name in &names {
+			if name.is_absolute() || name.components().any(|c| c == Component::ParentDir) {
+				results.push(format!(
+					"{} is outside of the root directory",
+					name.display()
+				));
+			}
+		}
+
+		if self.info.use_scripts {
+			for (script, data) in &self.info.scripts {
+				let name = Path::new("install").join(script.tgz_script_name());
+				if !data.chars().all(char::is_whitespace) && !names.contains(&name) {
+					results.push(format!("{} is missing", name.display()));
+				}
+			}
+		}
+
+		Ok(results)
+	}
+}
+
+/// The name of the file the package is built into.
+pub(crate) fn file_name(info: &PackageInfo, compression: TgzCompression) -> String {
+	format!("{}-{}.{}", info.name, info.version, compression.extension())
+}
+
+/// Appends `src` to the archive as `name`, recursing into directories.
+///
+/// Unlike [`tar::Builder::append_dir_all`], this takes the modification time
+/// of each entry from the unpacked tree, clamping it to `clamp_mtime` if given,
+/// and adds directory entries in a stable order.
+///
+/// Files that are hard links to a file already in the archive are added as
+/// links to it, using `inodes` to remember the names files were added under.
+pub(crate) fn append_entry(
+	tgz: &mut tar::Builder<impl Write>,
+	src: &Path,
+	name: &Path,
+	clamp_mtime: Option<u64>,
+	inodes: &mut HashMap<(u64, u64), PathBuf>,
+) -> Result<()> {
+	let meta = std::fs::symlink_metadata(src)?;
+
+	let mut header = tar::Header::new_gnu();
+	header.set_metadata(&meta);
+	let mtime = u64::try_from(meta.mtime()).unwrap_or_default();
+	header.set_mtime(clamp_mtime.map_or(mtime, |clamp| mtime.min(clamp)));
+
+	let inode = (meta.dev(), meta.ino());
+	let first_link = if meta.is_file() && meta.nlink() > 1 {
+		inodes.get(&inode)
+	} else {
+		None
+	};
+
+	if let Some(target) = first_link {
+		header.set_entry_type(tar::EntryType::Link);
+		header.set_size(0);
+		tgz.append_link(&mut header, name, target)?;
+	} else if meta.is_file() {
+		if meta.nlink() > 1 {
+			// Entry names lose their leading `./` in the archive, so links have to as well.
+			inodes.insert(inode, name.strip_prefix(".").unwrap_or(name).to_owned());
+		}
+		tgz.append_data(&mut header, name, File::open(src)?)?;
+	} else if meta.is_symlink() {
+		header.set_size(0);
+		tgz.append_link(&mut header, name, std::fs::read_link(src)?)?;
+	} else {
+		header.set_size(0);
+		tgz.append_data(&mut header, name, std::io::empty())?;
+	}
+
+	if meta.is_dir() {
+		let mut entries = std::fs::read_dir(src)?.collect::<Result<Vec<_>, _>>()?;
+		entries.sort_by_key(DirEntry::file_name);
+
+		for entry in entries {
+			let name = name.join(entry.file_name());
+			append_entry(tgz, &entry.path(), &name, clamp_mtime, inodes)?;
+		}
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{
+		borrow::Cow,
+		fs::File,
+		path::PathBuf,
+		time::{Duration, SystemTime},
+	};
+
+	use std::{collections::HashMap, io::Write};
+
+	use super::TgzTarget;
+	use crate::{
+		tgz::{source::open_tarball, TgzCompression},
+		PackageInfo, Script, TargetPackage,
+	};
+
+	#[test]
+	fn test_test() -> eyre::Result<()> {
+		let output_dir = tempfile::tempdir()?;
+		let tgz = output_dir.path().join("xenomorph-0.1.0.tgz");
+
+		// `tar::Builder` refuses to add `..` paths, so write the name directly.
+		let mut tar = tar::Builder::new(vec![]);
+		let mut header = tar::Header::new_gnu();
+		let name = b"../etc/passwd";
+		header.as_gnu_mut().unwrap().name[..name.len()].copy_from_slice(name);
+		header.set_mode(0o644);
+		header.set_size(0);
+		header.set_cksum();
+		tar.append(&header, std::io::empty())?;
+
+		let mut gz =
+			flate2::write::GzEncoder::new(File::create(&tgz)?, flate2::Compression::default());
+		gz.write_all(&tar.into_inner()?)?;
+		gz.finish()?;
+
+		let mut target = TgzTarget {
+			info: PackageInfo {
+				use_scripts: true,
+				scripts: HashMap::from([(Script::AfterInstall, "#!/bin/sh\n".into())]),
+				..Default::default()
+			},
+			unpacked_dir: PathBuf::new(),
+			compression: TgzCompression::Gzip,
+			clamp_mtime: None,
+		};
+		assert_eq!(
+			target.test(&tgz)?,
+			[
+				"../etc/passwd is outside of the root directory",
+				"install/doinst.sh is missing",
+			]
+		);
+
+		// Not even a tarball.
+		std::fs::write(&tgz, "xenomorph")?;
+		let results = target.test(&tgz)?;
+		assert_eq!(
+			results[0],
+			"package is not compressed as its .tgz extension says"
+		);
+		assert!(results[1].starts_with("package is not a valid tar archive"));
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_build_preserves_mtime() -> eyre::Result<()> {
+		let unpacked_dir = tempfile::tempdir()?;
+		let output_dir = tempfile::tempdir()?;
+
+		let mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+		let old = unpacked_dir.path().join("old");
+		std::fs::write(&old, "old")?;
+		File::options()
+			.write(true)
+			.open(&old)?
+			.set_modified(mtime)?;
+		std::fs::write(unpacked_dir.path().join("new"), "new")?;
+
+		let mut target = TgzTarget {
+			info: PackageInfo {
+				name: "xenomorph".into(),
+				version: "0.1.0".into(),
+				..Default::default()
+			},
+			unpacked_dir: unpacked_dir.path().to_owned(),
+			compression: TgzCompression::Gzip,
+			clamp_mtime: Some(1_500_000_000),
+		};
+		let tgz = target.build(output_dir.path())?;
+
+		let mut archive = open_tarball(&tgz)?;
+		for entry in archive.entries()? {
+			let entry = entry?;
+			let path = entry.path()?.into_owned();
+			let header_mtime = entry.header().mtime()?;
+
+			if path.ends_with("old") {
+				assert_eq!(header_mtime, 1_000_000_000);
+			} else {
+				assert_eq!(
+					header_mtime,
+					1_500_000_000,
+					"{} not clamped",
+					path.display()
+				);
+			}
+		}
+
+		Ok(())
+	}
+	#[test]
+	fn test_build_xz() -> eyre::Result<()> {
+		let unpacked_dir = tempfile::tempdir()?;
+		let output_dir = tempfile::tempdir()?;
+		std::fs::create_dir_all(unpacked_dir.path().join("usr/bin"))?;
+		std::fs::write(unpacked_dir.path().join("usr/bin/xenomorph"), "ELF")?;
+
+		let mut target = TgzTarget {
+			info: PackageInfo {
+				name: "xenomorph".into(),
+				version: "0.1.0".into(),
+				..Default::default()
+			},
+			unpacked_dir: unpacked_dir.path().to_owned(),
+			compression: TgzCompression::Xz,
+			clamp_mtime: None,
+		};
+		let txz = target.build(output_dir.path())?;
+		assert_eq!(txz, output_dir.path().join("xenomorph-0.1.0.txz"));
+		assert!(target.test(&txz)?.is_empty());
+
+		let mut archive = open_tarball(&txz)?;
+		let names = archive
+			.entries()?
+			.map(|e| Ok(e?.path()?.into_owned()))
+			.collect::<std::io::Result<Vec<_>>>()?;
+		assert!(names.iter().any(|n| n.ends_with("usr/bin/xenomorph")));
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_build_preserves_symlinks() -> eyre::Result<()> {
+		let unpacked_dir = tempfile::tempdir()?;
+		let output_dir = tempfile::tempdir()?;
+
+		let lib_dir = unpacked_dir.path().join("usr/lib");
+		std::fs::create_dir_all(&lib_dir)?;
+		std::fs::write(lib_dir.join("libfoo.so.1"), "ELF")?;
+		std::os::unix::fs::symlink("libfoo.so.1", lib_dir.join("libfoo.so"))?;
+
+		let mut target = TgzTarget {
+			info: PackageInfo {
+				name: "libfoo".into(),
+				version: "1.0".into(),
+				..Default::default()
+			},
+			unpacked_dir: unpacked_dir.path().to_owned(),
+			compression: TgzCompression::Gzip,
+			clamp_mtime: None,
+		};
+		let tgz = target.build(output_dir.path())?;
+
+		let mut archive = open_tarball(&tgz)?;
+		let link = archive
+			.entries()?
+			.map(|e| e.map_err(eyre::Report::from))
+			.find(|e| {
+				e.as_ref()
+					.map_or(true, |e| e.path().is_ok_and(|p| p.ends_with("libfoo.so")))
+			})
+			.expect("symlink should be in the archive")?;
+
+		assert_eq!(link.header().entry_type(), tar::EntryType::Symlink);
+		assert_eq!(
+			link.link_name()?.as_deref(),
+			Some(std::path::Path::new("libfoo.so.1"))
+		);
+
+		Ok(())
+	}
+	#[test]
+	fn test_build_preserves_hard_links() -> eyre::Result<()> {
+		let unpacked_dir = tempfile::tempdir()?;
+		let output_dir = tempfile::tempdir()?;
+
+		let doc_dir = unpacked_dir.path().join("usr/share/doc");
+		std::fs::create_dir_all(&doc_dir)?;
+		std::fs::write(doc_dir.join("COPYING"), "GPL")?;
+		std::fs::hard_link(doc_dir.join("COPYING"), doc_dir.join("LICENSE"))?;
+
+		let mut target = TgzTarget {
+			info: PackageInfo {
+				name: "xenomorph".into(),
+				version: "0.1.0".into(),
+				..Default::default()
+			},
+			unpacked_dir: unpacked_dir.path().to_owned(),
+			compression: TgzCompression::Gzip,
+			clamp_mtime: None,
+		};
+		let tgz = target.build(output_dir.path())?;
+
+		let mut archive = open_tarball(&tgz)?;
+		let mut entries = vec![];
+		for entry in archive.entries()? {
+			let entry = entry?;
+			entries.push((
+				entry.path()?.into_owned(),
+				entry.header().entry_type(),
+				entry.link_name()?.map(Cow::into_owned),
+			));
+		}
+
+		assert!(entries.contains(&(
+			PathBuf::from("usr/share/doc/COPYING"),
+			tar::EntryType::Regular,
+			None
+		)));
+		assert!(entries.contains(&(
+			PathBuf::from("usr/share/doc/LICENSE"),
+			tar::EntryType::Link,
+			Some(PathBuf::from("usr/share/doc/COPYING"))
+		)));
+
+		Ok(())
+	}
 }
@@ -1,16 +1,24 @@
-use std::fmt::Debug;
+use std::fmt::{Debug, Write as _};
 
 use bpaf::{construct, long, Parser};
 use enumflags2::BitFlags;
 use eyre::{bail, Context, Result};
 use subprocess::{CaptureData, Exec, NullFile, Pipeline, Redirection};
+use time::OffsetDateTime;
 
-use crate::{Format, PackageInfo};
+use crate::{deb::DebCompression, tgz::TgzCompression, Format, PackageInfo};
 
 use std::{
-	os::unix::prelude::PermissionsExt,
-	path::{Path, PathBuf},
-	sync::OnceLock,
+	collections::HashMap,
+	fs::File,
+	io::{BufRead, BufReader, IsTerminal, Read},
+	os::unix::{fs::MetadataExt, prelude::PermissionsExt},
+	path::{Component, Path, PathBuf},
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Mutex, OnceLock, PoisonError,
+	},
+	time::{Duration, Instant},
 };
 
 #[allow(clippy::struct_excessive_bools)]
@@ -33,26 +41,219 @@ pub struct Args {
 	#[bpaf(short, long)]
 	pub generate: bool,
 
+	/// Read the package as this format (deb, rpm, lsb, tgz, pkg or apk),
+	/// instead of guessing it.
+	#[bpaf(argument("format"))]
+	pub from: Option<Format>,
+
+	/// Print the extracted package information as JSON, but do not build package.
+	pub dump_info: bool,
+
+	/// Print a summary of the package for reading, but do not build package.
+	pub show: bool,
+
+	/// Print the files in the package, marking conffiles, but do not build package.
+	pub list_files: bool,
+
+	/// Separate the files printed by --list-files with NUL characters instead
+	/// of newlines, and do not mark conffiles.
+	#[bpaf(short('0'), long)]
+	pub null: bool,
+
+	/// Print which maintainer scripts are in the package, but do not build package.
+	pub list_scripts: bool,
+
+	/// Write each maintainer script in the package to this directory for
+	/// inspection, even without --scripts, but do not build package.
+	#[bpaf(argument("dir"))]
+	pub extract_scripts: Option<PathBuf>,
+
+	/// Warn if a package for all architectures contains native binaries.
+	pub check_arch: bool,
+
+	/// Convert rpm packages built for operating systems other than Linux,
+	/// instead of refusing to.
+	pub allow_foreign_os: bool,
+
+	/// Report any `AppStream` metadata and desktop files in the package, which
+	/// software centers need to find it, and check that the metadata is valid.
+	pub report_metainfo: bool,
+
+	/// Print the size and SHA-256 checksum of each generated package.
+	pub checksums: bool,
+
+	/// Test generated packages: debs with lintian, rpms with rpmlint,
+	/// pkgs with pkgchk, and tgzs by reading them back.
+	pub test: bool,
+
+	/// Run this shell command on each generated package, with `{}` replaced
+	/// by the package's path, or the path appended if there's no `{}`.
+	#[bpaf(argument("cmd"))]
+	pub post_build: Option<String>,
+
+	/// Fail if the package lists files that don't exist, instead of dropping them.
+	pub strict_files: bool,
+
+	/// Keep the contents of deb packages in a temporary file while converting
+	/// them, instead of in memory. This is done for big packages regardless.
+	pub low_mem: bool,
+
+	/// Do not delete the unpacked directory if conversion fails.
+	pub keep_tree_on_error: bool,
+
+	/// Show which packages would be generated, and which commands would be run
+	/// on them for --post-build and --install, but do not unpack or build anything.
+	pub dry_run: bool,
+
 	/// Include scripts in package.
 	#[bpaf(short('c'), long)]
 	pub scripts: bool,
 
+	/// Keep all dependencies of the source package, even those that
+	/// may not exist on the target distribution.
+	pub keep_depends: bool,
+
+	/// Recommend this package be installed along with generated packages.
+	/// May be given more than once.
+	#[bpaf(argument("package"), many)]
+	pub recommends: Vec<String>,
+
+	/// Suggest this package be installed along with generated packages.
+	/// May be given more than once.
+	#[bpaf(argument("package"), many)]
+	pub suggests: Vec<String>,
+
 	/// Set architecture of the generated package.
 	#[bpaf(argument("arch"))]
 	pub target: Option<String>,
 
+	/// Do not mention the conversion in the description and changelog of
+	/// generated packages.
+	pub no_conversion_notice: bool,
+
+	/// Add this note to the changelog of generated packages, after the
+	/// entry saying that the package was converted.
+	#[bpaf(argument("text"))]
+	pub changelog_entry: Option<String>,
+
+	/// Give the generated packages this name, instead of the original package's.
+	#[bpaf(argument("name"))]
+	pub rename: Option<String>,
+
+	/// Move files of rpm packages under OLD to NEW instead, as with
+	/// `rpm --relocate`. May be given more than once.
+	#[bpaf(argument::<String>("OLD=NEW"), parse(parse_relocation), many)]
+	pub relocate: Vec<(PathBuf, PathBuf)>,
+
+	/// Leave files matching this glob, such as `/usr/share/doc/**`, out of
+	/// generated packages. May be given more than once.
+	#[bpaf(argument("glob"), many)]
+	pub exclude: Vec<glob::Pattern>,
+
+	/// Move files in /bin, /lib, /lib64 and /sbin into their counterparts in
+	/// /usr, for systems where those directories are symlinks into /usr.
+	pub merge_usr: bool,
+
+	/// Use this as the maintainer of generated packages, instead of guessing
+	/// one from the environment.
+	#[bpaf(argument("maintainer"))]
+	pub maintainer: Option<String>,
+
+	/// Make USER (and GROUP, if given) the owner of every file in generated
+	/// packages once installed, instead of whoever ran xenomorph.
+	#[bpaf(argument("USER[:GROUP]"))]
+	pub owner: Option<String>,
+
+	/// Put generated rpm packages in this group, instead of one based on the
+	/// original package's section.
+	#[bpaf(argument("group"))]
+	pub group: Option<String>,
+
+	/// Put generated deb packages in this section, instead of one based on the
+	/// original package's section or group.
+	#[bpaf(argument("section"))]
+	pub section: Option<String>,
+
+	/// Give generated deb packages this priority, instead of `optional`.
+	#[bpaf(argument("priority"))]
+	pub priority: Option<String>,
+
+	/// Record this as the distribution of generated rpm packages, instead of
+	/// the original package's.
+	#[bpaf(argument("name"))]
+	pub distribution: Option<String>,
+
+	/// Record this as the host generated rpm packages were built on, instead
+	/// of this machine's hostname.
+	#[bpaf(argument("host"))]
+	pub build_host: Option<String>,
+
 	/// Display each command xenomorph runs.
 	#[bpaf(external)]
 	pub verbosity: Verbosity,
 
+	/// Don't warn about not running as root, or about skipped scripts.
+	#[bpaf(short, long)]
+	pub quiet: bool,
+
+	/// Use the descriptions of rpm packages translated to this locale,
+	/// such as `de_DE`, if they have them.
+	#[bpaf(argument("locale"))]
+	pub lang: Option<String>,
+
 	/// Do not change version of generated package.
 	#[bpaf(short, long)]
 	pub keep_version: bool,
 
+	/// Do not bump the release of generated packages, nor add a 1 to
+	/// releases that aren't numbers when generating debs. Characters that
+	/// aren't allowed are still left out, but dpkg may reject the package
+	/// if the release is otherwise invalid.
+	pub keep_release: bool,
+
 	/// Increment package version by this number.
 	#[bpaf(argument("number"), fallback(1))]
 	pub bump: u32,
 
+	/// Set the version of generated packages to this, instead of the
+	/// original package's.
+	#[bpaf(argument("version"))]
+	pub set_version: Option<String>,
+
+	/// Set the release of generated packages to this, instead of bumping
+	/// the original package's.
+	#[bpaf(argument("release"))]
+	pub set_release: Option<String>,
+
+	/// Clamp the modification times of files in generated packages to at most
+	/// this many seconds since the Unix epoch.
+	#[bpaf(argument("epoch"))]
+	pub clamp_mtime: Option<u64>,
+
+	/// Leave details of the machine doing the conversion, like its hostname,
+	/// out of generated packages.
+	pub reproducible: bool,
+
+	/// Unpack packages into this directory, instead of the current directory.
+	#[bpaf(argument("dir"))]
+	pub build_dir: Option<PathBuf>,
+
+	/// Convert up to this many packages at once.
+	#[bpaf(short, long, argument("n"), fallback(1))]
+	pub jobs: usize,
+
+	/// Show how many files have been unpacked so far, when run in a terminal.
+	pub progress: bool,
+
+	/// Write generated packages to this directory.
+	#[bpaf(short, long, argument("dir"), fallback(PathBuf::from(".")))]
+	pub output_dir: PathBuf,
+
+	/// Read default options from this file, instead of
+	/// ~/.config/xenomorph/config.toml.
+	#[bpaf(argument("path"))]
+	pub config: Option<PathBuf>,
+
 	/// Package file or files to convert.
 	#[bpaf(positional("FILES"), some("You must specify a file to convert."))]
 	pub files: Vec<PathBuf>,
@@ -60,12 +261,15 @@ pub struct Args {
 #[derive(Debug, bpaf::Bpaf)]
 pub struct DebArgs {
 	/// Specify patch file to use instead of automatically looking for patch
-	/// in /var/lib/xenomorph.
+	/// in /var/lib/xenomorph, or a directory to look for patches in first.
 	#[bpaf(
 		argument("patch"),
-		guard(patch_file_exists, "Specified patch file cannot be found")
+		guard(patch_file_exists, "Specified patch file or directory cannot be found")
 	)]
 	pub patch: Option<PathBuf>,
+	/// Also look for patches in this directory, before the default ones.
+	#[bpaf(argument("dir"), many)]
+	pub patch_dir: Vec<PathBuf>,
 	/// Do not use patches.
 	pub nopatch: bool,
 	/// Use even old version os patches.
@@ -73,10 +277,27 @@ pub struct DebArgs {
 	/// Like --generate, but do not create .orig directory.
 	#[bpaf(short, long)]
 	pub single: bool,
+	/// Do not create a .orig directory or use patches, for faster builds
+	/// when only the binary package is wanted.
+	pub binary_only: bool,
 	/// Munge/fix permissions and owners.
 	pub fixperms: bool,
-	/// Test generated packages with lintian.
-	pub test: bool,
+	/// Compress the package's data with this algorithm: gzip, xz, zstd or none.
+	#[bpaf(argument("algo"))]
+	pub compression: Option<DebCompression>,
+	#[bpaf(external)]
+	pub strip: bool,
+	/// Do not compress documentation in the package.
+	pub no_compress: bool,
+	/// Build the package even if its architecture doesn't match this system's.
+	pub force_arch: bool,
+	/// Assemble the package directly instead of with debhelper, so that neither
+	/// debhelper nor dpkg-dev has to be installed. Binaries are not stripped,
+	/// and shared library dependencies are not worked out.
+	pub no_debhelper: bool,
+	/// List conffiles in /etc in debian/conffiles too, instead of leaving them
+	/// for debhelper to find. Implied by --no-debhelper.
+	pub explicit_conffiles: bool,
 }
 
 #[derive(Debug, bpaf::Bpaf)]
@@ -88,6 +309,11 @@ pub struct TgzArgs {
 	#[bpaf(argument("version"))]
 	/// Specify package version.
 	pub version: Option<String>,
+
+	/// Compress generated tgz packages with this algorithm: gzip (the default),
+	/// xz or zstd.
+	#[bpaf(argument("algo"))]
+	pub tgz_compression: Option<TgzCompression>,
 }
 
 fn formats() -> impl Parser<BitFlags<Format>> {
@@ -111,15 +337,146 @@ fn formats() -> impl Parser<BitFlags<Format>> {
 		.short('p')
 		.help("Generate a Solaris pkg package.")
 		.flag(BitFlags::from(Format::Pkg), BitFlags::empty());
+	let to_apk = long("to-apk")
+		.help("Generate an Alpine apk package.")
+		.flag(BitFlags::from(Format::Apk), BitFlags::empty());
+	let to_dir = long("to-dir")
+		.help("Copy the package's files into a directory, without generating a package.")
+		.flag(BitFlags::from(Format::Dir), BitFlags::empty());
+
+	// Left empty if none are given, so that the configuration file can pick
+	// the default. See `Args::apply_config`.
+	construct!(to_deb, to_rpm, to_lsb, to_tgz, to_pkg, to_apk, to_dir,)
+		.map(|(d, r, l, t, p, a, dir)| d | r | l | t | p | a | dir)
+}
+
+/// Options that can be set once in a configuration file, instead of on
+/// every run. Options given on the command line win.
+///
+/// The file is TOML, and its keys are named after the options they set,
+/// e.g. `output-dir = "/srv/packages"`.
+#[derive(Debug, Default)]
+#[cfg_attr(
+	feature = "serde",
+	derive(serde::Deserialize),
+	serde(default, deny_unknown_fields, rename_all = "kebab-case")
+)]
+pub struct Config {
+	/// Formats to convert to when no `--to-*` option is given, e.g. `["rpm"]`.
+	pub formats: Vec<String>,
+	pub maintainer: Option<String>,
+	pub scripts: bool,
+	pub fixperms: bool,
+	pub compression: Option<String>,
+	pub tgz_compression: Option<String>,
+	pub output_dir: Option<PathBuf>,
+}
+impl Config {
+	/// Reads the configuration file at `path`.
+	#[cfg(feature = "serde")]
+	pub fn load(path: &Path) -> Result<Self> {
+		let text = std::fs::read_to_string(path)
+			.wrap_err_with(|| format!("Unable to read {}", path.display()))?;
+		toml::from_str(&text).wrap_err_with(|| format!("Unable to parse {}", path.display()))
+	}
+
+	/// Where the configuration file is looked for when `--config` isn't given.
+	#[must_use]
+	pub fn default_path() -> Option<PathBuf> {
+		let config_dir = match std::env::var_os("XDG_CONFIG_HOME") {
+			Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+			_ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+		};
+		Some(config_dir.join("xenomorph/config.toml"))
+	}
+}
+
+impl Args {
+	/// Reads the file given with `--config`, or else the one at
+	/// [`Config::default_path`] if there is one, and fills in the options
+	/// it sets that weren't given on the command line.
+	pub fn read_config(&mut self) -> Result<()> {
+		let path = match &self.config {
+			Some(path) => Some(path.clone()),
+			None => Config::default_path().filter(|p| p.exists()),
+		};
+		let config = match path {
+			#[cfg(feature = "serde")]
+			Some(path) => Config::load(&path)?,
+			#[cfg(not(feature = "serde"))]
+			Some(_) => bail!("xenomorph was built without support for configuration files"),
+			None => Config::default(),
+		};
+		self.apply_config(config)
+	}
 
-	construct!(to_deb, to_rpm, to_lsb, to_tgz, to_pkg,).map(|(d, r, l, t, p)| {
-		let mut formats = d | r | l | t | p;
-		if formats.is_empty() {
-			// Default to deb
-			formats |= Format::Deb;
+	/// Fills in the options `config` sets that weren't given on the command
+	/// line, and defaults to converting to deb if no format was chosen.
+	///
+	/// Since switches can't be turned back off, a switch set in `config` is
+	/// always on, and an output directory of `.` counts as not given.
+	pub fn apply_config(&mut self, config: Config) -> Result<()> {
+		if self.formats.is_empty() {
+			for format in &config.formats {
+				self.formats |= format.parse::<Format>().map_err(eyre::Report::msg)?;
+			}
 		}
-		formats
-	})
+		if self.formats.is_empty() {
+			self.formats = Format::Deb.into();
+		}
+
+		self.maintainer = self.maintainer.take().or(config.maintainer);
+		self.scripts |= config.scripts;
+		self.deb_args.fixperms |= config.fixperms;
+		if self.deb_args.compression.is_none() {
+			if let Some(compression) = &config.compression {
+				self.deb_args.compression = Some(
+					compression
+						.parse::<DebCompression>()
+						.map_err(eyre::Report::msg)?,
+				);
+			}
+		}
+		if self.tgz_args.tgz_compression.is_none() {
+			if let Some(compression) = &config.tgz_compression {
+				self.tgz_args.tgz_compression = Some(
+					compression
+						.parse::<TgzCompression>()
+						.map_err(eyre::Report::msg)?,
+				);
+			}
+		}
+		if let Some(output_dir) = config.output_dir {
+			if self.output_dir == Path::new(".") {
+				self.output_dir = output_dir;
+			}
+		}
+		Ok(())
+	}
+}
+
+fn strip() -> impl Parser<bool> {
+	let strip = long("strip")
+		.help("Strip debugging symbols from binaries, unless DEB_BUILD_OPTIONS contains nostrip.")
+		.req_flag(true);
+	let no_strip = long("no-strip")
+		.help("Do not strip binaries (default).")
+		.req_flag(false);
+
+	construct!([strip, no_strip]).fallback(false)
+}
+
+fn parse_relocation(mut s: String) -> Result<(PathBuf, PathBuf), String> {
+	let Some(index) = s.find('=') else {
+		return Err(format!("relocation `{s}` is not of the form OLD=NEW"));
+	};
+	let new = s.split_off(index + 1);
+	s.pop();
+
+	if !s.starts_with('/') || !new.starts_with('/') {
+		return Err(format!("relocation `{s}={new}` must use absolute paths"));
+	}
+	Ok((PathBuf::from(s), PathBuf::from(new)))
 }
 
 fn patch_file_exists(s: &Option<PathBuf>) -> bool {
@@ -146,44 +503,128 @@ fn verbosity() -> impl Parser<Verbosity> {
 	})
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+/// How much `xenomorph` tells about what it's doing.
+///
+/// Each package keeps the verbosity from the [`Args`] it was read or
+/// created with, so that conversions with different verbosities can run
+/// side by side.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
 pub enum Verbosity {
+	#[default]
 	Normal,
 	Verbose,
 	VeryVerbose,
 }
 impl Verbosity {
+	#[deprecated(note = "`xenomorph` no longer reads this; set `Args::verbosity` instead")]
 	pub fn set(self) {
 		VERBOSITY.set(self).unwrap();
 	}
+	/// Returns the verbosity given to [`Self::set`], or [`Verbosity::Normal`]
+	/// if it was never set.
+	#[deprecated(note = "`xenomorph` no longer reads this; use `Args::verbosity` instead")]
 	pub fn get() -> Verbosity {
-		*VERBOSITY.get().unwrap()
+		VERBOSITY.get().copied().unwrap_or(Verbosity::Normal)
 	}
 }
 static VERBOSITY: OnceLock<Verbosity> = OnceLock::new();
 
-pub(crate) trait ExecExt {
+/// When set, and stderr is a terminal, long-running operations report their
+/// progress through [`Progress`].
+pub fn set_progress(progress: bool) {
+	PROGRESS.store(
+		progress && std::io::stderr().is_terminal(),
+		Ordering::Relaxed,
+	);
+}
+static PROGRESS: AtomicBool = AtomicBool::new(false);
+
+/// A counter of processed files, redrawn on stderr as it goes up.
+///
+/// Nothing is printed unless enabled with [`set_progress`].
+pub(crate) struct Progress {
+	label: &'static str,
+	total: Option<usize>,
+	done: usize,
+	last_drawn: Option<Instant>,
+}
+impl Progress {
+	pub fn new(label: &'static str, total: Option<usize>) -> Self {
+		Self {
+			label,
+			total,
+			done: 0,
+			last_drawn: None,
+		}
+	}
+	pub fn is_enabled() -> bool {
+		PROGRESS.load(Ordering::Relaxed)
+	}
+	pub fn inc(&mut self) {
+		self.done += 1;
+		// Redrawing for every file would slow down unpacking large packages.
+		match self.last_drawn {
+			Some(t) if t.elapsed() < Duration::from_millis(100) => {}
+			_ => self.draw(),
+		}
+	}
+	pub fn finish(mut self) {
+		self.draw();
+		if Self::is_enabled() {
+			eprintln!();
+		}
+	}
+	fn draw(&mut self) {
+		if !Self::is_enabled() {
+			return;
+		}
+		match self.total {
+			Some(total) => eprint!("\r{}: {}/{total} files", self.label, self.done),
+			None => eprint!("\r{}: {} files", self.label, self.done),
+		}
+		self.last_drawn = Some(Instant::now());
+	}
+}
+
+/// Running commands while displaying them according to the [`Verbosity`].
+///
+/// Commands, and their output when very verbose, are displayed on stderr,
+/// so that stdout is left for the paths of generated packages.
+pub(crate) trait ExecExt: Sized {
 	type Output;
 
-	fn log_and_spawn(self, verbosity: impl Into<Option<Verbosity>>) -> Result<()>;
+	/// The command line, as it would be typed into a shell.
+	fn to_cmdline(&self) -> String;
+
+	fn log_and_spawn(self, verbosity: Verbosity) -> Result<()>;
 
 	#[must_use = "Use `log_and_spawn` if you just want to spawn a command and forget about it"]
-	fn log_and_output(self, verbosity: impl Into<Option<Verbosity>>) -> Result<CaptureData>;
+	fn log_and_output(self, verbosity: Verbosity) -> Result<CaptureData>;
 
 	#[must_use = "Use `log_and_spawn` if you just want to spawn a command and forget about it"]
-	fn log_and_output_without_checking(
-		self,
-		verbosity: impl Into<Option<Verbosity>>,
-	) -> Result<CaptureData>;
+	fn log_and_output_without_checking(self, verbosity: Verbosity) -> Result<CaptureData>;
+
+	/// Like [`ExecExt::log_and_spawn`], but for a dry run the command is only
+	/// printed, and not run.
+	fn log_and_spawn_unless_dry_run(self, verbosity: Verbosity, dry_run: bool) -> Result<()> {
+		if dry_run {
+			println!("Would run {}", self.to_cmdline());
+			return Ok(());
+		}
+		self.log_and_spawn(verbosity)
+	}
 }
 impl ExecExt for Exec {
 	type Output = CaptureData;
 
-	fn log_and_spawn(mut self, verbosity: impl Into<Option<Verbosity>>) -> Result<()> {
-		let verbosity = verbosity.into().unwrap_or_else(Verbosity::get);
+	fn to_cmdline(&self) -> String {
+		self.to_cmdline_lossy()
+	}
+
+	fn log_and_spawn(mut self, verbosity: Verbosity) -> Result<()> {
 		let cmdline = self.to_cmdline_lossy();
 		if verbosity != Verbosity::Normal {
-			println!("\t{cmdline}");
+			eprintln!("\t{cmdline}");
 		}
 		if verbosity != Verbosity::VeryVerbose {
 			self = self.stdout(NullFile);
@@ -198,29 +639,25 @@ impl ExecExt for Exec {
 		Ok(())
 	}
 
-	fn log_and_output(self, verbosity: impl Into<Option<Verbosity>>) -> Result<CaptureData> {
+	fn log_and_output(self, verbosity: Verbosity) -> Result<CaptureData> {
 		let out = self.log_and_output_without_checking(verbosity)?;
 		if !out.success() {
 			bail!("Error executing command - stderr:\n{}", out.stderr_str())
 		}
 		Ok(out)
 	}
-	fn log_and_output_without_checking(
-		mut self,
-		verbosity: impl Into<Option<Verbosity>>,
-	) -> Result<CaptureData> {
-		let verbosity = verbosity.into().unwrap_or_else(Verbosity::get);
+	fn log_and_output_without_checking(mut self, verbosity: Verbosity) -> Result<CaptureData> {
 		self = self.stdout(Redirection::Pipe);
 
 		let cmdline = self.to_cmdline_lossy();
 		if verbosity != Verbosity::Normal {
-			println!("\t{cmdline}");
+			eprintln!("\t{cmdline}");
 		}
 		let output = self.capture()?;
 
 		if verbosity == Verbosity::VeryVerbose {
 			let stdout = String::from_utf8_lossy(&output.stdout);
-			println!("{stdout}");
+			eprintln!("{stdout}");
 		}
 		Ok(output)
 	}
@@ -229,10 +666,13 @@ impl ExecExt for Exec {
 impl ExecExt for Pipeline {
 	type Output = CaptureData;
 
-	fn log_and_spawn(mut self, verbosity: impl Into<Option<Verbosity>>) -> Result<()> {
-		let verbosity = verbosity.into().unwrap_or_else(Verbosity::get);
+	fn to_cmdline(&self) -> String {
+		format!("{self:?}")
+	}
+
+	fn log_and_spawn(mut self, verbosity: Verbosity) -> Result<()> {
 		if verbosity != Verbosity::Normal {
-			println!("\t{self:?}");
+			eprintln!("\t{self:?}");
 		}
 		if verbosity != Verbosity::VeryVerbose {
 			self = self.stdout(NullFile);
@@ -247,48 +687,84 @@ impl ExecExt for Pipeline {
 		Ok(())
 	}
 
-	fn log_and_output(self, verbosity: impl Into<Option<Verbosity>>) -> Result<CaptureData> {
+	fn log_and_output(self, verbosity: Verbosity) -> Result<CaptureData> {
 		let out = self.log_and_output_without_checking(verbosity)?;
 		if !out.success() {
 			bail!("Error executing command - stderr:\n{}", out.stderr_str())
 		}
 		Ok(out)
 	}
-	fn log_and_output_without_checking(
-		self,
-		verbosity: impl Into<Option<Verbosity>>,
-	) -> Result<CaptureData> {
-		let verbosity = verbosity.into().unwrap_or_else(Verbosity::get);
+	fn log_and_output_without_checking(self, verbosity: Verbosity) -> Result<CaptureData> {
 		if verbosity != Verbosity::Normal {
-			println!("\t{self:?}");
+			eprintln!("\t{self:?}");
 		}
 		let output = self.capture()?;
 
 		if verbosity == Verbosity::VeryVerbose {
 			let stdout = String::from_utf8_lossy(&output.stdout);
-			println!("{stdout}");
+			eprintln!("{stdout}");
 		}
 		Ok(output)
 	}
 }
 
+/// Runs a pipeline like [`ExecExt::log_and_spawn`], counting each line it
+/// writes to stdout as one more file processed.
+pub(crate) fn spawn_with_progress(
+	pipeline: Pipeline,
+	mut progress: Progress,
+	verbosity: Verbosity,
+) -> Result<()> {
+	let cmdline = format!("{pipeline:?}");
+	if verbosity != Verbosity::Normal {
+		eprintln!("\t{cmdline}");
+	}
+
+	let mut children = pipeline.stdout(Redirection::Pipe).popen()?;
+	if let Some(stdout) = children.last_mut().and_then(|c| c.stdout.take()) {
+		for line in BufReader::new(stdout).split(b'\n') {
+			line?;
+			progress.inc();
+		}
+	}
+	progress.finish();
+
+	for child in &mut children {
+		if !child.wait()?.success() {
+			bail!("Error executing command {cmdline}");
+		}
+	}
+	Ok(())
+}
+
+/// Runs a command that installs a package, showing everything it prints.
+///
+/// For a dry run, the command is only printed.
+pub(crate) fn spawn_install(cmd: Exec, dry_run: bool) -> Result<()> {
+	cmd.log_and_spawn_unless_dry_run(Verbosity::VeryVerbose, dry_run)
+}
+
 #[cfg(unix)]
-pub(crate) fn mkdir<P: AsRef<Path>>(path: P) -> std::io::Result<()> {
-	fn _mkdir(path: &Path) -> std::io::Result<()> {
-		if let Some(Verbosity::Verbose) = VERBOSITY.get() {
-			println!("\tmkdir {}", path.display());
+pub(crate) fn mkdir<P: AsRef<Path>>(path: P, verbosity: Verbosity) -> std::io::Result<()> {
+	fn _mkdir(path: &Path, verbosity: Verbosity) -> std::io::Result<()> {
+		if verbosity == Verbosity::Verbose {
+			eprintln!("\tmkdir {}", path.display());
 		}
 
 		std::fs::create_dir(path)
 	}
-	_mkdir(path.as_ref())
+	_mkdir(path.as_ref(), verbosity)
 }
 
 #[cfg(unix)]
-pub(crate) fn chmod<P: AsRef<Path>>(path: P, mode: u32) -> std::io::Result<()> {
-	fn _chmod(path: &Path, mode: u32) -> std::io::Result<()> {
-		if let Some(Verbosity::Verbose) = VERBOSITY.get() {
-			println!("\tchmod {mode:o} {}", path.display());
+pub(crate) fn chmod<P: AsRef<Path>>(
+	path: P,
+	mode: u32,
+	verbosity: Verbosity,
+) -> std::io::Result<()> {
+	fn _chmod(path: &Path, mode: u32, verbosity: Verbosity) -> std::io::Result<()> {
+		if verbosity == Verbosity::Verbose {
+			eprintln!("\tchmod {mode:o} {}", path.display());
 		}
 
 		let mut perms = std::fs::metadata(path)?.permissions();
@@ -296,40 +772,644 @@ pub(crate) fn chmod<P: AsRef<Path>>(path: P, mode: u32) -> std::io::Result<()> {
 		std::fs::set_permissions(path, perms)?;
 		Ok(())
 	}
-	_chmod(path.as_ref(), mode)
+	_chmod(path.as_ref(), mode, verbosity)
 }
 
 #[cfg(not(unix))]
-pub(crate) fn chmod(_path: &Path, _mode: u32) -> std::io::Result<()> {
+pub(crate) fn chmod(_path: &Path, _mode: u32, _verbosity: Verbosity) -> std::io::Result<()> {
 	// do nothing :p
 }
 
-pub(crate) fn make_unpack_work_dir(info: &PackageInfo) -> Result<PathBuf> {
-	let work_dir = format!("{}-{}", info.name, info.version);
-	mkdir(&work_dir).wrap_err_with(|| format!("unable to mkdir {work_dir}"))?;
+/// Moves a file, falling back to copying and removing the original
+/// when the destination lies on a different filesystem.
+pub(crate) fn move_file(from: &Path, to: &Path) -> std::io::Result<()> {
+	if std::fs::rename(from, to).is_err() {
+		std::fs::copy(from, to)?;
+		std::fs::remove_file(from)?;
+	}
+	Ok(())
+}
+
+/// Turns a path inside an archive, which may be relative, start with `./`
+/// or already be absolute depending on the tool that made the archive,
+/// into the absolute path it's installed to.
+pub(crate) fn normalize_archive_path(path: &Path) -> PathBuf {
+	let mut normalized = PathBuf::from("/");
+	normalized.extend(
+		path.components()
+			.filter(|c| !matches!(c, Component::RootDir | Component::CurDir)),
+	);
+	normalized
+}
+
+/// Unpacks an archive into `dst`, refusing to unpack any entry that would
+/// end up outside of it.
+///
+/// Like `tar::Archive::unpack`, directories are unpacked last, deepest first,
+/// so that read-only directories don't stop their contents from being unpacked.
+pub(crate) fn unpack_archive<R: Read>(
+	archive: &mut tar::Archive<R>,
+	dst: &Path,
+) -> std::io::Result<()> {
+	let mut progress = Progress::new("Unpacking", None);
+
+	std::fs::create_dir_all(dst)?;
+	let mut dirs = vec![];
+	for entry in archive.entries()? {
+		let mut entry = entry?;
+		check_entry_inside(&entry)?;
+		if entry.header().entry_type() == tar::EntryType::Directory {
+			dirs.push(entry);
+		} else {
+			entry.unpack_in(dst)?;
+			progress.inc();
+		}
+	}
+	dirs.sort_by(|a, b| b.path_bytes().cmp(&a.path_bytes()));
+	for mut dir in dirs {
+		dir.unpack_in(dst)?;
+		progress.inc();
+	}
+	progress.finish();
+	Ok(())
+}
+
+/// Checks that an archive entry, and the file it's a hard link to if any,
+/// can't be unpacked outside of the directory the archive is unpacked into.
+///
+/// Absolute paths are fine, since they're unpacked relative to that directory.
+/// `tar` would silently skip entries with `..` in them, but a package that has
+/// any is either broken or malicious, so it's better to stop altogether.
+pub(crate) fn check_entry_inside<R: Read>(entry: &tar::Entry<'_, R>) -> std::io::Result<()> {
+	let escapes = |path: &Path| path.components().any(|c| c == Component::ParentDir);
+
+	let path = entry.path()?;
+	let link = match entry.header().entry_type() {
+		tar::EntryType::Link => entry.link_name()?,
+		_ => None,
+	};
+	if escapes(&path) || link.as_deref().is_some_and(escapes) {
+		return Err(std::io::Error::new(
+			std::io::ErrorKind::InvalidData,
+			format!(
+				"Refusing to unpack {}, which would end up outside of the package",
+				path.display()
+			),
+		));
+	}
+	Ok(())
+}
+
+/// Work directories handed out so far, so that packages with the same name
+/// and version being converted at the same time don't share one.
+static WORK_DIRS: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// Makes a uniquely-named work dir for the package under `build_dir`,
+/// or the current directory if not given, returning its absolute path.
+pub(crate) fn make_unpack_work_dir(
+	info: &PackageInfo,
+	build_dir: Option<&Path>,
+	verbosity: Verbosity,
+) -> Result<PathBuf> {
+	let build_dir = match build_dir {
+		Some(dir) => std::env::current_dir()?.join(dir),
+		None => std::env::current_dir()?,
+	};
+	let work_dir = {
+		let mut work_dirs = WORK_DIRS.lock().unwrap_or_else(PoisonError::into_inner);
+
+		let base = format!("{}-{}", info.name, info.version);
+		let mut work_dir = build_dir.join(&base);
+		let mut n = 1;
+		while work_dirs.contains(&work_dir) {
+			n += 1;
+			work_dir = build_dir.join(format!("{base}.{n}"));
+		}
+		work_dirs.push(work_dir.clone());
+		work_dir
+	};
+	mkdir(&work_dir, verbosity)
+		.wrap_err_with(|| format!("unable to mkdir {}", work_dir.display()))?;
 
 	// If the parent directory is suid/guid, mkdir will make the root
 	// directory of the package inherit those bits. That is a bad thing,
 	// so explicitly force perms to 755.
 
-	chmod(&work_dir, 0o755)?;
-	Ok(PathBuf::from(work_dir))
+	chmod(&work_dir, 0o755, verbosity)?;
+	Ok(work_dir)
+}
+
+/// The time generated packages are built at, as recorded in changelogs.
+pub(crate) fn fetch_build_date() -> Result<OffsetDateTime> {
+	build_date(std::env::var("SOURCE_DATE_EPOCH").ok())
+}
+
+/// Works out the build time from the value of `SOURCE_DATE_EPOCH`, so that
+/// builds can be reproduced, falling back to the current time.
+fn build_date(epoch: Option<String>) -> Result<OffsetDateTime> {
+	let Some(epoch) = epoch else {
+		return Ok(OffsetDateTime::now_local().unwrap_or_else(|_| OffsetDateTime::now_utc()));
+	};
+	let seconds = epoch
+		.trim()
+		.parse()
+		.wrap_err_with(|| format!("SOURCE_DATE_EPOCH ({epoch}) is not a number of seconds"))?;
+	Ok(OffsetDateTime::from_unix_timestamp(seconds)?)
 }
 
-pub(crate) fn fetch_email_address() -> String {
+pub(crate) fn fetch_email_address() -> Result<String> {
 	// TODO: how can this possibly work on windows?
 	// Also TODO: just ask the user for their email address. ffs.
 	// I don't have EMAIL set, and nor do i have `/etc/mailname`,
 	// so now I'm stuck with leah@procrastinator, which of course, is not a real email address.
+	email_address(std::env::var("EMAIL").ok(), Path::new("/etc/mailname"))
+}
+
+/// Works out an email address from the value of `EMAIL`, falling back to
+/// the current user at the domain in `mailname`, or the hostname if it
+/// doesn't exist.
+fn email_address(email: Option<String>, mailname: &Path) -> Result<String> {
+	if let Some(email) = email {
+		return Ok(email);
+	}
+	let mailname = match std::fs::read_to_string(mailname) {
+		Ok(mailname) => mailname.trim().to_owned(),
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+			whoami::fallible::hostname().unwrap_or_else(|_| "<unknown>".to_owned())
+		}
+		Err(e) => return Err(e).wrap_err_with(|| format!("Failed to read {}", mailname.display())),
+	};
+	let username = whoami::username();
+	Ok(format!("{username}@{mailname}"))
+}
+
+/// Warns about native binaries in a package that claims to be for all
+/// architectures, which is a packaging mistake that happens in the wild.
+pub(crate) fn check_arch_independent(
+	info: &PackageInfo,
+	unpacked_dir: &Path,
+	verbosity: Verbosity,
+) -> Result<()> {
+	if info.arch != "all" && info.arch != "noarch" {
+		return Ok(());
+	}
+	let binaries = find_elf_files(unpacked_dir)?;
+	if binaries.is_empty() {
+		return Ok(());
+	}
+
+	eprintln!(
+		"Warning: {} is for all architectures, but contains {} native binaries.",
+		info.name,
+		binaries.len()
+	);
+	if verbosity == Verbosity::Normal {
+		eprintln!("Warning: Use --verbose to see which files are binaries.");
+	} else {
+		for file in binaries {
+			let file = file.strip_prefix(unpacked_dir).unwrap_or(&file);
+			eprintln!("\t/{}", file.display());
+		}
+	}
+	Ok(())
+}
+
+/// Checks that the external tools needed to read (if `as_source`) or build
+/// packages of the given format are installed, listing all that are missing.
+pub fn preflight(format: Format, as_source: bool) -> Result<()> {
+	let action = if as_source { "read" } else { "build" };
+	check_tools(
+		&format!("{action} {format} packages"),
+		required_tools(format, as_source),
+		|tool| which::which(tool).is_ok(),
+	)
+}
 
-	if let Ok(email) = std::env::var("EMAIL") {
-		email
+/// The external tools needed to read or build packages of the given format,
+/// along with the package that provides each.
+fn required_tools(format: Format, as_source: bool) -> &'static [(&'static str, &'static str)] {
+	match (format, as_source) {
+		(Format::Lsb | Format::Rpm, true) => &[("rpm2cpio", "rpm2cpio"), ("cpio", "cpio")],
+		(Format::Pkg, true) => &[
+			("pkginfo", "SVR4 packaging tools"),
+			("pkgtrans", "SVR4 packaging tools"),
+		],
+		(Format::Deb, false) => &[
+			("make", "make"),
+			("dh_testdir", "debhelper"),
+			("dpkg-architecture", "dpkg-dev"),
+		],
+		(Format::Lsb | Format::Rpm, false) => &[("rpm", "rpm"), ("rpmbuild", "rpm-build")],
+		(Format::Pkg, false) => &[
+			("pkgproto", "SVR4 packaging tools"),
+			("pkgmk", "SVR4 packaging tools"),
+			("pkgtrans", "SVR4 packaging tools"),
+		],
+		_ => &[],
+	}
+}
+
+fn check_tools(purpose: &str, tools: &[(&str, &str)], exists: impl Fn(&str) -> bool) -> Result<()> {
+	let missing: Vec<_> = tools.iter().filter(|(tool, _)| !exists(tool)).collect();
+	if missing.is_empty() {
+		return Ok(());
+	}
+
+	let mut message = format!("Some tools needed to {purpose} are missing:");
+	for (tool, package) in missing {
+		_ = write!(message, "\n\t{tool} (from {package})");
+	}
+	bail!(message)
+}
+
+/// Sums up the sizes of all files under `dir`, in KiB, rounding up.
+pub(crate) fn installed_size(dir: &Path) -> Result<u64> {
+	fn size(dir: &Path) -> Result<u64> {
+		let mut total = 0;
+		for entry in std::fs::read_dir(dir)? {
+			let entry = entry?;
+			let meta = entry.metadata()?;
+			// Symlinks aren't followed, since they'd be counted twice otherwise.
+			if meta.is_dir() {
+				total += size(&entry.path())?;
+			} else {
+				total += meta.len();
+			}
+		}
+		Ok(total)
+	}
+	Ok(size(dir)?.div_ceil(1024))
+}
+
+/// Finds groups of files under `dir` that are hard links to each other,
+/// relative to `dir`. Each group is sorted, and has at least two files.
+pub(crate) fn find_hard_links(dir: &Path) -> Result<Vec<Vec<PathBuf>>> {
+	// Symlinked directories aren't followed, or the files in them would be
+	// found twice, under paths that aren't in the package.
+	fn walk(
+		dir: &Path,
+		prefix: &Path,
+		inodes: &mut HashMap<(u64, u64), Vec<PathBuf>>,
+	) -> Result<()> {
+		for entry in std::fs::read_dir(dir)? {
+			let entry = entry?;
+			let path = prefix.join(entry.file_name());
+			let file_type = entry.file_type()?;
+
+			if file_type.is_dir() {
+				walk(&entry.path(), &path, inodes)?;
+			} else if file_type.is_file() {
+				let meta = entry.metadata()?;
+				if meta.nlink() > 1 {
+					inodes
+						.entry((meta.dev(), meta.ino()))
+						.or_default()
+						.push(path);
+				}
+			}
+		}
+		Ok(())
+	}
+
+	let mut inodes = HashMap::new();
+	walk(dir, Path::new(""), &mut inodes)?;
+
+	// Links to files outside of the tree don't matter.
+	let mut groups: Vec<_> = inodes.into_values().filter(|g| g.len() > 1).collect();
+	for group in &mut groups {
+		group.sort();
+	}
+	groups.sort();
+	Ok(groups)
+}
+
+/// Finds all ELF files under `dir`, without following symlinks.
+fn find_elf_files(dir: &Path) -> Result<Vec<PathBuf>> {
+	let mut found = vec![];
+
+	let mut entries = std::fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+	entries.sort_by_key(std::fs::DirEntry::file_name);
+	for entry in entries {
+		let path = entry.path();
+		let file_type = entry.file_type()?;
+
+		if file_type.is_dir() {
+			found.extend(find_elf_files(&path)?);
+		} else if file_type.is_file() {
+			let mut magic = Vec::with_capacity(4);
+			File::open(&path)?.take(4).read_to_end(&mut magic)?;
+			if magic == b"\x7fELF" {
+				found.push(path);
+			}
+		}
+	}
+	Ok(found)
+}
+
+/// Guesses the format of a package from the magic bytes at its start,
+/// for when its file name gives no indication.
+///
+/// Compressed tarballs are assumed to be `.tgz` packages.
+pub fn sniff(file: &Path) -> std::io::Result<Option<Format>> {
+	// Short files are fine, they just won't match anything.
+	let mut magic = Vec::with_capacity(8);
+	File::open(file)?.take(8).read_to_end(&mut magic)?;
+
+	let format = if magic.starts_with(b"!<arch>\n") {
+		Some(Format::Deb)
+	} else if magic.starts_with(&[0xed, 0xab, 0xee, 0xdb]) {
+		Some(Format::Rpm)
+	} else if magic.starts_with(&[0x1f, 0x8b])
+		|| magic.starts_with(&[0xfd, b'7', b'z', b'X', b'Z'])
+		|| magic.starts_with(b"BZh")
+		|| magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd])
+	{
+		Some(Format::Tgz)
 	} else {
-		let mailname = std::fs::read_to_string("/etc/mailname")
-			.or_else(|_| whoami::fallible::hostname())
-			.unwrap_or("<unknown>".to_owned());
+		None
+	};
+	Ok(format)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::path::{Path, PathBuf};
 
+	use time::OffsetDateTime;
+
+	use super::{
+		build_date, check_tools, email_address, find_elf_files, find_hard_links, installed_size,
+		make_unpack_work_dir, normalize_archive_path, required_tools, sniff, unpack_archive,
+		Verbosity,
+	};
+	use crate::{Format, PackageInfo};
+
+	#[test]
+	fn test_check_tools() {
+		let installed = ["make", "dh_testdir", "rpm"];
+		let exists = |tool: &str| installed.contains(&tool);
+
+		let tools = required_tools(Format::Deb, false);
+		assert!(check_tools("build deb packages", tools, |_| false).is_err());
+		assert!(check_tools("build deb packages", tools, |_| true).is_ok());
+
+		let err = check_tools("build deb packages", tools, exists).unwrap_err();
+		assert_eq!(
+			err.to_string(),
+			"Some tools needed to build deb packages are missing:\n\tdpkg-architecture (from dpkg-dev)"
+		);
+		let err = check_tools(
+			"read rpm packages",
+			required_tools(Format::Rpm, true),
+			exists,
+		)
+		.unwrap_err();
+		assert_eq!(
+			err.to_string(),
+			"Some tools needed to read rpm packages are missing:\n\trpm2cpio (from rpm2cpio)\n\tcpio (from cpio)"
+		);
+
+		// Nothing is needed to read debs or build tarballs.
+		assert!(required_tools(Format::Deb, true).is_empty());
+		assert!(required_tools(Format::Tgz, false).is_empty());
+	}
+
+	#[test]
+	fn test_unpack_archive_traversal() -> eyre::Result<()> {
+		// `tar::Builder` refuses to write paths like these, so they're
+		// put into the header by hand.
+		let archive = |name: &[u8], link: Option<&[u8]>| -> std::io::Result<Vec<u8>> {
+			let mut builder = tar::Builder::new(vec![]);
+			let mut header = tar::Header::new_old();
+			header.as_old_mut().name[..name.len()].copy_from_slice(name);
+			if let Some(link) = link {
+				header.set_entry_type(tar::EntryType::Link);
+				header.as_old_mut().linkname[..link.len()].copy_from_slice(link);
+				header.set_size(0);
+			} else {
+				header.set_size(4);
+			}
+			header.set_mode(0o644);
+			header.set_cksum();
+			builder.append(&header, &b"evil"[..link.map_or(4, |_| 0)])?;
+			builder.into_inner()
+		};
+
+		let dir = tempfile::tempdir()?;
+		let dst = dir.path().join("work");
+
+		let evil = archive(b"../evil", None)?;
+		let err = unpack_archive(&mut tar::Archive::new(evil.as_slice()), &dst).unwrap_err();
+		assert_eq!(
+			err.to_string(),
+			"Refusing to unpack ../evil, which would end up outside of the package"
+		);
+		assert!(!dir.path().join("evil").exists());
+
+		let evil = archive(b"./usr/bin/evil", Some(b"../../../evil"))?;
+		assert!(unpack_archive(&mut tar::Archive::new(evil.as_slice()), &dst).is_err());
+
+		// Absolute paths end up inside the destination.
+		let fine = archive(b"/etc/fine", None)?;
+		unpack_archive(&mut tar::Archive::new(fine.as_slice()), &dst)?;
+		assert_eq!(std::fs::read(dst.join("etc/fine"))?, b"evil");
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_email_address() -> eyre::Result<()> {
+		let dir = tempfile::tempdir()?;
+		let mailname = dir.path().join("mailname");
 		let username = whoami::username();
-		format!("{username}@{mailname}")
+
+		assert_eq!(
+			email_address(Some("hi@pluie.me".into()), &mailname)?,
+			"hi@pluie.me"
+		);
+		// A missing mailname falls back to the hostname.
+		assert!(email_address(None, &mailname)?.starts_with(&format!("{username}@")));
+
+		std::fs::write(&mailname, "pluie.me\n")?;
+		assert_eq!(
+			email_address(None, &mailname)?,
+			format!("{username}@pluie.me")
+		);
+
+		// Other errors, like the mailname being a directory, aren't swallowed.
+		assert!(email_address(None, dir.path()).is_err());
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_build_date() -> eyre::Result<()> {
+		// Fri, 01 Mar 2024 12:00:00 +0000
+		assert_eq!(
+			build_date(Some("1709294400".into()))?,
+			OffsetDateTime::from_unix_timestamp(1_709_294_400)?
+		);
+		assert_eq!(build_date(Some(" 0\n".into()))?, OffsetDateTime::UNIX_EPOCH);
+		assert!(build_date(Some("yesterday".into())).is_err());
+		assert!(build_date(None).is_ok());
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_normalize_archive_path() {
+		for path in [
+			"./usr/bin/xenomorph",
+			"usr/bin/xenomorph",
+			"/usr/bin/xenomorph",
+		] {
+			assert_eq!(
+				normalize_archive_path(Path::new(path)),
+				Path::new("/usr/bin/xenomorph")
+			);
+		}
+		assert_eq!(normalize_archive_path(Path::new("./")), Path::new("/"));
+	}
+	#[test]
+	fn test_find_elf_files() -> eyre::Result<()> {
+		let dir = tempfile::tempdir()?;
+		std::fs::create_dir_all(dir.path().join("usr/bin"))?;
+		std::fs::write(dir.path().join("usr/bin/foo"), b"\x7fELF\x02\x01\x01")?;
+		std::fs::write(dir.path().join("usr/bin/bar"), "#!/bin/sh\n")?;
+		std::fs::write(dir.path().join("usr/bin/e"), "")?;
+		std::os::unix::fs::symlink("foo", dir.path().join("usr/bin/baz"))?;
+
+		assert_eq!(
+			find_elf_files(dir.path())?,
+			[dir.path().join("usr/bin/foo")]
+		);
+		Ok(())
+	}
+
+	#[test]
+	fn test_installed_size() -> eyre::Result<()> {
+		let dir = tempfile::tempdir()?;
+		std::fs::create_dir_all(dir.path().join("usr/bin"))?;
+		std::fs::write(dir.path().join("usr/bin/foo"), vec![0; 2048])?;
+		std::fs::write(dir.path().join("usr/bin/bar"), "bar")?;
+
+		assert_eq!(installed_size(dir.path())?, 3);
+		Ok(())
+	}
+
+	#[test]
+	fn test_find_hard_links() -> eyre::Result<()> {
+		let dir = tempfile::tempdir()?;
+		let lib = dir.path().join("usr/lib");
+		std::fs::create_dir_all(&lib)?;
+		std::fs::write(lib.join("libfoo.so.1"), "foo")?;
+		std::fs::hard_link(lib.join("libfoo.so.1"), lib.join("libfoo.so"))?;
+		std::fs::write(lib.join("libbar.so"), "bar")?;
+		// Neither of these are followed, so the links aren't found twice and
+		// the walk doesn't go round in circles.
+		std::os::unix::fs::symlink("usr/lib", dir.path().join("lib"))?;
+		std::os::unix::fs::symlink(".", dir.path().join("current"))?;
+
+		assert_eq!(
+			find_hard_links(dir.path())?,
+			[vec![
+				PathBuf::from("usr/lib/libfoo.so"),
+				PathBuf::from("usr/lib/libfoo.so.1")
+			]]
+		);
+		Ok(())
+	}
+
+	#[test]
+	fn test_make_unpack_work_dir() -> eyre::Result<()> {
+		let build_dir = tempfile::tempdir()?;
+		let info = PackageInfo {
+			name: "xenomorph".into(),
+			version: "0.1.0".into(),
+			..Default::default()
+		};
+
+		let work_dir = make_unpack_work_dir(&info, Some(build_dir.path()), Verbosity::Normal)?;
+		assert_eq!(work_dir, build_dir.path().join("xenomorph-0.1.0"));
+		assert!(work_dir.is_dir());
+
+		// Another package with the same name and version gets its own dir.
+		let work_dir = make_unpack_work_dir(&info, Some(build_dir.path()), Verbosity::Normal)?;
+		assert_eq!(work_dir, build_dir.path().join("xenomorph-0.1.0.2"));
+		assert!(work_dir.is_dir());
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_sniff() -> eyre::Result<()> {
+		let dir = tempfile::tempdir()?;
+		let cases: &[(&[u8], _)] = &[
+			(b"!<arch>\ndebian-binary", Some(Format::Deb)),
+			(&[0xed, 0xab, 0xee, 0xdb, 3, 0], Some(Format::Rpm)),
+			(&[0x1f, 0x8b, 8, 0], Some(Format::Tgz)),
+			(&[0xfd, b'7', b'z', b'X', b'Z', 0], Some(Format::Tgz)),
+			(b"BZh91AY&SY", Some(Format::Tgz)),
+			(b"#!/bin/sh\n", None),
+			(b"", None),
+		];
+
+		for (i, (content, format)) in cases.iter().enumerate() {
+			let file = dir.path().join(format!("package{i}"));
+			std::fs::write(&file, content)?;
+			assert_eq!(sniff(&file)?, *format, "case {i}");
+		}
+		Ok(())
+	}
+
+	#[test]
+	#[cfg(feature = "serde")]
+	fn test_read_config() -> eyre::Result<()> {
+		use bpaf::Parser;
+
+		use crate::deb::DebCompression;
+
+		let dir = tempfile::tempdir()?;
+		let config = dir.path().join("config.toml");
+		std::fs::write(
+			&config,
+			r#"formats = ["rpm", "tgz"]
+maintainer = "Leah <hi@pluie.me>"
+scripts = true
+compression = "xz"
+output-dir = "/srv/packages"
+"#,
+		)?;
+
+		let config = config.to_string_lossy();
+		let cli = ["--config", &config, "--output-dir", "out", "hello.deb"];
+		let mut args = super::args().to_options().run_inner(&cli[..]).unwrap();
+		args.read_config()?;
+
+		assert_eq!(args.formats, Format::Rpm | Format::Tgz);
+		assert_eq!(args.maintainer.as_deref(), Some("Leah <hi@pluie.me>"));
+		assert!(args.scripts);
+		assert!(!args.deb_args.fixperms);
+		assert_eq!(args.deb_args.compression, Some(DebCompression::Xz));
+		// Given on the command line, so the config file doesn't get a say.
+		assert_eq!(args.output_dir, Path::new("out"));
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_from() {
+		use bpaf::Parser;
+
+		let parse = |from: &str| {
+			let cli = ["--from", from, "hello.pkg"];
+			super::args()
+				.to_options()
+				.run_inner(&cli[..])
+				.map(|args| args.from)
+		};
+		assert_eq!(parse("rpm").ok(), Some(Some(Format::Rpm)));
+		assert_eq!(parse("redhat").ok(), Some(Some(Format::Rpm)));
+		assert!(parse("msi").is_err());
 	}
 }